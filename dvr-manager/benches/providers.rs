@@ -0,0 +1,49 @@
+//! Filtering the providers response for library directories, which happens
+//! whenever the backend connects or `doctor` runs
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dvr_manager::plex::{ProviderDirectoryType, ProvidersMediaProvider, ProvidersMediaProviders};
+use serde_json::json;
+
+/// A server with many libraries, alternating between shows and films
+fn providers(libraries: usize) -> Vec<ProvidersMediaProvider> {
+    let directories: Vec<_> = (0..libraries)
+        .map(|i| {
+            json!({
+                "type": if i % 2 == 0 { "show" } else { "movie" },
+                "id": i.to_string(),
+                "title": format!("Library {}", i),
+            })
+        })
+        .collect();
+    serde_json::from_value(json!([
+        {
+            "identifier": "tv.plex.providers.epg.xmltv:2",
+            "title": "Guide",
+            "Feature": [],
+        },
+        {
+            "identifier": "com.plexapp.plugins.library",
+            "title": "Library",
+            "Feature": [{ "type": "content", "Directory": directories }],
+        },
+    ]))
+    .unwrap()
+}
+
+fn get_dirs_of_type(c: &mut Criterion) {
+    for libraries in [10, 1000] {
+        let providers = providers(libraries);
+        c.bench_function(&format!("get_dirs_of_type/{}", libraries), |b| {
+            b.iter(|| {
+                black_box(&providers)
+                    .get_dirs_of_type(ProviderDirectoryType::Show)
+                    .unwrap()
+                    .len()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, get_dirs_of_type);
+criterion_main!(benches);
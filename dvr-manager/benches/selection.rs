@@ -0,0 +1,98 @@
+//! Deciding which airings to record, over guides far larger than any lineup,
+//! so a rule that gets slower with the guide's size shows up here
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use dvr_manager::backend::FixtureBackend;
+use dvr_manager::manager::{Manager, ManagerConfig};
+use dvr_manager::notify::Notifiers;
+use dvr_manager::plex::{self, GridMetadata};
+use dvr_manager::state::State;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tokio::sync::Notify;
+
+const CHANNELS: usize = 50;
+const SHOWS: usize = 500;
+const SIZES: [usize; 2] = [10_000, 50_000];
+
+/// Half-hour airings spread over the channels, a tenth already subscribed to
+fn grid(airings: usize) -> serde_json::Value {
+    let metadata: Vec<_> = (0..airings)
+        .map(|i| {
+            let begins_at = 1_791_763_200 + (i / CHANNELS) as i64 * 1800;
+            let mut airing = json!({
+                "ratingKey": i.to_string(),
+                "guid": format!("plex://episode/{:024x}", i),
+                "title": format!("Episode {}", i),
+                "type": "episode",
+                "duration": 1_800_000,
+                "grandparentTitle": format!("Show {}", i % SHOWS),
+                "grandparentGuid": format!("plex://show/{:024x}", i % SHOWS),
+                "Media": [{
+                    "id": i,
+                    "beginsAt": begins_at,
+                    "endsAt": begins_at + 1800,
+                    "channelIdentifier": format!("{:03}", i % CHANNELS),
+                    "channelTitle": format!("Channel {}", i % CHANNELS),
+                }],
+            });
+            if i % 10 == 0 {
+                airing["grandparentSubscriptionID"] = "1".into();
+            }
+            airing
+        })
+        .collect();
+    json!({ "MediaContainer": { "size": airings, "Metadata": metadata } })
+}
+
+/// Records a fifth of the shows on half the channels
+fn manager() -> Manager {
+    let corpus = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/responses/1.41.3"
+    );
+    let config = ManagerConfig {
+        channels: (0..CHANNELS / 2).map(|c| format!("{:03}", c)).collect(),
+        titles: (0..SHOWS / 5).map(|s| format!("Show {}", s * 5)).collect(),
+        ..ManagerConfig::default()
+    };
+    Manager::new(
+        Arc::new(FixtureBackend::open(Path::new(corpus)).unwrap()),
+        Arc::new(Notify::new()),
+        Arc::new(State::open(":memory:").unwrap()),
+        Arc::new(Notifiers::default()),
+        config,
+    )
+    .unwrap()
+}
+
+fn plan(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let manager = manager();
+    let mut group = c.benchmark_group("plan");
+    for size in SIZES {
+        let shows: Vec<GridMetadata> = plex::parse_grid(&grid(size).to_string()).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &shows, |b, shows| {
+            b.iter(|| runtime.block_on(manager.plan(black_box(shows))).len())
+        });
+    }
+    group.finish();
+}
+
+fn parse_grid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_grid");
+    // Each iteration decodes megabytes of JSON
+    group.sample_size(20);
+    for size in SIZES {
+        let json = grid(size).to_string();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &json, |b, json| {
+            b.iter(|| plex::parse_grid(black_box(json)).unwrap().len())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, plan, parse_grid);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Grids come from the EPG provider by way of Plex, and are parsed every pass
+
+#![no_main]
+
+use dvr_manager::plex;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|json: &str| {
+    if let Ok(airings) = plex::parse_grid(json) {
+        // Accessors the manager calls on every airing
+        for airing in airings {
+            airing.begins_at_ts();
+            airing.begins_at();
+            airing.show_title();
+            airing.year();
+        }
+    }
+});
@@ -0,0 +1,11 @@
+//! `Preferences.xml` is read at startup, so a surprise in it stops the manager
+//! before it's scheduled anything
+
+#![no_main]
+
+use dvr_manager::plex;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|xml: &str| {
+    let _ = plex::parse_preferences(xml);
+});
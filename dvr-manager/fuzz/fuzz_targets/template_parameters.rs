@@ -0,0 +1,11 @@
+//! Template parameters are a query string wrapped in another layer of URL
+//! encoding, decoded before every subscription
+
+#![no_main]
+
+use dvr_manager::plex;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|parameters: &str| {
+    let _ = plex::parse_template_parameters(parameters);
+});
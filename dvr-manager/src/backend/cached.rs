@@ -0,0 +1,141 @@
+use super::{DvrBackend, Guides, Result};
+use crate::plex::{Channel, GridMetadata, LibraryMetadata, MediaSubscription};
+use crate::state::State;
+use async_trait::async_trait;
+use chrono::Duration;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Keeps guides fetched from another backend in the state database, so a restart
+/// doesn't refetch every channel's multi-day guide
+pub struct CachedBackend {
+    inner: Arc<dyn DvrBackend>,
+    state: Arc<State>,
+    /// Seconds a cached guide is used for before it's fetched again
+    ttl: i64,
+}
+
+impl CachedBackend {
+    pub fn new(inner: Arc<dyn DvrBackend>, state: Arc<State>, ttl: i64) -> Self {
+        CachedBackend { inner, state, ttl }
+    }
+
+    fn cached(&self, channel: &str, date: &str) -> Option<Vec<GridMetadata>> {
+        let (airings, fetched_at) = match self.state.cached_grid(channel, date) {
+            Ok(grid) => grid?,
+            Err(e) => {
+                log::warn!("Couldn't read cached guide: {}", e);
+                return None;
+            }
+        };
+        if self.state.now().timestamp() - fetched_at >= self.ttl {
+            return None;
+        }
+        serde_json::from_str(&airings)
+            .map_err(|e| log::warn!("Ignoring unreadable cached guide: {}", e))
+            .ok()
+    }
+
+    fn store(&self, channel: &str, date: &str, airings: &[GridMetadata]) {
+        let stored = serde_json::to_string(airings)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                self.state
+                    .set_cached_grid(channel, date, &json)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = stored {
+            log::warn!("Couldn't cache guide: {}", e);
+        }
+    }
+
+    fn invalidate(&self) {
+        if let Err(e) = self.state.clear_grid_cache() {
+            log::warn!("Couldn't clear cached guides: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl DvrBackend for CachedBackend {
+    async fn channels(&self) -> Result<Vec<Channel>> {
+        self.inner.channels().await
+    }
+
+    async fn guide(&self, channel: &Channel, date: &str) -> Result<Vec<GridMetadata>> {
+        if let Some(airings) = self.cached(&channel.id, date) {
+            log::debug!("Using cached guide for {} on {}", channel.id, date);
+            return Ok(airings);
+        }
+        let airings = self.inner.guide(channel, date).await?;
+        self.store(&channel.id, date, &airings);
+        Ok(airings)
+    }
+
+    async fn guides(&self, channels: &[Channel], date: &str) -> Guides {
+        let mut guides = HashMap::new();
+        let mut stale = Vec::new();
+        for channel in channels {
+            match self.cached(&channel.id, date) {
+                Some(airings) => {
+                    guides.insert(channel.id.clone(), Ok(airings));
+                }
+                None => stale.push(channel.clone()),
+            }
+        }
+        if !stale.is_empty() {
+            for (channel, airings) in self.inner.guides(&stale, date).await {
+                if let Ok(airings) = &airings {
+                    self.store(&channel, date, airings);
+                }
+                guides.insert(channel, airings);
+            }
+        }
+        guides
+    }
+
+    async fn clock_skew(&self) -> Result<Option<Duration>> {
+        self.inner.clock_skew().await
+    }
+
+    async fn enable_channels(&self, channels: &[String]) -> Result<Vec<String>> {
+        self.inner.enable_channels(channels).await
+    }
+
+    async fn subscribe(&self, airing: &GridMetadata) -> Result<()> {
+        let result = self.inner.subscribe(airing).await;
+        self.invalidate();
+        result
+    }
+
+    async fn subscriptions(&self) -> Result<Vec<MediaSubscription>> {
+        self.inner.subscriptions().await
+    }
+
+    async fn cancel_subscription(&self, id: &str) -> Result<()> {
+        let result = self.inner.cancel_subscription(id).await;
+        self.invalidate();
+        result
+    }
+
+    async fn recording(&self, id: &str) -> Result<Option<LibraryMetadata>> {
+        self.inner.recording(id).await
+    }
+
+    async fn delete_recording(&self, id: &str) -> Result<()> {
+        self.inner.delete_recording(id).await
+    }
+
+    async fn tag(
+        &self,
+        recording: &LibraryMetadata,
+        labels: &[String],
+        collections: &[String],
+    ) -> Result<()> {
+        self.inner.tag(recording, labels, collections).await
+    }
+
+    async fn rescan(&self, recording: &LibraryMetadata, dir: &str) -> Result<()> {
+        self.inner.rescan(recording, dir).await
+    }
+}
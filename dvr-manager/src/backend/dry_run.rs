@@ -0,0 +1,90 @@
+use super::{DvrBackend, Guides, Result};
+use crate::plex::{Channel, GridMetadata, LibraryMetadata, MediaSubscription};
+use async_trait::async_trait;
+use chrono::Duration;
+
+/// Reads from another backend but only logs the changes it would have made
+pub struct DryRunBackend<B> {
+    inner: B,
+}
+
+impl<B> DryRunBackend<B> {
+    pub fn new(inner: B) -> Self {
+        DryRunBackend { inner }
+    }
+}
+
+#[async_trait]
+impl<B: DvrBackend> DvrBackend for DryRunBackend<B> {
+    async fn channels(&self) -> Result<Vec<Channel>> {
+        self.inner.channels().await
+    }
+
+    async fn guide(&self, channel: &Channel, date: &str) -> Result<Vec<GridMetadata>> {
+        self.inner.guide(channel, date).await
+    }
+
+    async fn guides(&self, channels: &[Channel], date: &str) -> Guides {
+        self.inner.guides(channels, date).await
+    }
+
+    async fn clock_skew(&self) -> Result<Option<Duration>> {
+        self.inner.clock_skew().await
+    }
+
+    async fn enable_channels(&self, channels: &[String]) -> Result<Vec<String>> {
+        log::info!(
+            "Dry run: would enable any of {:?} that are disabled",
+            channels
+        );
+        Ok(Vec::new())
+    }
+
+    async fn subscribe(&self, airing: &GridMetadata) -> Result<()> {
+        log::info!(
+            "Dry run: would record {} ({}) at {}",
+            airing.show_title(),
+            airing.guid,
+            airing.begins_at_ts()
+        );
+        Ok(())
+    }
+
+    async fn subscriptions(&self) -> Result<Vec<MediaSubscription>> {
+        self.inner.subscriptions().await
+    }
+
+    async fn cancel_subscription(&self, id: &str) -> Result<()> {
+        log::info!("Dry run: would cancel subscription {}", id);
+        Ok(())
+    }
+
+    async fn recording(&self, id: &str) -> Result<Option<LibraryMetadata>> {
+        self.inner.recording(id).await
+    }
+
+    async fn delete_recording(&self, id: &str) -> Result<()> {
+        log::info!("Dry run: would delete recording {}", id);
+        Ok(())
+    }
+
+    async fn tag(
+        &self,
+        recording: &LibraryMetadata,
+        labels: &[String],
+        collections: &[String],
+    ) -> Result<()> {
+        log::info!(
+            "Dry run: would tag {} with labels {:?} and collections {:?}",
+            recording.rating_key,
+            labels,
+            collections
+        );
+        Ok(())
+    }
+
+    async fn rescan(&self, recording: &LibraryMetadata, dir: &str) -> Result<()> {
+        log::info!("Dry run: would rescan {} for {}", dir, recording.rating_key);
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use super::{BackendError, DvrBackend, Result};
+use crate::plex::{self, Channel, GridMetadata, LibraryMetadata, MediaSubscription};
+use async_trait::async_trait;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+fn read_only<T>() -> Result<T> {
+    Err(BackendError::Fixture("fixtures can't be changed".into()))
+}
+
+/// Serves the channels and guide saved by `dump`, for working offline
+pub struct FixtureBackend {
+    grid_dir: PathBuf,
+    channels: Vec<Channel>,
+}
+
+impl FixtureBackend {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join("channels.json");
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| BackendError::Fixture(format!("{}: {}", path.display(), e)))?;
+        Ok(FixtureBackend {
+            grid_dir: dir.join("grid"),
+            channels: plex::parse_channels(&json)?,
+        })
+    }
+
+    /// Dates with a saved grid for a channel, in `plex::GRID_DATE_FORMAT`
+    pub fn dates(&self, channel: &Channel) -> Result<Vec<String>> {
+        let entries = std::fs::read_dir(&self.grid_dir)
+            .map_err(|e| BackendError::Fixture(format!("{}: {}", self.grid_dir.display(), e)))?;
+        let prefix = format!("{}_", channel.id);
+        let mut dates: Vec<String> = entries
+            .filter_map(|e| {
+                let name = e.ok()?.file_name().into_string().ok()?;
+                let date = name.strip_prefix(&prefix)?.strip_suffix(".json")?;
+                Some(date.to_string())
+            })
+            .collect();
+        dates.sort();
+        Ok(dates)
+    }
+}
+
+#[async_trait]
+impl DvrBackend for FixtureBackend {
+    async fn channels(&self) -> Result<Vec<Channel>> {
+        Ok(self.channels.clone())
+    }
+
+    async fn guide(&self, channel: &Channel, date: &str) -> Result<Vec<GridMetadata>> {
+        let path = self.grid_dir.join(format!("{}_{}.json", channel.id, date));
+        match std::fs::read_to_string(&path) {
+            Ok(json) => Ok(plex::parse_grid(&json)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(BackendError::Fixture(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    async fn subscribe(&self, _airing: &GridMetadata) -> Result<()> {
+        read_only()
+    }
+
+    async fn subscriptions(&self) -> Result<Vec<MediaSubscription>> {
+        Ok(Vec::new())
+    }
+
+    async fn cancel_subscription(&self, _id: &str) -> Result<()> {
+        read_only()
+    }
+
+    async fn recording(&self, _id: &str) -> Result<Option<LibraryMetadata>> {
+        Ok(None)
+    }
+
+    async fn delete_recording(&self, _id: &str) -> Result<()> {
+        read_only()
+    }
+
+    async fn tag(
+        &self,
+        _recording: &LibraryMetadata,
+        _labels: &[String],
+        _collections: &[String],
+    ) -> Result<()> {
+        read_only()
+    }
+
+    async fn rescan(&self, _recording: &LibraryMetadata, _dir: &str) -> Result<()> {
+        read_only()
+    }
+}
@@ -0,0 +1,152 @@
+mod cached;
+mod dry_run;
+mod fixtures;
+mod plex;
+
+pub use self::cached::CachedBackend;
+pub use self::dry_run::DryRunBackend;
+pub use self::fixtures::FixtureBackend;
+pub use self::plex::{PlexBackend, UnknownTypePolicy, TEMPLATE_TTL};
+
+use crate::plex::{
+    self as plex_api, Channel, GridMetadata, LibraryMetadata, MediaSubscription, PlexError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Local, Utc};
+use futures::future::join_all;
+use std::collections::HashMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("Plex error: {0}")]
+    Plex(#[from] PlexError),
+
+    #[error("Config error: {0}")]
+    Config(String),
+
+    #[error("Fixture error: {0}")]
+    Fixture(String),
+}
+
+/// Seconds a cached guide is used for, unless configured otherwise
+pub const GRID_CACHE_TTL: i64 = 15 * 60;
+
+pub type Result<T, E = BackendError> = std::result::Result<T, E>;
+
+/// Airings on several channels for one day, keyed by channel id. Channels
+/// whose guide couldn't be fetched have the error instead, so one bad grid
+/// doesn't cost every other channel its guide.
+pub type Guides = HashMap<String, Result<Vec<GridMetadata>>>;
+
+/// A DVR the manager can pick airings from and record with.
+///
+/// Plex's guide and library types double as the shared model until a second
+/// backend needs something they can't express.
+#[async_trait]
+pub trait DvrBackend: Send + Sync {
+    async fn channels(&self) -> Result<Vec<Channel>>;
+
+    /// Airings on a channel for one day, given in `plex::GRID_DATE_FORMAT`
+    async fn guide(&self, channel: &Channel, date: &str) -> Result<Vec<GridMetadata>>;
+
+    /// Airings on several channels for one day. Backends that can fetch
+    /// several channels at once should override this.
+    async fn guides(&self, channels: &[Channel], date: &str) -> Guides {
+        join_all(
+            channels
+                .iter()
+                .map(|c| async move { (c.id.clone(), self.guide(c, date).await) }),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// How far the DVR's clock is ahead of this machine's, negative if it's
+    /// behind, or `None` if the backend can't tell
+    async fn clock_skew(&self) -> Result<Option<Duration>> {
+        Ok(None)
+    }
+
+    /// Enables any of the channels, by lineup identifier, that are disabled in
+    /// the DVR's channel mapping, as lineup rescans can leave them. Returns
+    /// those still not enabled, including any the DVR has no mapping for.
+    /// Backends without a channel mapping have nothing to enable.
+    async fn enable_channels(&self, _channels: &[String]) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Records a single airing
+    async fn subscribe(&self, airing: &GridMetadata) -> Result<()>;
+
+    /// Recording rules, including ones the manager didn't make
+    async fn subscriptions(&self) -> Result<Vec<MediaSubscription>>;
+
+    /// Cancels a subscription, whether or not the manager made it
+    async fn cancel_subscription(&self, id: &str) -> Result<()>;
+
+    /// A finished recording, or `None` if it's no longer in the library
+    async fn recording(&self, id: &str) -> Result<Option<LibraryMetadata>>;
+
+    /// Deletes a recording and its files
+    async fn delete_recording(&self, id: &str) -> Result<()>;
+
+    /// Adds labels and collections to a recording, or to its show for episodes
+    async fn tag(
+        &self,
+        recording: &LibraryMetadata,
+        labels: &[String],
+        collections: &[String],
+    ) -> Result<()>;
+
+    /// Picks up changes to files in a recording's folder
+    async fn rescan(&self, recording: &LibraryMetadata, dir: &str) -> Result<()>;
+}
+
+/// The soonest airing today or tomorrow that hasn't finished by `now` and matches,
+/// searching every channel unless one is given by id or identifier
+pub async fn find_airing(
+    backend: &dyn DvrBackend,
+    channel: Option<&str>,
+    now: DateTime<Utc>,
+    matches: impl Fn(&GridMetadata) -> bool,
+) -> Result<Option<GridMetadata>> {
+    let today = now.with_timezone(&Local).date_naive();
+    let dates = [today, today + Duration::days(1)]
+        .map(|d| d.format(plex_api::GRID_DATE_FORMAT).to_string());
+
+    let channels: Vec<Channel> = backend
+        .channels()
+        .await?
+        .into_iter()
+        .filter(|c| {
+            channel.is_none_or(|wanted| c.id == wanted || c.identifier.as_deref() == Some(wanted))
+        })
+        .collect();
+
+    let mut found: Option<GridMetadata> = None;
+    for date in &dates {
+        for (channel, airings) in backend.guides(&channels, date).await {
+            let airings = match airings {
+                Ok(airings) => airings,
+                Err(e) => {
+                    log::warn!("Couldn't fetch the guide for channel {}: {}", channel, e);
+                    continue;
+                }
+            };
+            for airing in airings {
+                let ended = airing
+                    .media
+                    .first()
+                    .is_none_or(|m| m.ends_at < now.timestamp());
+                let sooner = found
+                    .as_ref()
+                    .is_none_or(|f| airing.begins_at_ts() < f.begins_at_ts());
+                if !ended && sooner && matches(&airing) {
+                    found = Some(airing);
+                }
+            }
+        }
+    }
+    Ok(found)
+}
@@ -0,0 +1,473 @@
+use super::{BackendError, DvrBackend, Guides, Result};
+use crate::plex::{
+    Channel, ChannelMapping, GridMetadata, LibraryMetadata, MediaSubscription, Plex, PlexError,
+    ProviderDirectoryType, ProvidersMediaProviders, Subscription, SubscriptionType, Tag,
+    TemplateParameters, TemplateSubscription,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::join_all;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+/// Plex's numeric metadata types, used when editing items
+const TYPE_MOVIE: u8 = 1;
+const TYPE_SHOW: u8 = 2;
+/// Channels asked for in one grid request, keeping URLs a sensible length
+const GRID_BATCH_SIZE: usize = 20;
+/// Seconds a subscription template is reused for airings of the same programme,
+/// unless configured otherwise
+pub const TEMPLATE_TTL: u64 = 10 * 60;
+/// How long library ids are trusted before checking Plex still has them, as a
+/// library that's deleted and recreated comes back with a new id
+const LIBRARY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// What to do with an airing whose subscription template is neither for a
+/// film nor a TV show
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownTypePolicy {
+    /// Don't subscribe, and alert about it
+    #[default]
+    Reject,
+    /// Record into the TV library
+    Tv,
+    /// Record into the film library
+    Film,
+}
+
+type Templates = Vec<TemplateSubscription<TemplateParameters>>;
+/// Filled by whichever subscription asks for the template first
+type SharedTemplates = Arc<OnceCell<Arc<Templates>>>;
+
+/// How a grid request for several channels at once went
+enum Batch {
+    /// Airings, by channel id
+    Fetched(HashMap<String, Vec<GridMetadata>>),
+    /// The request failed, so nothing is known about batching
+    Failed,
+    /// Airings couldn't be attributed to the channels asked for, or a channel
+    /// left empty had airings when asked for alone, which is how providers
+    /// that only support one key at a time respond
+    Unsupported,
+}
+
+fn section_id(metadata: &LibraryMetadata) -> Option<String> {
+    match metadata.library_section_id.as_ref()? {
+        serde_json::Value::String(id) => Some(id.clone()),
+        id => Some(id.to_string()),
+    }
+}
+
+fn unknown_plex_error(err: &str) -> BackendError {
+    BackendError::Plex(PlexError::PlexResponse(err.to_string()))
+}
+
+/// Ids of the libraries recordings go into
+#[derive(Clone, Debug)]
+struct Libraries {
+    tv: String,
+    film: String,
+}
+
+/// The TV and film libraries to record into, the ones asked for if given,
+/// otherwise the first of each type
+async fn find_libraries(
+    plex: &Plex,
+    tv_library_id: Option<&str>,
+    film_library_id: Option<&str>,
+) -> Result<Libraries> {
+    let providers = plex.get_providers().await?;
+
+    let get_library_id = |library_type, wanted: Option<&str>, name: &str| {
+        let dirs = providers.get_dirs_of_type(library_type)?;
+        let mut ids = dirs.iter().filter_map(|d| d.id.as_deref());
+        let id = match wanted {
+            Some(wanted) => ids.find(|id| *id == wanted),
+            None => ids.next(),
+        };
+        id.map(String::from).ok_or_else(|| {
+            BackendError::Config(match wanted {
+                Some(wanted) => format!("{} library {} isn't in Plex", name, wanted),
+                None => format!("No {} library found", name),
+            })
+        })
+    };
+
+    Ok(Libraries {
+        tv: get_library_id(ProviderDirectoryType::Show, tv_library_id, "TV Show")?,
+        film: get_library_id(ProviderDirectoryType::Movie, film_library_id, "Film")?,
+    })
+}
+
+/// Records with Plex DVR, into the configured TV and film libraries
+pub struct PlexBackend {
+    plex: Plex,
+    /// Libraries asked for, otherwise the first of each type is used
+    tv_library_id: Option<String>,
+    film_library_id: Option<String>,
+    /// Libraries in use and when they were last looked up, forgotten when Plex
+    /// refuses a subscription in case it was for a library that's gone
+    libraries: Mutex<(Libraries, Option<Instant>)>,
+    /// Cleared once the EPG provider turns out not to handle several grid keys
+    batch_grids: AtomicBool,
+    /// Templates by guid, fetched once however many airings are subscribed at a time
+    templates: Mutex<HashMap<String, (Instant, SharedTemplates)>>,
+    template_ttl: Duration,
+    unknown_types: UnknownTypePolicy,
+}
+
+impl PlexBackend {
+    /// Connects to Plex, using the first TV and film libraries unless others are given.
+    /// Subscription templates are reused for `template_ttl`.
+    pub async fn new(
+        plex: Plex,
+        tv_library_id: Option<String>,
+        film_library_id: Option<String>,
+        template_ttl: Duration,
+    ) -> Result<Self> {
+        let libraries =
+            find_libraries(&plex, tv_library_id.as_deref(), film_library_id.as_deref()).await?;
+        log::debug!(
+            "Using tv library {}, film library {}",
+            libraries.tv,
+            libraries.film
+        );
+
+        Ok(PlexBackend {
+            plex,
+            tv_library_id,
+            film_library_id,
+            libraries: Mutex::new((libraries, Some(Instant::now()))),
+            batch_grids: AtomicBool::new(true),
+            templates: Mutex::default(),
+            template_ttl,
+            unknown_types: UnknownTypePolicy::default(),
+        })
+    }
+
+    /// Routes templates of types other than film and TV show by `policy`
+    /// instead of rejecting them
+    pub fn with_unknown_types(mut self, policy: UnknownTypePolicy) -> Self {
+        self.unknown_types = policy;
+        self
+    }
+
+    /// The libraries to record into, looked up again once they've been
+    /// trusted for a while so recordings follow a recreated library
+    async fn libraries(&self) -> Result<Libraries> {
+        let (previous, checked) = self.libraries.lock().unwrap().clone();
+        if checked.is_some_and(|at| at.elapsed() < LIBRARY_TTL) {
+            return Ok(previous);
+        }
+
+        let libraries = find_libraries(
+            &self.plex,
+            self.tv_library_id.as_deref(),
+            self.film_library_id.as_deref(),
+        )
+        .await
+        .inspect_err(|e| log::error!("Can't find a library to record into: {}", e))?;
+        if previous.tv != libraries.tv {
+            log::warn!("TV library is now {}, was {}", libraries.tv, previous.tv);
+        }
+        if previous.film != libraries.film {
+            log::warn!(
+                "Film library is now {}, was {}",
+                libraries.film,
+                previous.film
+            );
+        }
+        *self.libraries.lock().unwrap() = (libraries.clone(), Some(Instant::now()));
+        Ok(libraries)
+    }
+
+    /// The subscription template for a programme, shared between concurrent and
+    /// repeated subscriptions to it
+    async fn templates(&self, guid: &str) -> Result<Arc<Templates>> {
+        let cell = {
+            let mut templates = self.templates.lock().unwrap();
+            templates.retain(|_, (fetched, _)| fetched.elapsed() < self.template_ttl);
+            templates
+                .entry(guid.to_string())
+                .or_insert_with(|| (Instant::now(), Arc::default()))
+                .1
+                .clone()
+        };
+        if cell.initialized() {
+            log::debug!("Reusing subscription template for {}", guid);
+        }
+        let templates = cell
+            .get_or_try_init(|| async {
+                Ok::<_, PlexError>(Arc::new(self.plex.get_subscription_template(guid).await?))
+            })
+            .await?;
+        Ok(templates.clone())
+    }
+
+    /// Fetches several channels' grids in one request
+    async fn batched_guide(&self, channels: &[Channel], date: &str) -> Batch {
+        let keys = channels.iter().map(|c| c.id.as_str()).join(",");
+        let airings = match self.plex.get_grid(&keys, date).await {
+            Ok(airings) => airings.unwrap_or_default(),
+            Err(e) => {
+                log::debug!("Batched grid request failed: {}", e);
+                return Batch::Failed;
+            }
+        };
+
+        let mut guides: HashMap<_, _> = channels
+            .iter()
+            .map(|c| (c.id.clone(), Vec::new()))
+            .collect();
+        for airing in airings {
+            let identifier = airing.media.first().map(|m| &m.channel_identifier);
+            let Some(guide) = channels
+                .iter()
+                .find(|c| c.identifier.as_ref() == identifier)
+                .and_then(|c| guides.get_mut(&c.id))
+            else {
+                return Batch::Unsupported;
+            };
+            guide.push(airing);
+        }
+        // Providers that ignore all but one key leave the rest empty, so one
+        // that's empty is asked for on its own to tell it from a channel
+        // that's off air or a day that isn't in the guide yet
+        let Some(empty) = channels.iter().find(|c| guides[&c.id].is_empty()) else {
+            return Batch::Fetched(guides);
+        };
+        match self.plex.get_grid(&empty.id, date).await {
+            Ok(airings) if airings.as_ref().is_none_or(Vec::is_empty) => Batch::Fetched(guides),
+            Ok(_) => Batch::Unsupported,
+            Err(e) => {
+                log::debug!("Grid request for {} failed: {}", empty.id, e);
+                Batch::Failed
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DvrBackend for PlexBackend {
+    async fn channels(&self) -> Result<Vec<Channel>> {
+        Ok(self.plex.get_channels().await?)
+    }
+
+    async fn guide(&self, channel: &Channel, date: &str) -> Result<Vec<GridMetadata>> {
+        Ok(self
+            .plex
+            .get_grid(&channel.id, date)
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn guides(&self, channels: &[Channel], date: &str) -> Guides {
+        let mut guides = Guides::new();
+        for chunk in channels.chunks(GRID_BATCH_SIZE) {
+            if chunk.len() > 1 && self.batch_grids.load(Ordering::Relaxed) {
+                match self.batched_guide(chunk, date).await {
+                    Batch::Fetched(batch) => {
+                        guides.extend(batch.into_iter().map(|(id, airings)| (id, Ok(airings))));
+                        continue;
+                    }
+                    // Likely a blip, so only these channels are fetched one at a time
+                    Batch::Failed => (),
+                    Batch::Unsupported => {
+                        log::info!("Guide can't be fetched for several channels at once, fetching one at a time");
+                        self.batch_grids.store(false, Ordering::Relaxed);
+                    }
+                }
+            }
+            let fetched = join_all(
+                chunk
+                    .iter()
+                    .map(|c| async move { (c.id.clone(), self.guide(c, date).await) }),
+            )
+            .await;
+            guides.extend(fetched);
+        }
+        guides
+    }
+
+    async fn clock_skew(&self) -> Result<Option<chrono::Duration>> {
+        let asked_at = Utc::now();
+        let Some(server_time) = self.plex.server_time().await? else {
+            return Ok(None);
+        };
+        // Taken as answered halfway through the request. The header's only to
+        // the second, so less than that can't be told from no skew at all.
+        let local_time = asked_at + (Utc::now() - asked_at) / 2;
+        let skew = server_time - local_time;
+        Ok(Some(if skew.num_seconds().abs() < 2 {
+            chrono::Duration::zero()
+        } else {
+            skew
+        }))
+    }
+
+    async fn enable_channels(&self, channels: &[String]) -> Result<Vec<String>> {
+        let mut dvrs = self.plex.get_dvrs().await?;
+        let wanted = |m: &ChannelMapping| channels.iter().any(|c| m.is_for(c));
+        let mut changed = false;
+        for device in dvrs.iter().flat_map(|d| &d.devices) {
+            let disabled: Vec<_> = device
+                .channel_mappings
+                .iter()
+                .filter(|m| !m.is_enabled() && wanted(m))
+                .collect();
+            if disabled.is_empty() {
+                continue;
+            }
+            log::info!(
+                "Enabling channels {} on tuner device {}",
+                disabled
+                    .iter()
+                    .filter_map(|m| m.lineup_identifier.as_deref())
+                    .join(", "),
+                device.key
+            );
+            let enabled: Vec<_> = device
+                .channel_mappings
+                .iter()
+                .filter(|m| m.is_enabled() || wanted(m))
+                .map(|m| m.device_identifier.as_str())
+                .collect();
+            self.plex.set_channel_map(device, &enabled).await?;
+            changed = true;
+        }
+        // Plex may not have taken every change, so see what it has now
+        if changed {
+            dvrs = self.plex.get_dvrs().await?;
+        }
+        let mappings: Vec<_> = dvrs
+            .iter()
+            .flat_map(|d| &d.devices)
+            .flat_map(|d| &d.channel_mappings)
+            .collect();
+        Ok(channels
+            .iter()
+            .filter(|c| !mappings.iter().any(|m| m.is_enabled() && m.is_for(c)))
+            .cloned()
+            .collect())
+    }
+
+    async fn subscribe(&self, metadata: &GridMetadata) -> Result<()> {
+        let templates = self.templates(&metadata.guid).await?;
+
+        log::debug!(
+            "Subscription template for {}: {:#?}",
+            metadata.guid,
+            templates
+        );
+
+        let media = metadata
+            .media
+            .first()
+            .ok_or_else(|| unknown_plex_error("Recording has no Media"))?;
+
+        let media_template = templates
+            .first()
+            .ok_or_else(|| unknown_plex_error("Subscription template has no media"))?;
+        let libraries = self.libraries().await?;
+        let target_library = match (media_template.r#type, self.unknown_types) {
+            (SubscriptionType::Movie, _)
+            | (SubscriptionType::Other(_), UnknownTypePolicy::Film) => &libraries.film,
+            (SubscriptionType::Show, _) | (SubscriptionType::Other(_), UnknownTypePolicy::Tv) => {
+                &libraries.tv
+            }
+            (SubscriptionType::Other(other), UnknownTypePolicy::Reject) => {
+                return Err(BackendError::Config(format!(
+                    "Subscription template for {} is of type {}, neither a film nor a TV show",
+                    metadata.guid, other
+                )));
+            }
+        };
+        let sub = Subscription::one_shot(media_template, media, target_library)?;
+
+        let result = self.plex.create_subscription(&sub).await;
+        if let Err(PlexError::PlexResponse(_)) = result {
+            // Refused, maybe for a library that's gone, so look them up again
+            // before the retry
+            self.libraries.lock().unwrap().1 = None;
+        }
+        Ok(result?)
+    }
+
+    async fn subscriptions(&self) -> Result<Vec<MediaSubscription>> {
+        Ok(self.plex.get_subscriptions().await?)
+    }
+
+    async fn cancel_subscription(&self, id: &str) -> Result<()> {
+        Ok(self.plex.delete_subscription(id).await?)
+    }
+
+    async fn recording(&self, id: &str) -> Result<Option<LibraryMetadata>> {
+        Ok(self.plex.get_metadata(id).await?)
+    }
+
+    async fn delete_recording(&self, id: &str) -> Result<()> {
+        Ok(self.plex.delete_metadata(id).await?)
+    }
+
+    async fn tag(
+        &self,
+        recording: &LibraryMetadata,
+        labels: &[String],
+        collections: &[String],
+    ) -> Result<()> {
+        // Episodes can't be collected, so their show is tagged instead
+        let show;
+        let (target, item_type) = match (
+            recording.r#type.as_deref(),
+            &recording.grandparent_rating_key,
+        ) {
+            (Some("movie"), _) => (recording, TYPE_MOVIE),
+            (Some("episode"), Some(key)) => match self.plex.get_metadata(key).await? {
+                Some(metadata) => {
+                    show = metadata;
+                    (&show, TYPE_SHOW)
+                }
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        let Some(section) = section_id(target) else {
+            return Ok(());
+        };
+
+        // Setting tags replaces them, so keep the ones already there
+        let merge = |existing: &[Tag], new: &[String]| {
+            existing
+                .iter()
+                .map(|t| t.tag.clone())
+                .chain(new.iter().cloned())
+                .unique()
+                .collect::<Vec<_>>()
+        };
+        let labels = merge(&target.labels, labels);
+        let collections = merge(&target.collections, collections);
+        self.plex
+            .set_tags(
+                &section,
+                item_type,
+                &target.rating_key,
+                &labels,
+                &collections,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn rescan(&self, recording: &LibraryMetadata, dir: &str) -> Result<()> {
+        match section_id(recording) {
+            Some(section) => Ok(self.plex.scan_path(&section, dir).await?),
+            None => Ok(()),
+        }
+    }
+}
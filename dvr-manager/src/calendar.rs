@@ -0,0 +1,79 @@
+use crate::state::CalendarEntry;
+use chrono::{DateTime, TimeZone, Utc};
+use std::fmt::Write;
+
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+/// Lines longer than this many bytes must be folded
+const MAX_LINE: usize = 75;
+
+fn format_time(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0)
+        .single()
+        .map_or_else(String::new, |t| t.format(TIME_FORMAT).to_string())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Appends a content line, folding it so no line exceeds the limit
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// Renders the calendar as an iCalendar feed, stamped as made at `now`
+pub fn ics(entries: &[CalendarEntry], now: DateTime<Utc>) -> String {
+    let mut ics = String::new();
+    let now = format_time(now.timestamp());
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//dvr-manager//EN");
+    push_line(&mut ics, "X-WR-CALNAME:DVR");
+
+    for e in entries {
+        let mut event = String::new();
+        let _ = write!(
+            event,
+            "UID:{}-{}@dvr-manager\n\
+             DTSTAMP:{}\n\
+             DTSTART:{}\n\
+             DTEND:{}\n\
+             SUMMARY:{}\n\
+             LOCATION:{}\n\
+             STATUS:{}",
+            e.channel.replace(|c: char| !c.is_ascii_alphanumeric(), ""),
+            e.begins_at,
+            now,
+            format_time(e.begins_at),
+            format_time(e.ends_at.max(e.begins_at)),
+            escape(&e.title),
+            escape(&e.channel_title),
+            // Candidates may still be skipped, so they're only pencilled in
+            if e.scheduled {
+                "CONFIRMED"
+            } else {
+                "TENTATIVE"
+            },
+        );
+        push_line(&mut ics, "BEGIN:VEVENT");
+        for line in event.lines() {
+            push_line(&mut ics, line);
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
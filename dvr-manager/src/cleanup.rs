@@ -0,0 +1,209 @@
+use crate::backend::{BackendError, DvrBackend};
+use crate::notify::{Event, Notifiers};
+use crate::state::{self, LibraryRecording, State};
+use crate::tautulli::{self, Tautulli};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+
+const DEFAULT_INTERVAL: u64 = 60 * 60;
+const BYTES_PER_GB: u64 = 1_000_000_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CleanupError {
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+
+    #[error(transparent)]
+    Tautulli(#[from] tautulli::TautulliError),
+
+    #[error(transparent)]
+    State(#[from] state::StateError),
+}
+
+pub type Result<T, E = CleanupError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct CleanupConfig {
+    /// Plex users who must all have watched a recording before it's deleted
+    #[serde(default)]
+    pub cleanup_watchers: Vec<String>,
+    /// Seconds between cleanup runs
+    pub cleanup_interval: Option<u64>,
+    /// Most gigabytes the manager's recordings may take up, the oldest being
+    /// deleted once they're over it, watched or not
+    pub size_limit: Option<u64>,
+}
+
+/// Deletes recordings the manager made once they're no longer wanted
+pub struct Cleanup {
+    backend: Arc<dyn DvrBackend>,
+    state: Arc<State>,
+    notifiers: Arc<Notifiers>,
+    /// Set if recordings are deleted once watched
+    tautulli: Option<Tautulli>,
+    watchers: Vec<String>,
+    /// Bytes of recordings to keep within
+    size_limit: Option<i64>,
+    interval: Duration,
+    /// Keeps tracking recordings, since the backend won't really have deleted them
+    dry_run: bool,
+}
+
+impl Cleanup {
+    /// Returns `None` if no cleanup policy is configured
+    pub fn new(
+        config: CleanupConfig,
+        tautulli: Option<Tautulli>,
+        backend: Arc<dyn DvrBackend>,
+        state: Arc<State>,
+        notifiers: Arc<Notifiers>,
+        dry_run: bool,
+    ) -> Option<Self> {
+        let watched = !config.cleanup_watchers.is_empty();
+        let tautulli = match tautulli {
+            Some(tautulli) if watched => Some(tautulli),
+            None if watched => {
+                log::warn!(
+                    "Cleanup watchers are set but Tautulli isn't configured, not cleaning up watched recordings"
+                );
+                None
+            }
+            _ => None,
+        };
+        let size_limit = config
+            .size_limit
+            .map(|gb| gb.saturating_mul(BYTES_PER_GB).min(i64::MAX as u64) as i64);
+        if tautulli.is_none() && size_limit.is_none() {
+            return None;
+        }
+        Some(Cleanup {
+            backend,
+            state,
+            notifiers,
+            watchers: match tautulli {
+                Some(_) => config
+                    .cleanup_watchers
+                    .iter()
+                    .map(|w| w.to_lowercase())
+                    .collect(),
+                None => Vec::new(),
+            },
+            tautulli,
+            size_limit,
+            interval: Duration::from_secs(config.cleanup_interval.unwrap_or(DEFAULT_INTERVAL)),
+            dry_run,
+        })
+    }
+
+    /// Logs what's deleted and how often, so the effective settings are clear
+    pub fn log_policy(&self) {
+        let mut rules = Vec::new();
+        if self.tautulli.is_some() {
+            rules.push(format!("once watched by {}", self.watchers.join(", ")));
+        }
+        if let Some(limit) = self.size_limit {
+            rules.push(format!(
+                "oldest first once over {} GB",
+                limit as u64 / BYTES_PER_GB
+            ));
+        }
+        log::info!(
+            "Deleting recordings {}, checking every {}s{}",
+            rules.join(" and "),
+            self.interval.as_secs(),
+            if self.dry_run { " (dry run)" } else { "" }
+        );
+    }
+
+    async fn emit(&self, event: Event) {
+        if let Err(e) = self.state.record_event(&event) {
+            log::warn!("Couldn't record event: {}", e);
+        }
+        self.notifiers.send(event).await;
+    }
+
+    /// Whether every watcher has seen a recording
+    async fn watched(&self, recording: &LibraryRecording) -> Result<bool> {
+        let Some(tautulli) = &self.tautulli else {
+            return Ok(false);
+        };
+        let watched_by = tautulli.watched_by(&recording.rating_key).await?;
+        Ok(self.watchers.iter().all(|w| watched_by.contains(w)))
+    }
+
+    async fn delete(&self, recording: &LibraryRecording, reason: &str) -> Result<()> {
+        self.backend.delete_recording(&recording.rating_key).await?;
+        log::info!("Deleted {}, {}", recording.title, reason);
+        self.emit(Event::Deleted {
+            title: recording.title.clone(),
+            reason: reason.into(),
+        })
+        .await;
+        if !self.dry_run {
+            self.state.remove_recording(recording.id)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes recordings that every watcher has seen, then the oldest of
+    /// the rest while they're over the size limit
+    pub async fn clean(&self) -> Result<()> {
+        let mut deleted = 0;
+        let mut bytes_freed = 0;
+        let mut kept = Vec::new();
+
+        for recording in self.state.library_recordings()? {
+            let watched = self.watched(&recording).await?;
+            if !watched && self.size_limit.is_none() {
+                continue;
+            }
+
+            let Some(metadata) = self.backend.recording(&recording.rating_key).await? else {
+                // Removed by someone else, so there's nothing left to track
+                log::debug!("{} is no longer in the library", recording.title);
+                if !self.dry_run {
+                    self.state.remove_recording(recording.id)?;
+                }
+                continue;
+            };
+            if watched {
+                self.delete(&recording, "watched by everyone").await?;
+                deleted += 1;
+                bytes_freed += metadata.size();
+            } else {
+                kept.push((recording, metadata.size()));
+            }
+        }
+
+        if let Some(limit) = self.size_limit {
+            // Recordings come oldest first
+            let mut total: i64 = kept.iter().map(|(_, size)| size).sum();
+            for (recording, size) in kept {
+                if total <= limit {
+                    break;
+                }
+                self.delete(&recording, "over the size limit").await?;
+                total -= size;
+                deleted += 1;
+                bytes_freed += size;
+            }
+        }
+
+        if !self.dry_run {
+            self.state.record_cleanup(deleted, bytes_freed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs forever, cleaning up each interval
+pub async fn run(cleanup: Cleanup) {
+    loop {
+        if let Err(e) = cleanup.clean().await {
+            log::warn!("Cleanup failed: {}", e);
+        }
+        sleep(cleanup.interval).await;
+    }
+}
@@ -0,0 +1,138 @@
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// Automatically records upcoming airings on a Plex DVR
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log what would be recorded or deleted without changing anything in Plex
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// How to print results
+    #[arg(long, global = true, value_enum, default_value_t = Output::Table)]
+    pub output: Output,
+
+    /// Shorthand for `--output json`
+    #[arg(long, global = true, hide = true)]
+    pub json: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Aligned columns for reading
+    Table,
+    /// Pretty-printed JSON for scripts
+    Json,
+}
+
+impl Cli {
+    pub fn output(&self) -> Output {
+        if self.json {
+            Output::Json
+        } else {
+            self.output
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the scheduling loop (the default)
+    Run,
+
+    /// Show upcoming recordings, recent errors and cleanup statistics
+    Status,
+
+    /// Check Plex, the DVR and the configured libraries and channels
+    Doctor,
+
+    /// Save providers, channels and guide data for bug reports or simulation
+    Dump {
+        /// Directory to write to
+        dir: PathBuf,
+    },
+
+    /// Preview what the current rules would record, and why the rest won't be
+    Plan {
+        /// How far ahead to look
+        #[arg(long, default_value_t = 24)]
+        hours: u32,
+    },
+
+    /// Replay the recording rules against data saved by `dump`
+    Simulate {
+        /// Directory written by `dump`
+        #[arg(long)]
+        fixtures: PathBuf,
+    },
+
+    /// List the DVR lineup, to find identifiers for the channels setting
+    Channels,
+
+    /// Show a channel's guide for a day
+    Guide {
+        /// Channel id or identifier, as listed by `channels`
+        #[arg(long)]
+        channel: String,
+        /// Day to show, e.g. 2024-01-31, defaulting to today
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+
+    /// Record the next airing of a programme, whatever the manager's rules say
+    Record {
+        /// Guid of the airing, or a show or film title
+        programme: String,
+        /// Channel id or identifier to search, rather than the whole lineup
+        #[arg(long)]
+        channel: Option<String>,
+    },
+
+    /// List or delete DVR subscriptions
+    Subscriptions {
+        #[command(subcommand)]
+        action: Option<SubscriptionsAction>,
+    },
+
+    /// Browse the guide interactively, recording or cancelling airings
+    #[cfg(feature = "tui")]
+    Tui,
+
+    /// Back up or restore the manager's state
+    State {
+        #[command(subcommand)]
+        action: StateAction,
+    },
+
+    /// Authorize access to your Trakt watchlist
+    TraktAuth,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubscriptionsAction {
+    /// List every subscription (the default)
+    List,
+
+    /// Delete a subscription by id
+    Delete { id: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StateAction {
+    /// Write the state as JSON, including service tokens
+    Export {
+        /// File to write to, instead of standard output
+        file: Option<PathBuf>,
+    },
+
+    /// Replace the state with an export, while the manager is stopped
+    Import {
+        /// File written by `state export`
+        file: PathBuf,
+    },
+}
@@ -0,0 +1,28 @@
+use super::connect_plex;
+use crate::cli::Output;
+use crate::config::Config;
+use crate::plex::Channel;
+
+fn print_table(channels: &[Channel]) {
+    println!(
+        "{:<8} {:<24} {:<12} {:<24} TITLE",
+        "NUMBER", "IDENTIFIER", "CALLSIGN", "ID"
+    );
+    for c in channels {
+        println!(
+            "{:<8} {:<24} {:<12} {:<24} {}",
+            c.channel_vcn.as_deref().unwrap_or("-"),
+            c.identifier.as_deref().unwrap_or("-"),
+            c.call_sign.as_deref().unwrap_or("-"),
+            c.id,
+            c.title.as_deref().unwrap_or(""),
+        );
+    }
+}
+
+/// Prints the lineup, since channels are configured by identifier
+pub async fn run(config: &Config, output: Output) -> Result<(), Box<dyn std::error::Error>> {
+    let channels = connect_plex(config)?.get_channels().await?;
+    super::print(output, channels.as_slice(), print_table)?;
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use super::connect_plex;
+use crate::cli::Output;
+use crate::config::Config;
+use crate::plex::{
+    self, Plex, ProviderDirectoryType, ProvidersMediaProvider, ProvidersMediaProviders,
+};
+use reqwest::StatusCode;
+use serde::Serialize;
+
+/// The outcome of one preflight check
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    passed: bool,
+    detail: String,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            passed: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            passed: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+async fn check_server(plex: &Plex) -> Check {
+    const NAME: &str = "Plex server";
+    match plex.get(plex::PROVIDERS_RESOURCE).send().await {
+        Err(e) => Check::fail(NAME, format!("unreachable: {}", e.without_url())),
+        Ok(r) if r.status() == StatusCode::UNAUTHORIZED => {
+            Check::fail(NAME, "token was rejected, try signing in to Plex again")
+        }
+        Ok(r) if !r.status().is_success() => Check::fail(NAME, format!("returned {}", r.status())),
+        Ok(_) => Check::pass(NAME, "reachable and accepted the token"),
+    }
+}
+
+fn check_library(
+    name: &'static str,
+    providers: &[ProvidersMediaProvider],
+    dir_type: ProviderDirectoryType,
+    configured: Option<&String>,
+) -> Check {
+    let ids: Vec<&str> = match providers.get_dirs_of_type(dir_type) {
+        Ok(dirs) => dirs.into_iter().filter_map(|d| d.id.as_deref()).collect(),
+        Err(e) => return Check::fail(name, e.to_string()),
+    };
+    match configured {
+        Some(id) if ids.contains(&id.as_str()) => {
+            Check::pass(name, format!("using library {}", id))
+        }
+        Some(id) => Check::fail(
+            name,
+            format!("library {} not found, have [{}]", id, ids.join(", ")),
+        ),
+        None => match ids.first() {
+            Some(id) => Check::pass(name, format!("using library {}", id)),
+            None => Check::fail(name, "no library of this type"),
+        },
+    }
+}
+
+async fn run_checks(config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let plex = match connect_plex(config) {
+        Ok(plex) => {
+            checks.push(Check::pass("Plex token", "read from Plex preferences"));
+            plex
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "Plex token",
+                format!("couldn't read Plex preferences: {}", e),
+            ));
+            return checks;
+        }
+    };
+
+    let server = check_server(&plex).await;
+    let reachable = server.passed;
+    checks.push(server);
+    if !reachable {
+        return checks;
+    }
+
+    match plex.get_providers().await {
+        Ok(providers) => {
+            checks.push(check_library(
+                "TV library",
+                &providers,
+                ProviderDirectoryType::Show,
+                config.tv_library_id.as_ref(),
+            ));
+            checks.push(check_library(
+                "Film library",
+                &providers,
+                ProviderDirectoryType::Movie,
+                config.film_library_id.as_ref(),
+            ));
+        }
+        Err(e) => checks.push(Check::fail("Libraries", e.to_string())),
+    }
+
+    let channels = match plex.get_channels().await {
+        Ok(channels) if channels.is_empty() => {
+            checks.push(Check::fail("DVR lineup", "the lineup has no channels"));
+            return checks;
+        }
+        Ok(channels) => {
+            checks.push(Check::pass(
+                "DVR lineup",
+                format!("{} channels", channels.len()),
+            ));
+            channels
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "DVR lineup",
+                format!("no DVR set up in Plex? {}", e),
+            ));
+            return checks;
+        }
+    };
+
+    let missing: Vec<&str> = config
+        .channels
+        .iter()
+        .filter(|wanted| {
+            !channels
+                .iter()
+                .any(|c| c.identifier.as_ref() == Some(wanted) || &c.id == *wanted)
+        })
+        .map(String::as_str)
+        .collect();
+    checks.push(if config.channels.is_empty() {
+        Check::pass("Channels", "none configured, recording from all")
+    } else if missing.is_empty() {
+        Check::pass(
+            "Channels",
+            format!("all {} configured channels found", config.channels.len()),
+        )
+    } else {
+        Check::fail(
+            "Channels",
+            format!(
+                "not in the lineup: {}, see `dvr-manager channels`",
+                missing.join(", ")
+            ),
+        )
+    });
+
+    checks
+}
+
+/// Checks everything the manager needs from Plex, reporting each as pass or fail
+pub async fn run(config: &Config, output: Output) -> Result<(), Box<dyn std::error::Error>> {
+    let checks = run_checks(config).await;
+    super::print(output, checks.as_slice(), |checks| {
+        for c in checks {
+            let result = if c.passed { "PASS" } else { "FAIL" };
+            println!("[{}] {:<14} {}", result, c.name, c.detail);
+        }
+    })?;
+
+    let failed = checks.iter().filter(|c| !c.passed).count();
+    if failed > 0 {
+        return Err(format!("{} check(s) failed", failed).into());
+    }
+    Ok(())
+}
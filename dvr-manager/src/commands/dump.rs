@@ -0,0 +1,63 @@
+use super::connect_plex;
+use crate::config::Config;
+use crate::manager::guide_dates;
+use crate::plex::{self, Plex};
+use chrono::{Local, Utc};
+use std::path::Path;
+
+async fn write_json(
+    plex: &Plex,
+    path: &Path,
+    resource: &str,
+    query: &[(&str, &str)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let value = plex.get_raw(resource, query).await?;
+    let json = plex.redact(&serde_json::to_string_pretty(&value)?);
+    std::fs::write(path, json)?;
+    log::info!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Saves the Plex responses the manager works from, with the token removed
+pub async fn run(config: &Config, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let plex = connect_plex(config)?;
+
+    let grid_dir = dir.join("grid");
+    std::fs::create_dir_all(&grid_dir)?;
+
+    write_json(
+        &plex,
+        &dir.join("providers.json"),
+        plex::PROVIDERS_RESOURCE,
+        &[],
+    )
+    .await?;
+    write_json(
+        &plex,
+        &dir.join("channels.json"),
+        plex::CHANNELS_RESOURCE,
+        &[],
+    )
+    .await?;
+
+    let dates = guide_dates(Utc::now(), &Local);
+
+    let channels = plex.get_channels().await?;
+    let wanted = channels.iter().filter(|c| c.selected_by(&config.channels));
+
+    for channel in wanted {
+        for date in &dates {
+            let path = grid_dir.join(format!("{}_{}.json", channel.id, date));
+            write_json(
+                &plex,
+                &path,
+                plex::GRID_RESOURCE,
+                &[("channelGridKey", &channel.id), ("date", date)],
+            )
+            .await?;
+        }
+    }
+
+    println!("Dumped Plex data to {}", dir.display());
+    Ok(())
+}
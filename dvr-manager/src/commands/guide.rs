@@ -0,0 +1,101 @@
+use super::connect_plex;
+use crate::cli::Output;
+use crate::config::Config;
+use crate::plex::{self, GridMetadata, GridMetadataType};
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::Serialize;
+
+/// An airing as printed by `guide`
+#[derive(Debug, Serialize)]
+struct Airing {
+    guid: String,
+    title: String,
+    show: Option<String>,
+    season: Option<u64>,
+    episode: Option<u64>,
+    r#type: &'static str,
+    begins_at: i64,
+    ends_at: i64,
+    subscribed: bool,
+}
+
+impl From<GridMetadata> for Airing {
+    fn from(a: GridMetadata) -> Self {
+        Airing {
+            r#type: match a.r#type {
+                GridMetadataType::Movie => "film",
+                GridMetadataType::Show => "show",
+                GridMetadataType::Other => "other",
+            },
+            begins_at: a.begins_at_ts(),
+            ends_at: a.media.first().map_or(0, |m| m.ends_at),
+            subscribed: a.is_subscribed(),
+            guid: a.guid,
+            title: a.title,
+            show: a.grandparent_title,
+            season: a.parent_index,
+            episode: a.index,
+        }
+    }
+}
+
+fn episode(airing: &Airing) -> String {
+    match (airing.season, airing.episode) {
+        (Some(season), Some(episode)) => format!("S{:02}E{:02}", season, episode),
+        (None, Some(episode)) => format!("E{:02}", episode),
+        _ => "-".into(),
+    }
+}
+
+fn print_table(airings: &[Airing]) {
+    println!(
+        "{:<6} {:<8} {:<6} {:<4} TITLE",
+        "TIME", "EPISODE", "TYPE", "SUB"
+    );
+    for a in airings {
+        let time = Local
+            .timestamp_opt(a.begins_at, 0)
+            .single()
+            .map_or_else(String::new, |t| t.format("%H:%M").to_string());
+        let title = match &a.show {
+            Some(show) => format!("{} - {}", show, a.title),
+            None => a.title.clone(),
+        };
+        println!(
+            "{:<6} {:<8} {:<6} {:<4} {}",
+            time,
+            episode(a),
+            a.r#type,
+            if a.subscribed { "yes" } else { "" },
+            title
+        );
+    }
+}
+
+/// Prints what Plex's grid has on a channel, as the manager sees it
+pub async fn run(
+    config: &Config,
+    channel: &str,
+    date: Option<NaiveDate>,
+    output: Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let plex = connect_plex(config)?;
+    let found = plex
+        .get_channels()
+        .await?
+        .into_iter()
+        .find(|c| c.id == channel || c.identifier.as_deref() == Some(channel))
+        .ok_or_else(|| format!("No channel {} in the lineup", channel))?;
+
+    let date = date.unwrap_or_else(|| Local::now().date_naive());
+    let mut airings: Vec<Airing> = plex
+        .get_grid(&found.id, &date.format(plex::GRID_DATE_FORMAT).to_string())
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .map(Airing::from)
+        .collect();
+    airings.sort_by_key(|a| a.begins_at);
+    super::print(output, airings.as_slice(), print_table)?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+pub mod channels;
+pub mod doctor;
+pub mod dump;
+pub mod guide;
+pub mod plan;
+pub mod record;
+pub mod simulate;
+pub mod state;
+pub mod status;
+pub mod subscriptions;
+pub mod trakt;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+use crate::backend::{self, DryRunBackend, DvrBackend, PlexBackend};
+use crate::cli::Output;
+use crate::config::Config;
+use crate::plex::{self, Plex, PlexHost};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub fn connect_plex(config: &Config) -> plex::Result<Plex> {
+    let host = match &config.plex_url {
+        Some(url) => PlexHost::parse(url)?,
+        None => PlexHost::Localhost,
+    };
+    match &config.plex_token {
+        Some(token) if token.is_empty() => Err(plex::PlexError::EmptyToken),
+        Some(token) => Ok(Plex::with_token(token.clone(), host)),
+        None => Plex::new(config.plex_prefs_path.clone(), host),
+    }
+}
+
+pub async fn connect_backend(config: &Config) -> backend::Result<Arc<dyn DvrBackend>> {
+    let plex = PlexBackend::new(
+        connect_plex(config)?,
+        config.tv_library_id.clone(),
+        config.film_library_id.clone(),
+        Duration::from_secs(config.template_cache_ttl.unwrap_or(backend::TEMPLATE_TTL)),
+    )
+    .await?
+    .with_unknown_types(config.unknown_template_type);
+    if config.dry_run {
+        log::info!("Dry run, nothing will be changed in Plex");
+        Ok(Arc::new(DryRunBackend::new(plex)))
+    } else {
+        Ok(Arc::new(plex))
+    }
+}
+
+/// Prints results as JSON, or as a table for people to read
+pub fn print<T: Serialize + ?Sized>(
+    output: Output,
+    value: &T,
+    table: impl FnOnce(&T),
+) -> serde_json::Result<()> {
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Output::Table => table(value),
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use super::connect_backend;
+use crate::cli::Output;
+use crate::config::Config;
+use crate::decision::SkipReason;
+use crate::manager::{Manager, ManagerConfig};
+use crate::notify::Notifiers;
+use crate::plex::{self, GridMetadata};
+use crate::state::State;
+use chrono::{Duration, Local, TimeZone, Utc};
+use itertools::Itertools;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+fn format_time(ts: i64) -> String {
+    Local.timestamp_opt(ts, 0).single().map_or_else(
+        || ts.to_string(),
+        |t| t.format("%Y-%m-%d %H:%M").to_string(),
+    )
+}
+
+/// What the rules made of one airing
+#[derive(Serialize)]
+pub struct Decision {
+    guid: String,
+    title: String,
+    channel: String,
+    begins_at: i64,
+    /// Why it wouldn't be recorded, if it wouldn't
+    skip_reason: Option<SkipReason>,
+}
+
+impl Decision {
+    pub fn new(show: &GridMetadata, skip_reason: Option<SkipReason>) -> Self {
+        Decision {
+            guid: show.guid.clone(),
+            title: show.show_title(),
+            channel: show
+                .media
+                .first()
+                .map_or_else(String::new, |m| m.channel_title.clone()),
+            begins_at: show.begins_at_ts(),
+            skip_reason,
+        }
+    }
+}
+
+pub fn print_table(decisions: &[Decision]) {
+    println!(
+        "{:<16} {:<24} {:<40} DECISION",
+        "STARTS", "CHANNEL", "TITLE"
+    );
+    for d in decisions {
+        let decision = match d.skip_reason {
+            Some(reason) => format!("skip, {}", reason),
+            None => "record".into(),
+        };
+        println!(
+            "{:<16} {:<24} {:<40} {}",
+            format_time(d.begins_at),
+            d.channel,
+            d.title,
+            decision
+        );
+    }
+
+    let skipped: Vec<_> = decisions.iter().filter_map(|d| d.skip_reason).collect();
+    println!();
+    println!(
+        "Recording {} of {} airings",
+        decisions.len() - skipped.len(),
+        decisions.len()
+    );
+    for (reason, count) in skipped
+        .iter()
+        .counts()
+        .iter()
+        .sorted_by_key(|(_, c)| **c)
+        .rev()
+    {
+        println!("  {:<24} {}", reason.to_string(), count);
+    }
+}
+
+/// Prints what the rules would record over the coming hours, and why the rest won't be.
+/// Everything is checked as it would be at scheduling time, Sonarr and TMDB included.
+pub async fn run(
+    config: Config,
+    hours: u32,
+    output: Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = connect_backend(&config).await?;
+
+    let now = Utc::now();
+    let until = now + Duration::hours(hours.into());
+    let last = until.with_timezone(&Local).date_naive();
+    let dates: Vec<String> = now
+        .with_timezone(&Local)
+        .date_naive()
+        .iter_days()
+        .take_while(|d| *d <= last)
+        .map(|d| d.format(plex::GRID_DATE_FORMAT).to_string())
+        .collect();
+
+    let mut shows = Vec::new();
+    for channel in backend.channels().await? {
+        if !channel.selected_by(&config.channels) {
+            continue;
+        }
+        for date in &dates {
+            shows.extend(backend.guide(&channel, date).await?);
+        }
+    }
+    let shows: Vec<_> = shows
+        .into_iter()
+        .filter(|s| (now.timestamp()..until.timestamp()).contains(&s.begins_at_ts()))
+        .unique_by(|s| (s.guid.clone(), s.begins_at_ts()))
+        .sorted_by_key(|s| s.begins_at_ts())
+        .collect();
+
+    let state = Arc::new(State::open(config.state_path())?);
+    let manager_config = ManagerConfig {
+        channels: config.channels,
+        titles: config.titles,
+        title_aliases: config.title_aliases,
+        premieres_only: config.premieres_only,
+        wishlist: config.wishlist,
+        sonarr: config.sonarr,
+        radarr: config.radarr,
+        trakt: config.trakt,
+        tmdb: config.tmdb,
+        ..Default::default()
+    };
+    let manager = Manager::new(
+        backend,
+        Arc::new(Notify::new()),
+        state,
+        Arc::new(Notifiers::default()),
+        manager_config,
+    )?;
+    let decisions: Vec<Decision> = shows
+        .iter()
+        .zip(manager.preview(&shows).await)
+        .map(|(show, skip_reason)| Decision::new(show, skip_reason))
+        .collect();
+
+    super::print(output, decisions.as_slice(), print_table)?;
+    Ok(())
+}
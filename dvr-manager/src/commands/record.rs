@@ -0,0 +1,48 @@
+use super::connect_backend;
+use crate::backend;
+use crate::cli::Output;
+use crate::config::Config;
+use chrono::{Local, Utc};
+use serde_json::json;
+
+/// Subscribes to the next airing with a matching guid or title
+pub async fn run(
+    config: &Config,
+    programme: &str,
+    channel: Option<&str>,
+    output: Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = connect_backend(config).await?;
+    let airing = backend::find_airing(backend.as_ref(), channel, Utc::now(), |a| {
+        a.guid == programme
+            || a.title.eq_ignore_ascii_case(programme)
+            || a.show_title().eq_ignore_ascii_case(programme)
+    })
+    .await?
+    .ok_or_else(|| format!("No upcoming airing of {} in the guide", programme))?;
+
+    backend.subscribe(&airing).await?;
+
+    let channel = airing
+        .media
+        .first()
+        .map_or("", |m| m.channel_title.as_str());
+    let result = json!({
+        "guid": airing.guid,
+        "title": airing.show_title(),
+        "channel": channel,
+        "begins_at": airing.begins_at_ts(),
+    });
+    super::print(output, &result, |_| {
+        let time = airing.begins_at().map_or_else(String::new, |t| {
+            t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+        });
+        println!(
+            "Recording {} on {} at {}",
+            airing.show_title(),
+            channel,
+            time
+        );
+    })?;
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use super::plan::{print_table, Decision};
+use crate::backend::{DvrBackend, FixtureBackend};
+use crate::cli::Output;
+use crate::config::Config;
+use crate::manager::{Manager, ManagerConfig};
+use crate::notify::Notifiers;
+use crate::state::State;
+use itertools::Itertools;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Prints what would be recorded from a dump, using the configured channels and titles.
+/// External services like Trakt and TMDB aren't consulted.
+pub async fn run(
+    config: Config,
+    dir: &Path,
+    output: Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fixtures = Arc::new(FixtureBackend::open(dir)?);
+
+    let mut shows = Vec::new();
+    for channel in fixtures.channels().await? {
+        if !channel.selected_by(&config.channels) {
+            continue;
+        }
+        for date in fixtures.dates(&channel)? {
+            shows.extend(fixtures.guide(&channel, &date).await?);
+        }
+    }
+    // Grids for neighbouring days overlap around midnight
+    let shows: Vec<_> = shows
+        .into_iter()
+        .unique_by(|s| (s.guid.clone(), s.begins_at_ts()))
+        .sorted_by_key(|s| s.begins_at_ts())
+        .collect();
+
+    let manager_config = ManagerConfig {
+        channels: config.channels,
+        titles: config.titles,
+        title_aliases: config.title_aliases,
+        premieres_only: config.premieres_only,
+        wishlist: config.wishlist,
+        ..Default::default()
+    };
+    let manager = Manager::new(
+        fixtures,
+        Arc::new(Notify::new()),
+        Arc::new(State::open(":memory:")?),
+        Arc::new(Notifiers::default()),
+        manager_config,
+    )?;
+    let decisions: Vec<Decision> = shows
+        .iter()
+        .zip(manager.plan(&shows).await)
+        .map(|(show, skip_reason)| Decision::new(show, skip_reason))
+        .collect();
+
+    super::print(output, decisions.as_slice(), print_table)?;
+    Ok(())
+}
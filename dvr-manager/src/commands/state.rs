@@ -0,0 +1,29 @@
+use crate::cli::StateAction;
+use crate::config::Config;
+use crate::lock::InstanceLock;
+use crate::state::State;
+
+/// Exports or imports the state database as JSON
+pub async fn run(config: &Config, action: StateAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        StateAction::Export { file } => {
+            let dump = State::open(config.state_path())?.export()?;
+            let json = serde_json::to_string_pretty(&dump)?;
+            match file {
+                Some(path) => {
+                    std::fs::write(&path, json)?;
+                    log::info!("Exported state to {}", path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        StateAction::Import { file } => {
+            // A running manager would keep writing over what's imported
+            let _lock = InstanceLock::acquire(&config.lock_path(), false).await?;
+            let dump = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+            State::open(config.state_path())?.import(&dump)?;
+            println!("Imported state from {}", file.display());
+        }
+    }
+    Ok(())
+}
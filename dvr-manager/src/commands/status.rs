@@ -0,0 +1,125 @@
+use crate::cli::Output;
+use crate::config::Config;
+use crate::state::{State, StatusReport};
+use chrono::{Local, TimeZone, Utc};
+
+/// How late a wake-up can be before the daemon is reported as stuck
+const OVERDUE_GRACE: i64 = 5 * 60;
+
+fn format_time(ts: i64) -> String {
+    Local.timestamp_opt(ts, 0).single().map_or_else(
+        || ts.to_string(),
+        |t| t.format("%Y-%m-%d %H:%M").to_string(),
+    )
+}
+
+fn format_duration(secs: i64) -> String {
+    let (days, hours, mins) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else {
+        format!("{}h {}m", hours, mins)
+    }
+}
+
+fn print_table(report: &StatusReport) {
+    let now = Utc::now().timestamp();
+
+    match report.started_at {
+        Some(ts) => println!(
+            "Started:      {} (up {})",
+            format_time(ts),
+            format_duration(now - ts)
+        ),
+        None => println!("Started:      never"),
+    }
+    match report.last_pass {
+        Some(ts) => println!("Last pass:    {}", format_time(ts)),
+        None => println!("Last pass:    never"),
+    }
+    match report.next_wake {
+        Some(ts) if ts + OVERDUE_GRACE < now => println!(
+            "Next wake:    {} (overdue by {}, the manager may be stuck)",
+            format_time(ts),
+            format_duration(now - ts)
+        ),
+        Some(ts) => println!("Next wake:    {}", format_time(ts)),
+        None => println!("Next wake:    unknown"),
+    }
+
+    println!();
+    if report.upcoming.is_empty() {
+        println!("No upcoming recordings");
+    } else {
+        println!("{:<16} {:<24} TITLE", "STARTS", "CHANNEL");
+        for u in &report.upcoming {
+            println!(
+                "{:<16} {:<24} {}",
+                format_time(u.begins_at),
+                u.channel_title,
+                u.title
+            );
+        }
+    }
+
+    if !report.channels.is_empty() {
+        println!();
+        println!(
+            "{:<24} {:>8} {:>10} {:>8} {:>8}",
+            "CHANNEL", "SEEN", "SCHEDULED", "SKIPPED", "FAILED"
+        );
+        for c in &report.channels {
+            println!(
+                "{:<24} {:>8} {:>10} {:>8} {:>8}",
+                c.channel_title, c.seen, c.scheduled, c.skipped, c.failed
+            );
+        }
+    }
+
+    if !report.errors.is_empty() {
+        println!();
+        println!("Recent errors:");
+        for e in &report.errors {
+            println!("  {}  {}", format_time(e.at), e.error);
+        }
+    }
+
+    if !report.failures.is_empty() {
+        println!();
+        println!("Failed recordings:");
+        for f in &report.failures {
+            let outcome = match f.retry_at {
+                Some(ts) => format!("retrying {}", format_time(ts)),
+                None => "gave up".to_string(),
+            };
+            println!(
+                "  {}  {} on {} after {} attempts, {}: {}",
+                format_time(f.begins_at),
+                f.title,
+                f.channel_title,
+                f.attempts,
+                outcome,
+                f.error
+            );
+        }
+    }
+
+    println!();
+    let cleanup = &report.cleanup;
+    match cleanup.last_run {
+        Some(ts) => println!(
+            "Cleanup:      {} runs, last {}, {} deleted, {} bytes freed",
+            cleanup.runs,
+            format_time(ts),
+            cleanup.deleted,
+            cleanup.bytes_freed
+        ),
+        None => println!("Cleanup:      never run"),
+    }
+}
+
+pub fn run(config: &Config, output: Output) -> Result<(), Box<dyn std::error::Error>> {
+    let report = State::open(config.state_path())?.status()?;
+    super::print(output, &report, print_table)?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use super::connect_backend;
+use crate::cli::{Output, SubscriptionsAction};
+use crate::config::Config;
+use crate::plex::MediaSubscription;
+use chrono::{Local, TimeZone};
+use serde_json::json;
+
+fn kind(subscription: &MediaSubscription) -> &'static str {
+    match subscription.r#type {
+        1 => "film",
+        2 => "show",
+        4 => "episode",
+        _ => "other",
+    }
+}
+
+fn print_table(subscriptions: &[MediaSubscription]) {
+    if subscriptions.is_empty() {
+        println!("No subscriptions");
+        return;
+    }
+    println!("{:<8} {:<8} {:<16} TITLE", "ID", "TYPE", "CREATED");
+    for s in subscriptions {
+        let created = s
+            .created_at
+            .and_then(|ts| Local.timestamp_opt(ts, 0).single())
+            .map_or_else(String::new, |t| t.format("%Y-%m-%d %H:%M").to_string());
+        println!(
+            "{:<8} {:<8} {:<16} {}",
+            s.key,
+            kind(s),
+            created,
+            s.title.as_deref().unwrap_or("")
+        );
+    }
+}
+
+/// Lists subscriptions, or deletes one the manager shouldn't have made
+pub async fn run(
+    config: &Config,
+    action: Option<SubscriptionsAction>,
+    output: Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = connect_backend(config).await?;
+    match action.unwrap_or(SubscriptionsAction::List) {
+        SubscriptionsAction::List => {
+            let subscriptions = backend.subscriptions().await?;
+            super::print(output, subscriptions.as_slice(), print_table)?;
+        }
+        SubscriptionsAction::Delete { id } => {
+            backend.cancel_subscription(&id).await?;
+            super::print(output, &json!({ "deleted": id }), |_| {
+                println!("Deleted subscription {}", id)
+            })?;
+        }
+    }
+    Ok(())
+}
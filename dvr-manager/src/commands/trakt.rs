@@ -0,0 +1,12 @@
+use crate::config::Config;
+use crate::state::State;
+use crate::trakt::Trakt;
+use std::sync::Arc;
+
+pub async fn run(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let state = Arc::new(State::open(config.state_path())?);
+    let trakt = Trakt::new(&config.trakt, state)
+        .ok_or("Set DVR_MANAGER_TRAKT_CLIENT_ID and DVR_MANAGER_TRAKT_CLIENT_SECRET first")?;
+    trakt.authorize().await?;
+    Ok(())
+}
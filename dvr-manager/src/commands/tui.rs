@@ -0,0 +1,313 @@
+use super::connect_backend;
+use crate::backend::DvrBackend;
+use crate::config::Config;
+use crate::plex::{self, Channel, GridMetadata};
+use crate::state::{CalendarEntry, State};
+use chrono::{Duration, Local, NaiveDate, TimeZone};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::Arc;
+
+/// How often to check for key presses
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+fn format_time(ts: i64, format: &str) -> String {
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map_or_else(String::new, |t| t.format(format).to_string())
+}
+
+#[derive(PartialEq, Eq)]
+enum Focus {
+    Channels,
+    Guide,
+}
+
+/// A guide browser over the DVR, showing what the manager plans alongside it
+struct App {
+    backend: Arc<dyn DvrBackend>,
+    state: Option<State>,
+    channels: Vec<Channel>,
+    channel_list: ListState,
+    date: NaiveDate,
+    airings: Vec<GridMetadata>,
+    airing_table: TableState,
+    plan: Vec<CalendarEntry>,
+    showing_plan: bool,
+    focus: Focus,
+    message: String,
+}
+
+impl App {
+    fn channel(&self) -> Option<&Channel> {
+        self.channels.get(self.channel_list.selected()?)
+    }
+
+    fn airing(&self) -> Option<&GridMetadata> {
+        self.airings.get(self.airing_table.selected()?)
+    }
+
+    /// The manager's calendar, as of its last pass
+    fn load_plan(&mut self) {
+        self.plan = match &self.state {
+            Some(state) => state.calendar().unwrap_or_else(|e| {
+                self.message = format!("Couldn't read the manager's plan: {}", e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+    }
+
+    async fn load_guide(&mut self) {
+        let Some(channel) = self.channel().cloned() else {
+            return;
+        };
+        let date = self.date.format(plex::GRID_DATE_FORMAT).to_string();
+        match self.backend.guide(&channel, &date).await {
+            Ok(mut airings) => {
+                airings.sort_by_key(|a| a.begins_at_ts());
+                self.airings = airings;
+            }
+            Err(e) => {
+                self.airings.clear();
+                self.message = format!("Couldn't load the guide: {}", e);
+            }
+        }
+        self.airing_table
+            .select((!self.airings.is_empty()).then_some(0));
+    }
+
+    async fn record(&mut self) {
+        let Some(airing) = self.airing().cloned() else {
+            return;
+        };
+        self.message = match self.backend.subscribe(&airing).await {
+            Ok(()) => format!("Recording {}", airing.show_title()),
+            Err(e) => format!("Couldn't record {}: {}", airing.show_title(), e),
+        };
+        self.load_guide().await;
+    }
+
+    async fn cancel(&mut self) {
+        let Some(airing) = self.airing().cloned() else {
+            return;
+        };
+        let Some(id) = &airing.subscription_id else {
+            self.message = format!("{} has no subscription of its own", airing.show_title());
+            return;
+        };
+        self.message = match self.backend.cancel_subscription(id).await {
+            Ok(()) => format!("Cancelled {}", airing.show_title()),
+            Err(e) => format!("Couldn't cancel {}: {}", airing.show_title(), e),
+        };
+        self.load_guide().await;
+    }
+
+    fn select_next(&mut self, delta: isize) {
+        let (len, selected) = match self.focus {
+            Focus::Channels => (self.channels.len(), self.channel_list.selected()),
+            Focus::Guide => (self.airings.len(), self.airing_table.selected()),
+        };
+        if len == 0 {
+            return;
+        }
+        let next = selected
+            .map_or(0, |s| s as isize + delta)
+            .clamp(0, len as isize - 1) as usize;
+        match self.focus {
+            Focus::Channels => self.channel_list.select(Some(next)),
+            Focus::Guide => self.airing_table.select(Some(next)),
+        }
+    }
+
+    /// Handles a key press, returning false to quit
+    async fn handle_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Channels => Focus::Guide,
+                    Focus::Guide => Focus::Channels,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.select_next(-1);
+                if self.focus == Focus::Channels {
+                    self.load_guide().await;
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.select_next(1);
+                if self.focus == Focus::Channels {
+                    self.load_guide().await;
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.date -= Duration::days(1);
+                self.load_guide().await;
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.date += Duration::days(1);
+                self.load_guide().await;
+            }
+            KeyCode::Char('r') => self.record().await,
+            KeyCode::Char('c') => self.cancel().await,
+            KeyCode::Char('p') => {
+                self.showing_plan = !self.showing_plan;
+                self.load_plan();
+            }
+            KeyCode::Char('R') => {
+                self.load_plan();
+                self.load_guide().await;
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(frame.area());
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        if self.showing_plan {
+            let rows = self.plan.iter().map(|e| {
+                Row::new(vec![
+                    format_time(e.begins_at, "%a %H:%M"),
+                    e.channel_title.clone(),
+                    e.title.clone(),
+                    if e.scheduled {
+                        "scheduled"
+                    } else {
+                        "candidate"
+                    }
+                    .to_string(),
+                ])
+            });
+            let table = Table::new(
+                rows,
+                [
+                    Constraint::Length(10),
+                    Constraint::Length(20),
+                    Constraint::Fill(1),
+                    Constraint::Length(10),
+                ],
+            )
+            .header(Row::new(["STARTS", "CHANNEL", "TITLE", ""]))
+            .block(Block::bordered().title(" Auto-recorder plan "));
+            frame.render_widget(table, main);
+        } else {
+            let [left, right] =
+                Layout::horizontal([Constraint::Percentage(30), Constraint::Fill(1)]).areas(main);
+
+            let channels = List::new(self.channels.iter().map(|c| {
+                c.title
+                    .clone()
+                    .or_else(|| c.identifier.clone())
+                    .unwrap_or_else(|| c.id.clone())
+            }))
+            .block(Block::bordered().title(" Channels "))
+            .highlight_style(if self.focus == Focus::Channels {
+                highlight
+            } else {
+                Style::default().add_modifier(Modifier::BOLD)
+            });
+            frame.render_stateful_widget(channels, left, &mut self.channel_list);
+
+            let channel_id = self.channel().map(|c| c.id.clone());
+            let rows = self.airings.iter().map(|a| {
+                let planned = self.plan.iter().any(|e| {
+                    Some(&e.channel) == channel_id.as_ref() && e.begins_at == a.begins_at_ts()
+                });
+                let flag = if a.is_subscribed() {
+                    "REC"
+                } else if planned {
+                    "auto"
+                } else {
+                    ""
+                };
+                let title = match &a.grandparent_title {
+                    Some(show) => format!("{} - {}", show, a.title),
+                    None => a.title.clone(),
+                };
+                Row::new(vec![
+                    format_time(a.begins_at_ts(), "%H:%M"),
+                    flag.to_string(),
+                    title,
+                ])
+            });
+            let guide = Table::new(
+                rows,
+                [
+                    Constraint::Length(6),
+                    Constraint::Length(5),
+                    Constraint::Fill(1),
+                ],
+            )
+            .header(Row::new(["TIME", "", "TITLE"]))
+            .block(Block::bordered().title(format!(" {} ", self.date.format("%A %-d %B"))))
+            .row_highlight_style(if self.focus == Focus::Guide {
+                highlight
+            } else {
+                Style::default().add_modifier(Modifier::BOLD)
+            });
+            frame.render_stateful_widget(guide, right, &mut self.airing_table);
+        }
+
+        let help = "tab switch  ↑↓ move  ←→ day  r record  c cancel  p plan  R refresh  q quit";
+        frame.render_widget(
+            Paragraph::new(format!("{}\n{}", self.message, help)),
+            footer,
+        );
+    }
+
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(POLL_INTERVAL)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key.code).await {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Browses the guide for the configured channels, scheduling or cancelling by hand
+pub async fn run(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = connect_backend(config).await?;
+    let channels: Vec<Channel> = backend
+        .channels()
+        .await?
+        .into_iter()
+        .filter(|c| c.selected_by(&config.channels))
+        .collect();
+
+    let mut app = App {
+        backend,
+        state: State::open(config.state_path()).ok(),
+        channel_list: ListState::default().with_selected((!channels.is_empty()).then_some(0)),
+        channels,
+        date: Local::now().date_naive(),
+        airings: Vec::new(),
+        airing_table: TableState::default(),
+        plan: Vec::new(),
+        showing_plan: false,
+        focus: Focus::Channels,
+        message: String::new(),
+    };
+    app.load_plan();
+    app.load_guide().await;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal).await;
+    ratatui::restore();
+    Ok(result?)
+}
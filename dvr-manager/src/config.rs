@@ -0,0 +1,368 @@
+use crate::backend::UnknownTypePolicy;
+use crate::cleanup::CleanupConfig;
+use crate::daily_window::DailyWindow;
+use crate::lease::LeaseConfig;
+use crate::notify::NotifyConfig;
+use crate::plex;
+#[cfg(feature = "postprocess")]
+use crate::postprocess::PostProcessConfig;
+use crate::radarr::RadarrConfig;
+use crate::sonarr::SonarrConfig;
+use crate::state;
+use crate::tautulli::TautulliConfig;
+use crate::tmdb::TmdbConfig;
+use crate::trakt::TraktConfig;
+use crate::weekly_report::WeeklyReportConfig;
+use crate::xmltv::XmltvConfig;
+use figment::providers::{Env, Serialized};
+use figment::Figment;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const DATA_DIR: &str = "/config/dvr-manager";
+/// Where the state database lived before the data directory
+const LEGACY_STATE_PATH: &str = "/config/dvr-manager.db";
+
+/// Stands in for a secret in debug output, showing only whether it's set
+pub fn redacted(secret: &Option<String>) -> Option<&'static str> {
+    secret.as_ref().map(|_| plex::REDACTED)
+}
+
+/// Channels collected under a name, so long lists can be referred to as
+/// `@name` wherever channels are given
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(transparent)]
+pub struct ChannelGroups(HashMap<String, Vec<String>>);
+
+impl ChannelGroups {
+    pub fn new(groups: HashMap<String, Vec<String>>) -> Self {
+        ChannelGroups(groups)
+    }
+
+    /// Replaces references to groups with their channels, keeping the order
+    /// they're given in and leaving out repeats
+    pub fn expand(&self, channels: &[String]) -> Result<Vec<String>, String> {
+        let mut expanded: Vec<String> = Vec::new();
+        for channel in channels {
+            let members = match channel.strip_prefix('@') {
+                Some(name) => self
+                    .0
+                    .get(name)
+                    .ok_or_else(|| format!("There's no channel group called {}", name))?
+                    .as_slice(),
+                None => std::slice::from_ref(channel),
+            };
+            for member in members {
+                if member.starts_with('@') {
+                    return Err(format!(
+                        "Channel group {} refers to {}, but groups can't contain groups",
+                        channel, member
+                    ));
+                }
+                if !expanded.contains(member) {
+                    expanded.push(member.clone());
+                }
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+/// How the async runtime schedules work
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeFlavor {
+    /// A pool of worker threads, one per core unless `worker_threads` is set
+    #[default]
+    MultiThread,
+    /// Everything on the main thread, enough for the mostly idle manager on low
+    /// power devices
+    CurrentThread,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    pub plex_prefs_path: Option<String>,
+    pub plex_url: Option<String>,
+    /// Used instead of the token in Plex's preferences if set
+    pub plex_token: Option<String>,
+    pub tv_library_id: Option<String>,
+    pub film_library_id: Option<String>,
+    /// Channel identifiers, or `@name` for all those in a channel group
+    pub channels: Vec<String>,
+    /// Named sets of channels, e.g. `{kids=["002","003"]}`
+    #[serde(default)]
+    pub channel_groups: ChannelGroups,
+    /// Names of extra Plex servers to manage, each configured by
+    /// `DVR_MANAGER_<NAME>_*` variables over these settings
+    #[serde(default)]
+    pub servers: Vec<String>,
+    #[serde(default)]
+    pub titles: Vec<String>,
+    /// Other names shows and films go by, e.g. `{"NCIS: LA"="NCIS: Los Angeles"}`,
+    /// so they're recognised under any of them
+    #[serde(default)]
+    pub title_aliases: HashMap<String, String>,
+    /// Record series premieres from the channels, besides any titles
+    #[serde(default)]
+    pub premieres_only: bool,
+    /// Phrases to record any airing mentioning, on any channel in the lineup
+    #[serde(default)]
+    pub wishlist: Vec<String>,
+    pub heartbeat_url: Option<String>,
+    /// Where the state database and other persistent files live
+    pub data_dir: Option<String>,
+    /// Defaults to a database in the data directory
+    pub state_path: Option<String>,
+    /// File locked while the manager runs, so only one instance schedules at a time
+    pub lock_path: Option<String>,
+    /// Wait for another running instance to exit instead of failing
+    #[serde(default)]
+    pub wait_for_lock: bool,
+    pub sentry_dsn: Option<String>,
+    #[serde(default)]
+    pub runtime: RuntimeFlavor,
+    /// Worker threads for the multi-threaded runtime
+    pub worker_threads: Option<usize>,
+    /// Seconds before retrying a pass that panicked, and the most a pass
+    /// that couldn't reach Plex backs off for
+    pub restart_delay: Option<u64>,
+    /// Hours of guide to have for each channel, later days are only fetched
+    /// once what's been fetched runs out within this, 12 by default
+    pub guide_horizon: Option<u64>,
+    /// Most seconds added at random to the hourly wake-up when nothing's due, 300 by default
+    pub poll_jitter: Option<u64>,
+    /// Seconds those wake-ups spread each day's guide requests over, 60 by default
+    pub guide_fetch_spread: Option<u64>,
+    /// Days of history, failures and cached data to keep, 90 by default or 0 to keep everything
+    pub history_retention: Option<u64>,
+    /// Attempts at scheduling an airing before giving up, 3 by default
+    pub retry_attempts: Option<u32>,
+    /// Seconds the NAS's and Plex's clocks may disagree by once Plex's has been
+    /// allowed for. Airings are subscribed to this much earlier, and still this
+    /// long after they seem to have started. 0 by default.
+    pub clock_skew_allowance: Option<u64>,
+    /// Daily local time Plex restarts for updates, e.g. `03:00-03:30`, when
+    /// passes pause and failures aren't notified about
+    pub maintenance_window: Option<DailyWindow>,
+    /// Seconds a pass may take, on top of the time guide requests are spread
+    /// over, before it's abandoned and retried. 180 by default.
+    pub pass_timeout: Option<u64>,
+    /// Seconds before retrying a failed subscription, doubling each attempt
+    pub retry_backoff: Option<u64>,
+    /// Re-enable configured channels the DVR's channel mapping has disabled,
+    /// as lineup rescans sometimes do
+    #[serde(default)]
+    pub enable_channels: bool,
+    /// Most recordings to have going at once, below the tuner count to keep
+    /// one free for live TV
+    pub max_concurrent_recordings: Option<u32>,
+    /// How many tuners the DVR has, needed to keep one free for live TV
+    pub tuners: Option<u32>,
+    /// Times of day to keep a tuner free for watching live TV, e.g.
+    /// `["19:00-22:00"]`
+    #[serde(default)]
+    pub live_tv_windows: Vec<DailyWindow>,
+    /// What to do with airings whose subscription template is for something
+    /// other than a film or TV show: `reject` them (the default), or record
+    /// them into the `tv` or `film` library
+    #[serde(default)]
+    pub unknown_template_type: UnknownTypePolicy,
+    /// Seconds to reuse a programme's subscription template, 0 to fetch it every time
+    pub template_cache_ttl: Option<u64>,
+    /// Seconds to reuse a fetched guide across passes and restarts, 0 to always refetch
+    pub grid_cache_ttl: Option<i64>,
+    /// Decide what to record or delete, but only log it
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Address for the HTTP server, e.g. `0.0.0.0:8080`, disabled if unset
+    pub listen_addr: Option<String>,
+    /// Required as `?token=` on the Plex webhook URL if set
+    pub webhook_token: Option<String>,
+    pub api_token: Option<String>,
+    /// Serve Swagger UI for the API at `/docs`
+    #[serde(default)]
+    pub api_docs: bool,
+    pub calendar_path: Option<String>,
+    /// Notify with what changed in the plan after each pass
+    #[serde(default)]
+    pub notify_plan_changes: bool,
+    #[serde(flatten)]
+    pub notify: NotifyConfig,
+    #[serde(flatten)]
+    pub lease: LeaseConfig,
+    #[serde(flatten)]
+    pub sonarr: SonarrConfig,
+    #[serde(flatten)]
+    pub radarr: RadarrConfig,
+    #[serde(flatten)]
+    pub trakt: TraktConfig,
+    #[serde(flatten)]
+    pub tmdb: TmdbConfig,
+    #[serde(flatten)]
+    pub xmltv: XmltvConfig,
+    #[serde(flatten)]
+    pub tautulli: TautulliConfig,
+    #[serde(flatten)]
+    pub cleanup: CleanupConfig,
+    #[serde(flatten)]
+    pub weekly_report: WeeklyReportConfig,
+    #[cfg(feature = "postprocess")]
+    #[serde(flatten)]
+    pub postprocess: PostProcessConfig,
+}
+
+/// Dumped at debug level, so secrets only show whether they're set
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut config = f.debug_struct("Config");
+        config
+            .field("plex_prefs_path", &self.plex_prefs_path)
+            .field("plex_url", &self.plex_url)
+            .field("plex_token", &redacted(&self.plex_token))
+            .field("tv_library_id", &self.tv_library_id)
+            .field("film_library_id", &self.film_library_id)
+            .field("channels", &self.channels)
+            .field("channel_groups", &self.channel_groups)
+            .field("servers", &self.servers)
+            .field("titles", &self.titles)
+            .field("title_aliases", &self.title_aliases)
+            .field("premieres_only", &self.premieres_only)
+            .field("wishlist", &self.wishlist)
+            .field("heartbeat_url", &self.heartbeat_url)
+            .field("data_dir", &self.data_dir)
+            .field("state_path", &self.state_path)
+            .field("lock_path", &self.lock_path)
+            .field("wait_for_lock", &self.wait_for_lock)
+            .field("sentry_dsn", &redacted(&self.sentry_dsn))
+            .field("runtime", &self.runtime)
+            .field("worker_threads", &self.worker_threads)
+            .field("restart_delay", &self.restart_delay)
+            .field("guide_horizon", &self.guide_horizon)
+            .field("poll_jitter", &self.poll_jitter)
+            .field("guide_fetch_spread", &self.guide_fetch_spread)
+            .field("history_retention", &self.history_retention)
+            .field("retry_attempts", &self.retry_attempts)
+            .field("clock_skew_allowance", &self.clock_skew_allowance)
+            .field("maintenance_window", &self.maintenance_window)
+            .field("pass_timeout", &self.pass_timeout)
+            .field("retry_backoff", &self.retry_backoff)
+            .field("enable_channels", &self.enable_channels)
+            .field("max_concurrent_recordings", &self.max_concurrent_recordings)
+            .field("tuners", &self.tuners)
+            .field("live_tv_windows", &self.live_tv_windows)
+            .field("unknown_template_type", &self.unknown_template_type)
+            .field("template_cache_ttl", &self.template_cache_ttl)
+            .field("grid_cache_ttl", &self.grid_cache_ttl)
+            .field("dry_run", &self.dry_run)
+            .field("listen_addr", &self.listen_addr)
+            .field("webhook_token", &redacted(&self.webhook_token))
+            .field("api_token", &redacted(&self.api_token))
+            .field("api_docs", &self.api_docs)
+            .field("calendar_path", &self.calendar_path)
+            .field("notify_plan_changes", &self.notify_plan_changes)
+            .field("notify", &self.notify)
+            .field("lease", &self.lease)
+            .field("sonarr", &self.sonarr)
+            .field("radarr", &self.radarr)
+            .field("trakt", &self.trakt)
+            .field("tmdb", &self.tmdb)
+            .field("xmltv", &self.xmltv)
+            .field("tautulli", &self.tautulli)
+            .field("cleanup", &self.cleanup)
+            .field("weekly_report", &self.weekly_report);
+        #[cfg(feature = "postprocess")]
+        config.field("postprocess", &self.postprocess);
+        config.finish()
+    }
+}
+
+impl Config {
+    pub fn data_dir(&self) -> &Path {
+        Path::new(self.data_dir.as_deref().unwrap_or(DATA_DIR))
+    }
+
+    pub fn state_path(&self) -> PathBuf {
+        match &self.state_path {
+            Some(path) => PathBuf::from(path),
+            None => self.data_dir().join(state::STATE_FILE),
+        }
+    }
+
+    /// Defaults to a `.lock` file beside the state database
+    pub fn lock_path(&self) -> PathBuf {
+        match &self.lock_path {
+            Some(path) => PathBuf::from(path),
+            None => self.state_path().with_extension("lock"),
+        }
+    }
+
+    /// Creates the data directory, readable only by the owner and group since the
+    /// state holds API tokens, and moves in a database left at the old default path
+    pub fn create_data_dir(&self) -> std::io::Result<()> {
+        let dir = self.data_dir();
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o750);
+        builder.create(dir)?;
+
+        let state_path = self.state_path();
+        let legacy = Path::new(LEGACY_STATE_PATH);
+        if self.state_path.is_none() && !state_path.exists() && legacy.exists() {
+            log::info!(
+                "Moving state from {} to {}",
+                legacy.display(),
+                state_path.display()
+            );
+            std::fs::rename(legacy, &state_path)?;
+        }
+        Ok(())
+    }
+
+    fn figment() -> Figment {
+        Figment::from(Serialized::defaults(Config::default())).merge(Env::prefixed("DVR_MANAGER_"))
+    }
+
+    pub fn load() -> Result<Self, Box<figment::Error>> {
+        let config: Config = Self::figment().extract_lossy().map_err(Box::new)?;
+        config.with_groups_expanded()
+    }
+
+    /// Resolves `@name` references to channel groups, so the rest of the
+    /// manager only sees channel identifiers
+    fn with_groups_expanded(mut self) -> Result<Self, Box<figment::Error>> {
+        self.channels = self
+            .channel_groups
+            .expand(&self.channels)
+            .map_err(|e| Box::new(figment::Error::from(e)))?;
+        Ok(self)
+    }
+
+    /// Settings for one of the extra `servers`, from `DVR_MANAGER_<NAME>_*`
+    /// variables layered over the shared ones
+    pub fn load_server(&self, name: &str) -> Result<Self, Box<figment::Error>> {
+        let prefix = format!("DVR_MANAGER_{}_", name.to_uppercase());
+        let mut config: Config = Self::figment()
+            .merge(Env::prefixed(&prefix))
+            .extract_lossy()
+            .map_err(Box::new)?;
+        // Each server needs its own state, or their passes would overwrite each other
+        if config.state_path == self.state_path {
+            let path = self.state_path();
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("dvr-manager");
+            config.state_path = Some(
+                path.with_file_name(format!("{}-{}.db", stem, name))
+                    .display()
+                    .to_string(),
+            );
+        }
+        config.dry_run = self.dry_run;
+        config.servers = Vec::new();
+        config.with_groups_expanded()
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// A daily stretch of local time, e.g. `03:00-03:30`. The end may be past
+/// midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DailyWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl DailyWindow {
+    /// When the window `at` falls in ends, or `None` if it's outside the
+    /// window. Times are those of `tz`, Plex's clock being on local time.
+    pub fn end_of<Tz: TimeZone>(&self, at: DateTime<Utc>, tz: &Tz) -> Option<DateTime<Utc>> {
+        let local = at.with_timezone(tz).naive_local();
+        let time = local.time();
+        let date = local.date();
+        let end_date = if self.start < self.end {
+            (self.start <= time && time < self.end).then_some(date)?
+        } else if time >= self.start {
+            date.succ_opt()?
+        } else if time < self.end {
+            date
+        } else {
+            return None;
+        };
+        Some(resolve(end_date.and_time(self.end), tz))
+    }
+
+    /// Whether any of the time from `begins_at` until `ends_at` is in the window
+    pub fn overlaps<Tz: TimeZone>(
+        &self,
+        begins_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        tz: &Tz,
+    ) -> bool {
+        if self.end_of(begins_at, tz).is_some() {
+            return true;
+        }
+        // Otherwise the window has to start part way through
+        let last = ends_at.with_timezone(tz).date_naive();
+        begins_at
+            .with_timezone(tz)
+            .date_naive()
+            .iter_days()
+            .take_while(|date| *date <= last)
+            .map(|date| resolve(date.and_time(self.start), tz))
+            .any(|start| begins_at < start && start < ends_at)
+    }
+}
+
+/// A local time as UTC, taking the moment after a daylight saving gap if
+/// the clocks skip it
+fn resolve<Tz: TimeZone>(local: NaiveDateTime, tz: &Tz) -> DateTime<Utc> {
+    (0..=2)
+        .find_map(|hours| {
+            tz.from_local_datetime(&(local + Duration::hours(hours)))
+                .earliest()
+        })
+        .map_or_else(|| local.and_utc(), |t| t.with_timezone(&Utc))
+}
+
+impl FromStr for DailyWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Time window must be like 03:00-03:30, not {}", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse =
+            |t: &str| NaiveTime::parse_from_str(t.trim(), TIME_FORMAT).map_err(|_| invalid());
+        let window = DailyWindow {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!("Time window {} is empty", s));
+        }
+        Ok(window)
+    }
+}
+
+impl TryFrom<String> for DailyWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for DailyWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format(TIME_FORMAT),
+            self.end.format(TIME_FORMAT)
+        )
+    }
+}
+
+impl From<DailyWindow> for String {
+    fn from(window: DailyWindow) -> Self {
+        window.to_string()
+    }
+}
@@ -0,0 +1,110 @@
+use crate::plex::GridMetadata;
+use serde::Serialize;
+use std::fmt;
+
+/// Log target for per-airing decisions, enable with
+/// `RUST_LOG=dvr_manager::decision=trace` to see why shows weren't recorded
+const TARGET: &str = "dvr_manager::decision";
+
+/// Why an airing in the guide wasn't picked for recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Its start time is out of range, so the guide entry is garbled
+    InvalidStartTime,
+    /// The guide entry has no guid, which Plex needs to subscribe
+    MissingGuid,
+    /// Began before the pass started
+    AlreadyStarted,
+    /// Plex already has a subscription covering it
+    AlreadySubscribed,
+    /// Airs on a channel that isn't in the configured list
+    ChannelNotSelected,
+    /// Not among the configured titles or the Trakt watchlist
+    NotInAllowlist,
+    /// Only series premieres are being recorded, and this isn't one
+    NotAPremiere,
+    /// An earlier airing on the same channel will be recorded first
+    LaterAiring,
+    /// Rated below the configured threshold on TMDB
+    LowRating,
+    /// Sonarr already has or will download the episode
+    Sonarr,
+    /// Radarr already has or will download the film
+    Radarr,
+    /// As many recordings as are allowed at once would be going
+    RecordingLimit,
+    /// A tuner is being kept free for live TV
+    LiveTv,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            SkipReason::InvalidStartTime => "invalid start time",
+            SkipReason::MissingGuid => "missing guid",
+            SkipReason::AlreadyStarted => "already started",
+            SkipReason::AlreadySubscribed => "already subscribed",
+            SkipReason::ChannelNotSelected => "channel not selected",
+            SkipReason::NotInAllowlist => "not in allowlist",
+            SkipReason::NotAPremiere => "not a series premiere",
+            SkipReason::LaterAiring => "later airing",
+            SkipReason::LowRating => "rated too low",
+            SkipReason::Sonarr => "handled by Sonarr",
+            SkipReason::Radarr => "handled by Radarr",
+            SkipReason::RecordingLimit => "too many recordings at once",
+            SkipReason::LiveTv => "tuner kept for live TV",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl SkipReason {
+    /// Whether skipping for this reason belongs in the decision log. Airings
+    /// that have started or been subscribed to were decided on before.
+    pub fn is_decision(self) -> bool {
+        !matches!(
+            self,
+            SkipReason::AlreadyStarted | SkipReason::AlreadySubscribed
+        )
+    }
+}
+
+/// What a pass made of an airing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Scheduled,
+    Skipped(SkipReason),
+    Failed(String),
+}
+
+impl Verdict {
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            Verdict::Scheduled => "scheduled",
+            Verdict::Skipped(_) => "skipped",
+            Verdict::Failed(_) => "failed",
+        }
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            Verdict::Scheduled => None,
+            Verdict::Skipped(reason) => Some(reason.to_string()),
+            Verdict::Failed(error) => Some(error.clone()),
+        }
+    }
+}
+
+pub fn log_skip(show: &GridMetadata, reason: SkipReason) {
+    let media = show.media.first();
+    log::trace!(
+        target: TARGET,
+        "skip guid={} title={:?} channel={} begins_at={} reason={:?}",
+        show.guid,
+        show.show_title(),
+        media.map_or("", |m| m.channel_identifier.as_str()),
+        show.begins_at_ts(),
+        reason.to_string(),
+    );
+}
@@ -0,0 +1,224 @@
+use crate::notify;
+#[cfg(feature = "notifications")]
+use crate::notify::email::Email;
+use crate::state::{self, CleanupStats, HistoryEntry, State};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::time::sleep;
+
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestPeriod {
+    Daily,
+    Weekly,
+}
+
+impl DigestPeriod {
+    fn duration(self) -> Duration {
+        match self {
+            DigestPeriod::Daily => Duration::days(1),
+            DigestPeriod::Weekly => Duration::weeks(1),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DigestPeriod::Daily => "day",
+            DigestPeriod::Weekly => "week",
+        }
+    }
+}
+
+/// Summary of what the manager did over a period
+pub struct Digest {
+    period: DigestPeriod,
+    history: Vec<HistoryEntry>,
+    cleanup: CleanupStats,
+}
+
+impl Digest {
+    pub fn build(state: &State, period: DigestPeriod) -> state::Result<Self> {
+        Self::build_at(state, period, state.now())
+    }
+
+    /// The digest for the period up to `now`
+    pub fn build_at(
+        state: &State,
+        period: DigestPeriod,
+        now: DateTime<Utc>,
+    ) -> state::Result<Self> {
+        let since = (now - period.duration()).timestamp();
+        Ok(Digest {
+            period,
+            history: state.history_since(since)?,
+            cleanup: state.cleanup_since(since)?,
+        })
+    }
+
+    fn of_kind<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a HistoryEntry> {
+        self.history.iter().filter(move |h| h.kind == kind)
+    }
+
+    /// Failures, and airings dropped for lack of a tuner
+    pub fn problems(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history
+            .iter()
+            .filter(|h| h.kind == "failed" || h.kind == "dropped")
+    }
+
+    /// Each problem as a line, with when it happened
+    pub fn problem_lines(&self) -> Vec<String> {
+        self.problems().map(problem_line).collect()
+    }
+
+    pub fn cleanup_line(&self) -> String {
+        format!(
+            "{} recordings deleted, {} MB freed",
+            self.cleanup.deleted,
+            self.cleanup.bytes_freed / 1_000_000
+        )
+    }
+
+    pub fn subject(&self) -> String {
+        format!(
+            "DVR digest: {} scheduled, {} recorded, {} failed",
+            self.of_kind("scheduled").count(),
+            self.of_kind("recorded").count(),
+            self.of_kind("failed").count()
+        )
+    }
+
+    pub fn body(&self) -> String {
+        let mut body = format!(
+            "What the DVR manager did in the past {}.\n",
+            self.period.name()
+        );
+
+        let scheduled: Vec<_> = self.of_kind("scheduled").collect();
+        body += &format!("\nScheduled ({}):\n", scheduled.len());
+        for h in &scheduled {
+            body += &format!(
+                "  {}  {} ({})\n",
+                notify::format_time(h.begins_at.unwrap_or(h.at)),
+                h.title.as_deref().unwrap_or("?"),
+                h.channel.as_deref().unwrap_or("?")
+            );
+        }
+
+        let recorded: Vec<_> = self.of_kind("recorded").collect();
+        body += &format!("\nRecorded ({}):\n", recorded.len());
+        for h in &recorded {
+            body += &format!(
+                "  {}  {}\n",
+                notify::format_time(h.at),
+                h.title.as_deref().unwrap_or("?")
+            );
+        }
+
+        let failed: Vec<_> = self.of_kind("failed").collect();
+        if !failed.is_empty() {
+            body += &format!("\nFailed ({}):\n", failed.len());
+            for h in &failed {
+                body += &format!("  {}\n", problem_line(h));
+            }
+        }
+
+        let deleted: Vec<_> = self.of_kind("deleted").collect();
+        if !deleted.is_empty() {
+            body += &format!("\nDeleted ({}):\n", deleted.len());
+            for h in &deleted {
+                body += &format!(
+                    "  {}  {}\n",
+                    notify::format_time(h.at),
+                    h.title.as_deref().unwrap_or("?")
+                );
+            }
+        }
+
+        body += &format!("\nCleanup: {}\n", self.cleanup_line());
+        body
+    }
+}
+
+fn problem_line(h: &HistoryEntry) -> String {
+    let title = h.title.as_deref().unwrap_or("pass");
+    let what = match h.kind.as_str() {
+        "dropped" => format!("{} not recorded", title),
+        _ => title.to_string(),
+    };
+    format!(
+        "{}  {}: {}",
+        notify::format_time(h.begins_at.unwrap_or(h.at)),
+        what,
+        h.error.as_deref().unwrap_or("")
+    )
+}
+
+/// When something sent every so often was last sent, as kept in the state
+pub struct Periodic {
+    pub period: Duration,
+    pub last: fn(&State) -> state::Result<Option<i64>>,
+    pub set_last: fn(&State, DateTime<Utc>) -> state::Result<()>,
+}
+
+impl Periodic {
+    async fn send_if_due<F, Fut>(&self, state: &State, send: &mut F) -> Result<(), String>
+    where
+        F: FnMut(DateTime<Utc>) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let now = state.now();
+        match (self.last)(state).map_err(|e| e.to_string())? {
+            // Start counting from the first run rather than sending about a
+            // period the manager wasn't running for
+            None => return (self.set_last)(state, now).map_err(|e| e.to_string()),
+            Some(last) if now.timestamp() - last < self.period.num_seconds() => return Ok(()),
+            Some(_) => (),
+        }
+        send(now).await?;
+        (self.set_last)(state, now).map_err(|e| e.to_string())
+    }
+
+    /// Runs forever, calling `send` once each period with the time it's for
+    pub async fn run<F, Fut>(self, state: Arc<State>, what: &str, mut send: F)
+    where
+        F: FnMut(DateTime<Utc>) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        loop {
+            if let Err(e) = self.send_if_due(&state, &mut send).await {
+                log::warn!("Couldn't send {}: {}", what, e);
+            }
+            sleep(CHECK_INTERVAL).await;
+        }
+    }
+}
+
+/// Runs forever, emailing a digest each period
+#[cfg(feature = "notifications")]
+pub async fn run(state: Arc<State>, email: Email, period: DigestPeriod) {
+    let periodic = Periodic {
+        period: period.duration(),
+        last: State::last_digest,
+        set_last: State::set_last_digest,
+    };
+    periodic
+        .run(state.clone(), "digest", |now| {
+            let state = state.clone();
+            let email = email.clone();
+            async move {
+                let digest = Digest::build_at(&state, period, now).map_err(|e| e.to_string())?;
+                email
+                    .send(&digest.subject(), digest.body())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                log::info!("Sent {} digest", period.name());
+                Ok(())
+            }
+        })
+        .await
+}
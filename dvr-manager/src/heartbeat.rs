@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pings a healthchecks.io-style URL so that a stalled loop can be alerted on.
+/// Success pings go to the URL itself, failures to `<url>/fail`.
+pub struct Heartbeat {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Heartbeat {
+    pub fn new(url: String) -> Self {
+        Heartbeat {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn success(&self) {
+        self.ping(&self.url, String::new()).await;
+    }
+
+    pub async fn fail(&self, message: &str) {
+        self.ping(&format!("{}/fail", self.url), message.to_string())
+            .await;
+    }
+
+    /// Heartbeat failures are logged but never interrupt scheduling
+    async fn ping(&self, url: &str, body: String) {
+        let result = self
+            .client
+            .post(url)
+            .timeout(PING_TIMEOUT)
+            .body(body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+
+        if let Err(e) = result {
+            log::warn!("Heartbeat ping failed: {}", e.without_url());
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::clock::{Clock, SystemClock};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const DEFAULT_TTL: u64 = 60;
+/// How long to wait after writing the lease before trusting it, so a peer
+/// writing at the same moment has its write seen
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+pub enum LeaseError {
+    #[error("Couldn't access lease {0}: {1}")]
+    Io(String, std::io::Error),
+
+    #[error("Lost leadership to {0}")]
+    Lost(String),
+}
+
+pub type Result<T, E = LeaseError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct LeaseConfig {
+    /// File on storage shared with standby instances; only the holder schedules
+    pub lease_path: Option<String>,
+    /// Seconds a lease lasts without being renewed
+    pub lease_ttl: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct LeaseFile {
+    holder: String,
+    expires_at: i64,
+}
+
+/// A lease on a shared file, so redundant instances take turns being leader
+pub struct Lease {
+    path: PathBuf,
+    ttl: Duration,
+    id: String,
+    /// Expiries are written by this, so they must agree with peers' clocks
+    clock: Arc<dyn Clock>,
+}
+
+impl Lease {
+    /// Returns `None` if no lease file is configured
+    pub fn new(config: &LeaseConfig) -> Option<Self> {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".into());
+        Some(Lease {
+            path: PathBuf::from(config.lease_path.as_ref()?),
+            ttl: Duration::from_secs(config.lease_ttl.unwrap_or(DEFAULT_TTL)),
+            id: format!("{}:{}", host, std::process::id()),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Times expiries by another clock, e.g. to simulate a peer's lease running out
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Lease { clock, ..self }
+    }
+
+    fn io_err(&self, e: std::io::Error) -> LeaseError {
+        LeaseError::Io(self.path.display().to_string(), e)
+    }
+
+    async fn read(&self) -> Result<Option<LeaseFile>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            // A partial or corrupt lease is treated as expired
+            Ok(json) => Ok(serde_json::from_str(&json).ok()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(self.io_err(e)),
+        }
+    }
+
+    /// Writes the lease via a rename, so peers never read half of it
+    async fn write(&self) -> Result<()> {
+        let lease = LeaseFile {
+            holder: self.id.clone(),
+            expires_at: self.clock.now().timestamp() + self.ttl.as_secs() as i64,
+        };
+        let tmp = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        let json = serde_json::to_string(&lease).expect("lease is serializable");
+        tokio::fs::write(&tmp, json)
+            .await
+            .map_err(|e| self.io_err(e))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| self.io_err(e))
+    }
+
+    /// Tries to take or renew the lease, returning the current holder if someone else has it
+    async fn try_hold(&self) -> Result<Option<String>> {
+        match self.read().await? {
+            Some(lease)
+                if lease.holder != self.id && lease.expires_at > self.clock.now().timestamp() =>
+            {
+                return Ok(Some(lease.holder));
+            }
+            _ => (),
+        }
+        self.write().await?;
+        sleep(SETTLE_TIME).await;
+        match self.read().await? {
+            Some(lease) if lease.holder != self.id => Ok(Some(lease.holder)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Waits on standby until this instance becomes leader
+    pub async fn acquire(&self) -> Result<()> {
+        let mut announced = false;
+        loop {
+            match self.try_hold().await? {
+                None => {
+                    log::info!("Became leader as {}", self.id);
+                    return Ok(());
+                }
+                Some(holder) if !announced => {
+                    log::info!("Standing by while {} is leader", holder);
+                    announced = true;
+                }
+                Some(_) => (),
+            }
+            sleep(self.ttl / 3).await;
+        }
+    }
+
+    /// Renews the lease until another instance takes it over, which only
+    /// happens if renewals stopped for longer than the TTL
+    pub async fn keep(&self) -> LeaseError {
+        let mut renewed = Instant::now();
+        loop {
+            sleep(self.ttl / 3).await;
+            match self.try_hold().await {
+                Ok(None) => renewed = Instant::now(),
+                Ok(Some(holder)) => return LeaseError::Lost(holder),
+                // A standby may have taken over by now, so stop rather than risk two leaders
+                Err(e) if renewed.elapsed() >= self.ttl => return e,
+                Err(e) => log::warn!("Couldn't renew lease: {}", e),
+            }
+        }
+    }
+}
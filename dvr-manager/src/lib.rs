@@ -0,0 +1,36 @@
+//! The DVR manager as a library, so tests and benchmarks can reach its internals.
+//! The binary in `main.rs` wires these together.
+
+pub mod backend;
+pub mod calendar;
+pub mod cleanup;
+pub mod cli;
+pub mod clock;
+pub mod commands;
+pub mod config;
+pub mod daily_window;
+pub mod decision;
+pub mod digest;
+pub mod heartbeat;
+pub mod lease;
+pub mod lock;
+pub mod manager;
+pub mod notify;
+pub mod plan_diff;
+pub mod plex;
+#[cfg(feature = "postprocess")]
+pub mod postprocess;
+pub mod radarr;
+pub mod reporting;
+pub mod retention;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sonarr;
+pub mod state;
+pub mod tautulli;
+pub mod title;
+pub mod tmdb;
+pub mod trakt;
+pub mod weekly_report;
+pub mod wishlist;
+pub mod xmltv;
@@ -0,0 +1,60 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockError {
+    #[error("Another dvr-manager ({0}) is already running against this config, stop it first or set DVR_MANAGER_WAIT_FOR_LOCK to wait for it")]
+    Held(String),
+
+    #[error("Couldn't lock {0}: {1}")]
+    Io(String, std::io::Error),
+}
+
+pub type Result<T, E = LockError> = std::result::Result<T, E>;
+
+/// Keeps other instances from scheduling against the same Plex server until dropped
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock, failing straight away if it's held unless `wait` is set
+    pub async fn acquire(path: &Path, wait: bool) -> Result<Self> {
+        let io_err = |e| LockError::Io(path.display().to_string(), e);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(io_err)?;
+
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) if wait => {
+                log::info!("Waiting for another instance to release {}", path.display());
+                file = tokio::task::spawn_blocking(move || file.lock().map(|_| file))
+                    .await
+                    .expect("lock task doesn't panic")
+                    .map_err(io_err)?;
+            }
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+                let holder = match holder.trim() {
+                    "" => "unknown pid".to_string(),
+                    pid => format!("pid {}", pid),
+                };
+                return Err(LockError::Held(holder));
+            }
+            Err(TryLockError::Error(e)) => return Err(io_err(e)),
+        }
+
+        // Record who holds it, for the message the next instance prints
+        file.set_len(0).map_err(io_err)?;
+        file.rewind().map_err(io_err)?;
+        write!(file, "{}", std::process::id()).map_err(io_err)?;
+        Ok(InstanceLock { _file: file })
+    }
+}
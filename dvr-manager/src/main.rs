@@ -1,45 +1,241 @@
-mod manager;
-mod plex;
-
-use figment::{providers::Serialized, Figment};
-use manager::{Manager, ManagerConfig};
-use plex::{Plex, PlexHost};
-use serde::{Serialize, Deserialize};
-
-#[derive(Serialize, Deserialize, Default, Debug)]
-struct Config {
-    plex_prefs_path: Option<String>,
-    plex_url: Option<String>,
-    tv_library_id: Option<String>,
-    film_library_id: Option<String>,
-    channels: Vec<String>,
-    size_limit: Option<usize>,
-}
+use clap::Parser;
+use dvr_manager::backend::{self, CachedBackend, DvrBackend};
+use dvr_manager::cli::{Cli, Command};
+use dvr_manager::config::{Config, RuntimeFlavor};
+#[cfg(feature = "notifications")]
+use dvr_manager::digest;
+use dvr_manager::lease::Lease;
+use dvr_manager::lock::InstanceLock;
+use dvr_manager::manager::{Manager, ManagerConfig};
+#[cfg(feature = "notifications")]
+use dvr_manager::notify::email::Email;
+use dvr_manager::notify::Notifiers;
+#[cfg(feature = "postprocess")]
+use dvr_manager::postprocess::{self, PostProcessor};
+#[cfg(feature = "server")]
+use dvr_manager::server;
+use dvr_manager::state::State;
+#[cfg(feature = "postprocess")]
+use dvr_manager::title::TitleAliases;
+use dvr_manager::weekly_report;
+use dvr_manager::{commands, reporting};
+use futures::future::try_join_all;
+use std::sync::Arc;
+use tokio::runtime::{self, Runtime};
+use tokio::sync::Notify;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
+/// Schedules recordings on one Plex server. Only the primary server serves HTTP,
+/// since webhooks and the API are tied to a single listen address.
+async fn run_server(
+    config: Config,
+    notifiers: Arc<Notifiers>,
+    primary: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = commands::connect_backend(&config).await?;
 
-    let config: Config = Figment::from(Serialized::defaults(Config::default()))
-        .merge(figment::providers::Env::prefixed("DVR_MANAGER_"))
-        .extract()?;
+    let state = Arc::new(State::open(config.state_path())?);
 
-    log::debug!("{:#?}", config);
-    
-    let host = config.plex_url
-        .map(PlexHost::Custom)
-        .unwrap_or(PlexHost::Localhost);
-    let plex = Plex::new(config.plex_prefs_path, host)?;
+    let grid_cache_ttl = config.grid_cache_ttl.unwrap_or(backend::GRID_CACHE_TTL);
+    let backend: Arc<dyn DvrBackend> = if grid_cache_ttl > 0 {
+        Arc::new(CachedBackend::new(backend, state.clone(), grid_cache_ttl))
+    } else {
+        backend
+    };
 
     let manager_config = ManagerConfig {
-        tv_library_id: config.tv_library_id,
-        film_library_id: config.film_library_id,
         channels: config.channels,
-        limit: config.size_limit,
+        titles: config.titles,
+        title_aliases: config.title_aliases.clone(),
+        premieres_only: config.premieres_only,
+        wishlist: config.wishlist,
+        heartbeat_url: config.heartbeat_url,
+        restart_delay: config.restart_delay,
+        guide_horizon: config.guide_horizon,
+        poll_jitter: config.poll_jitter,
+        guide_fetch_spread: config.guide_fetch_spread,
+        retry_attempts: config.retry_attempts,
+        clock_skew_allowance: config.clock_skew_allowance,
+        pass_timeout: config.pass_timeout,
+        maintenance_window: config.maintenance_window,
+        retry_backoff: config.retry_backoff,
+        enable_channels: config.enable_channels,
+        max_concurrent_recordings: config.max_concurrent_recordings,
+        tuners: config.tuners,
+        live_tv_windows: config.live_tv_windows,
+        sonarr: config.sonarr,
+        radarr: config.radarr,
+        trakt: config.trakt,
+        tmdb: config.tmdb,
+        xmltv: config.xmltv,
+        calendar_path: config.calendar_path,
+        notify_plan_changes: config.notify_plan_changes,
+        cleanup: config.cleanup,
+        tautulli: config.tautulli,
+        history_retention: config.history_retention,
+        dry_run: config.dry_run,
     };
 
-    let manager = Manager::new(plex, manager_config).await?;
+    let listen_addr = config.listen_addr.filter(|_| primary);
+    #[cfg(feature = "postprocess")]
+    let completed = {
+        let queue_size = config.postprocess.queue_size();
+        let aliases = TitleAliases::new(&config.title_aliases);
+        match PostProcessor::new(config.postprocess, backend.clone(), state.clone(), aliases) {
+            Some(_) if listen_addr.is_none() => {
+                log::warn!("Post-processing needs Plex webhooks, set a listen address");
+                None
+            }
+            Some(processor) => {
+                let (tx, rx) = tokio::sync::mpsc::channel(queue_size);
+                tokio::spawn(postprocess::run(processor, rx));
+                Some(tx)
+            }
+            None => None,
+        }
+    };
+
+    let wake = Arc::new(Notify::new());
+
+    #[cfg(feature = "server")]
+    if let Some(addr) = listen_addr {
+        let app = server::AppState {
+            backend: backend.clone(),
+            state: state.clone(),
+            notifiers: notifiers.clone(),
+            webhook_token: config.webhook_token,
+            api_token: config.api_token,
+            api_docs: config.api_docs,
+            wake: wake.clone(),
+            #[cfg(feature = "postprocess")]
+            completed,
+        };
+        tokio::spawn(async move {
+            if let Err(e) = server::serve(addr, app).await {
+                log::error!("HTTP server stopped: {}", e);
+            }
+        });
+    }
+    #[cfg(not(feature = "server"))]
+    if listen_addr.is_some() {
+        log::warn!("A listen address is set, but this build has no HTTP server");
+    }
+
+    #[cfg(feature = "notifications")]
+    if let (true, Some(Ok(email)), Some(period)) = (
+        primary,
+        Email::new(&config.notify),
+        config.notify.email_digest,
+    ) {
+        tokio::spawn(digest::run(state.clone(), email, period));
+    }
+
+    if primary && config.weekly_report.is_enabled() {
+        tokio::spawn(weekly_report::run(
+            state.clone(),
+            notifiers.clone(),
+            config.weekly_report,
+        ));
+    }
+
+    let manager = Manager::new(backend, wake, state, notifiers, manager_config)?;
     manager.auto_record().await?;
 
     Ok(())
 }
+
+async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let _lock = InstanceLock::acquire(&config.lock_path(), config.wait_for_lock).await?;
+    let lease = Lease::new(&config.lease);
+    if let Some(lease) = &lease {
+        lease.acquire().await?;
+    }
+
+    // Notifications would claim things happened that didn't
+    let notifiers = Arc::new(if config.dry_run {
+        Notifiers::default()
+    } else {
+        Notifiers::new(&config.notify)
+    });
+
+    let mut servers = Vec::new();
+    for name in &config.servers {
+        let server = config.load_server(name)?;
+        log::info!("Also managing Plex server {}", name);
+        servers.push(run_server(server, notifiers.clone(), false));
+    }
+    servers.push(run_server(config, notifiers, true));
+    let servers = try_join_all(servers);
+
+    match &lease {
+        Some(lease) => tokio::select! {
+            result = servers => result?,
+            lost = lease.keep() => return Err(lost.into()),
+        },
+        None => servers.await?,
+    };
+
+    Ok(())
+}
+
+fn build_runtime(config: &Config) -> std::io::Result<Runtime> {
+    let mut builder = match config.runtime {
+        RuntimeFlavor::MultiThread => {
+            let mut builder = runtime::Builder::new_multi_thread();
+            if let Some(threads) = config.worker_threads {
+                builder.worker_threads(threads);
+            }
+            builder
+        }
+        RuntimeFlavor::CurrentThread => runtime::Builder::new_current_thread(),
+    };
+    builder.enable_all().build()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let cli = Cli::parse();
+    let mut config = Config::load()?;
+    config.dry_run |= cli.dry_run;
+
+    log::debug!("{:#?}", config);
+
+    build_runtime(&config)?.block_on(start(cli, config))
+}
+
+async fn start(cli: Cli, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(e) = config.create_data_dir() {
+        log::warn!(
+            "Couldn't create data directory {}: {}",
+            config.data_dir().display(),
+            e
+        );
+    }
+
+    reporting::install_panic_hook();
+    let _reporting = reporting::init(config.sentry_dsn.as_deref());
+
+    let output = cli.output();
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run(config).await,
+        Command::Status => commands::status::run(&config, output),
+        Command::Doctor => commands::doctor::run(&config, output).await,
+        Command::Dump { dir } => commands::dump::run(&config, &dir).await,
+        Command::Channels => commands::channels::run(&config, output).await,
+        Command::Guide { channel, date } => {
+            commands::guide::run(&config, &channel, date, output).await
+        }
+        Command::Record { programme, channel } => {
+            commands::record::run(&config, &programme, channel.as_deref(), output).await
+        }
+        Command::Subscriptions { action } => {
+            commands::subscriptions::run(&config, action, output).await
+        }
+        Command::Plan { hours } => commands::plan::run(config, hours, output).await,
+        Command::Simulate { fixtures } => commands::simulate::run(config, &fixtures, output).await,
+        #[cfg(feature = "tui")]
+        Command::Tui => commands::tui::run(&config).await,
+        Command::State { action } => commands::state::run(&config, action).await,
+        Command::TraktAuth => commands::trakt::run(&config).await,
+    }
+}
@@ -1,238 +1,1662 @@
-use crate::plex::Plex;
-use crate::plex::{
-    self, GridMetadata, PlexError, ProviderDirectoryType, ProvidersMediaProviders, Subscription,
-    SubscriptionPrefs,
+use crate::backend::{BackendError, DvrBackend, Guides};
+use crate::calendar;
+use crate::cleanup::{self, Cleanup, CleanupConfig};
+use crate::clock::{Clock, SystemClock};
+use crate::daily_window::DailyWindow;
+use crate::decision::{self, SkipReason, Verdict};
+use crate::heartbeat::Heartbeat;
+use crate::notify::{Event, Notifiers};
+use crate::plan_diff::PlanDiff;
+use crate::plex::{self, Channel, GridMetadata, GridMetadataType};
+use crate::radarr::{Radarr, RadarrConfig};
+use crate::reporting;
+use crate::retention;
+use crate::sonarr::{Sonarr, SonarrConfig};
+use crate::state::{
+    self, CalendarEntry, ChannelStats, Decision, FailedRecording, State, UpcomingRecording,
 };
-use chrono::{DateTime, Duration, Utc};
-use futures::future::try_join_all;
+use crate::tautulli::{Tautulli, TautulliConfig};
+use crate::title::TitleAliases;
+use crate::tmdb::{Tmdb, TmdbConfig};
+use crate::trakt::{Trakt, TraktConfig};
+use crate::wishlist::Wishlist;
+use crate::xmltv::{Xmltv, XmltvConfig};
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use futures::{stream, FutureExt, StreamExt};
 use itertools::Itertools;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use tokio::time::sleep;
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ManagerError {
-    #[error("Plex error: {0}")]
-    Plex(#[from] plex::PlexError),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
 
-    #[error("Config error: {0}")]
-    Config(String),
+    #[error(transparent)]
+    State(#[from] state::StateError),
+
+    #[error("The DVR's lineup has no channels to record from")]
+    EmptyLineup,
+
+    #[error("Scheduling pass took longer than {0}s, abandoned it")]
+    PassTimeout(u64),
+
+    #[error("None of the channels configured ({configured}) are in the DVR's lineup: {lineup}")]
+    NoChannelsMatched { configured: String, lineup: String },
+
+    #[error("Couldn't schedule {show} on {channel}: {source}")]
+    Scheduling {
+        channel: String,
+        show: String,
+        source: Box<ManagerError>,
+    },
 }
 
 impl ManagerError {
-    fn from_unknown_plex_error(err: &str) -> Self {
-        ManagerError::Plex(PlexError::PlexResponse(err.to_string()))
+    /// Tags describing what was being worked on when the error happened
+    fn event(&self) -> Event {
+        match self {
+            ManagerError::Scheduling {
+                channel,
+                show,
+                source,
+            } => Event::Failed {
+                title: Some(show.clone()),
+                channel: Some(channel.clone()),
+                error: source.to_string(),
+            },
+            _ => Event::failed(self.to_string()),
+        }
+    }
+
+    /// Whether the error is likely to pass, e.g. Plex being restarted or
+    /// throttling, so the pass is worth retrying rather than giving up
+    fn is_transient(&self) -> bool {
+        match self {
+            ManagerError::Backend(BackendError::Plex(_)) | ManagerError::PassTimeout(_) => true,
+            ManagerError::Scheduling { source, .. } => source.is_transient(),
+            _ => false,
+        }
+    }
+
+    fn context(&self) -> Vec<(&str, &str)> {
+        match self {
+            ManagerError::Scheduling { channel, show, .. } => {
+                vec![("channel", channel), ("show", show)]
+            }
+            _ => Vec::new(),
+        }
     }
 }
 
 type Result<T, E = ManagerError> = std::result::Result<T, E>;
 
-const PRE_SCHEDULE_TIME: i64 = 30;
+/// Seconds before an airing starts that it's subscribed to
+pub const PRE_SCHEDULE_TIME: i64 = 30;
+/// Shortest sleep between passes, so a wake time that's already passed can't spin the loop
+const MIN_SLEEP: std::time::Duration = std::time::Duration::from_secs(1);
+const DEFAULT_RESTART_DELAY: u64 = 60;
+/// First wait before retrying a pass that couldn't reach the DVR, short enough
+/// to still catch an airing that was about to be subscribed to
+const FAILED_PASS_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+/// Seconds a pass may take before it's abandoned, most likely as a request
+/// to the DVR has hung
+const DEFAULT_PASS_TIMEOUT: u64 = 3 * 60;
+/// Subscriptions made at once when several airings are due in the same pass
+const SUBSCRIBE_CONCURRENCY: usize = 4;
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+/// Seconds before the first retry of a failed subscription, doubling after each attempt
+const DEFAULT_RETRY_BACKOFF: u64 = 60;
+/// Hours ahead a channel's guide must reach before later days are left unfetched
+const DEFAULT_GUIDE_HORIZON: u64 = 12;
+/// Seconds between passes when nothing is due
+const IDLE_POLL: i64 = 60 * 60;
+/// Seconds between comparing clocks with the DVR
+const SKEW_CHECK_INTERVAL: i64 = 6 * 60 * 60;
+const DEFAULT_POLL_JITTER: u64 = 5 * 60;
+const DEFAULT_GUIDE_FETCH_SPREAD: u64 = 60;
+/// Channels whose guides are requested together when spreading requests out
+const SPREAD_GROUP_SIZE: usize = 5;
+/// Seconds between checks that the channels are enabled in the DVR's mapping
+const CHANNEL_MAP_INTERVAL: i64 = 60 * 60;
+/// Seconds an XMLTV start time may differ from Plex's for the same airing
+const AIRING_TOLERANCE: i64 = 60;
 
 #[derive(Default, Deserialize, Serialize)]
 pub struct ManagerConfig {
-    pub tv_library_id: Option<String>,
-    pub film_library_id: Option<String>,
     pub channels: Vec<String>,
-    pub limit: Option<usize>,
+    /// Only record these shows and films, if given
+    pub titles: Vec<String>,
+    /// Other names titles go by, mapped to the title
+    pub title_aliases: HashMap<String, String>,
+    /// Also record the first episode of any series on the channels, or only
+    /// those if no titles are given
+    pub premieres_only: bool,
+    /// Record anything mentioning one of these phrases, on any channel
+    pub wishlist: Vec<String>,
+    pub heartbeat_url: Option<String>,
+    pub restart_delay: Option<u64>,
+    pub guide_horizon: Option<u64>,
+    pub poll_jitter: Option<u64>,
+    pub guide_fetch_spread: Option<u64>,
+    /// Attempts at scheduling an airing before giving up on it
+    pub retry_attempts: Option<u32>,
+    /// Seconds the clocks may still disagree by once Plex's is allowed for
+    pub clock_skew_allowance: Option<u64>,
+    /// Seconds a pass may take besides spreading out guide requests
+    pub pass_timeout: Option<u64>,
+    /// When Plex is expected to be down each day
+    pub maintenance_window: Option<DailyWindow>,
+    pub retry_backoff: Option<u64>,
+    /// Re-enable configured channels the DVR's channel mapping has disabled
+    pub enable_channels: bool,
+    /// Most recordings to have going at once, to leave tuners free
+    pub max_concurrent_recordings: Option<u32>,
+    /// The DVR's tuners, one of which is left free during `live_tv_windows`
+    pub tuners: Option<u32>,
+    /// When to keep a tuner free for watching live TV
+    pub live_tv_windows: Vec<DailyWindow>,
+    pub sonarr: SonarrConfig,
+    pub radarr: RadarrConfig,
+    pub trakt: TraktConfig,
+    pub tmdb: TmdbConfig,
+    pub xmltv: XmltvConfig,
+    /// Where to write the iCalendar feed after each pass
+    pub calendar_path: Option<String>,
+    /// Send a notification listing what changed in the plan after each pass
+    pub notify_plan_changes: bool,
+    /// When recordings are deleted, by who's watched them and a size limit
+    pub cleanup: CleanupConfig,
+    pub tautulli: TautulliConfig,
+    /// Days of history to keep, 0 to keep everything
+    pub history_retention: Option<u64>,
+    /// Decide what to delete, but only log it
+    pub dry_run: bool,
 }
 
 pub struct Manager {
-    plex: Plex,
-    tv_library_id: String,
-    film_library_id: String,
+    backend: Arc<dyn DvrBackend>,
+    clock: Arc<dyn Clock>,
+    wake: Arc<Notify>,
+    state: Arc<State>,
+    notifiers: Arc<Notifiers>,
     channels: Vec<String>,
-    limit: Option<usize>
+    titles: HashSet<String>,
+    aliases: TitleAliases,
+    premieres_only: bool,
+    wishlist: Wishlist,
+    trakt: Option<Trakt>,
+    tmdb: Option<Tmdb>,
+    xmltv: Option<Xmltv>,
+    calendar_path: Option<String>,
+    notify_plan_changes: bool,
+    /// Started alongside the passes, so taken once they start
+    cleanup: Mutex<Option<Cleanup>>,
+    /// Days of history to keep, 0 to keep everything
+    history_retention: u64,
+    heartbeat: Option<Heartbeat>,
+    sonarr: Option<Sonarr>,
+    radarr: Option<Radarr>,
+    restart_delay: std::time::Duration,
+    /// Seconds ahead the guide should cover
+    guide_horizon: i64,
+    /// Most seconds added to idle wake-ups, so they drift away from Plex's own guide refresh
+    poll_jitter: i64,
+    guide_fetch_spread: std::time::Duration,
+    /// Longest a pass may take, spreading out guide requests aside
+    pass_timeout: std::time::Duration,
+    maintenance_window: Option<DailyWindow>,
+    /// Set when the next pass was scheduled with nothing due, so has time to spare
+    idle_pass: AtomicBool,
+    retry_attempts: i64,
+    retry_backoff: i64,
+    max_concurrent_recordings: Option<usize>,
+    /// Recordings allowed at once during the live TV windows
+    live_tv_limit: Option<usize>,
+    live_tv_windows: Vec<DailyWindow>,
+    /// Seconds airings are subscribed to earlier, and still once they've
+    /// started, in case the clocks disagree by more than was measured
+    skew_allowance: i64,
+    /// How far the DVR's clock is ahead of ours, and when that was checked
+    skew: Mutex<(Option<DateTime<Utc>>, Duration)>,
+    /// Passes in a row each channel's guide has failed to fetch on, so they're
+    /// only notified about once and retried less often the longer they fail
+    failing_guides: Mutex<HashMap<String, u32>>,
+    enable_channels: bool,
+    /// When the channel mapping was last checked, and the channels that
+    /// couldn't be enabled then, which have been alerted about
+    channel_map_checked: Mutex<(Option<DateTime<Utc>>, HashSet<String>)>,
+    /// Decisions made in the pass so far, saved together as it ends
+    decisions: Mutex<Vec<Decision>>,
+}
+
+/// Totals for a pass, logged as one line of `key=value` pairs so it's easy
+/// to spot, and to parse, in the container's logs
+#[derive(Debug, PartialEq, Eq)]
+pub struct PassSummary {
+    /// Channels in the lineup, including ones whose guide couldn't be fetched
+    pub channels: usize,
+    pub airings: i64,
+    pub scheduled: i64,
+    pub skipped: i64,
+    pub failed: i64,
+    pub next_wake: DateTime<Utc>,
+}
+
+impl PassSummary {
+    pub fn new(stats: &[ChannelStats], failed_guides: usize, next_wake: DateTime<Utc>) -> Self {
+        PassSummary {
+            channels: stats.len() + failed_guides,
+            airings: stats.iter().map(|s| s.seen).sum(),
+            scheduled: stats.iter().map(|s| s.scheduled).sum(),
+            skipped: stats.iter().map(|s| s.skipped).sum(),
+            failed: stats.iter().map(|s| s.failed).sum(),
+            next_wake,
+        }
+    }
+}
+
+impl std::fmt::Display for PassSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "channels={} airings={} scheduled={} skipped={} failed={} next_wake={}",
+            self.channels,
+            self.airings,
+            self.scheduled,
+            self.skipped,
+            self.failed,
+            self.next_wake
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        )
+    }
+}
+
+/// What became of an airing that was due in a pass
+enum DueOutcome {
+    /// Left alone, having been logged why. Vetoed airings count as skipped.
+    Passed {
+        vetoed: bool,
+    },
+    Subscribed,
+    Failed(BackendError),
+}
+
+/// A recording scheduled by the manager that hasn't finished
+struct Booking {
+    channel: String,
+    title: String,
+    begins_at: i64,
+    ends_at: i64,
+}
+
+impl Booking {
+    fn overlaps(&self, begins_at: i64, ends_at: i64) -> bool {
+        self.begins_at < ends_at && begins_at < self.ends_at
+    }
+}
+
+/// Recordings scheduled by the manager that haven't finished, which airings
+/// due in a pass are booked alongside so no more than `limit` record at once,
+/// or `live_tv_limit` during the live TV windows
+struct Bookings {
+    limit: usize,
+    live_tv_limit: usize,
+    live_tv_windows: Vec<DailyWindow>,
+    booked: std::sync::Mutex<Vec<Booking>>,
+}
+
+impl Bookings {
+    /// Books an airing unless that would make more than the limit record at
+    /// once at any point during it, returning whether it was newly booked, or
+    /// why there's no room
+    fn book(
+        &self,
+        channel: &str,
+        title: &str,
+        begins_at: i64,
+        ends_at: i64,
+    ) -> Result<bool, SkipReason> {
+        let mut booked = self.booked.lock().unwrap();
+        if booked
+            .iter()
+            .any(|b| b.channel == channel && b.begins_at == begins_at)
+        {
+            return Ok(false);
+        }
+        let overlapping: Vec<_> = booked
+            .iter()
+            .filter(|b| b.overlaps(begins_at, ends_at))
+            .collect();
+        // The most recording at once is reached as one of them starts
+        let busiest = std::iter::once(begins_at)
+            .chain(
+                overlapping
+                    .iter()
+                    .map(|b| b.begins_at)
+                    .filter(|b| *b > begins_at),
+            )
+            .map(|at| {
+                overlapping
+                    .iter()
+                    .filter(|b| b.begins_at <= at && at < b.ends_at)
+                    .count()
+            })
+            .max()
+            .unwrap_or(0);
+        if busiest >= self.limit {
+            return Err(SkipReason::RecordingLimit);
+        }
+        if busiest >= self.live_tv_limit && self.during_live_tv(begins_at, ends_at) {
+            return Err(SkipReason::LiveTv);
+        }
+        booked.push(Booking {
+            channel: channel.to_string(),
+            title: title.to_string(),
+            begins_at,
+            ends_at,
+        });
+        Ok(true)
+    }
+
+    /// Titles of what's booked to record at some point during an airing
+    fn alongside(&self, begins_at: i64, ends_at: i64) -> Vec<String> {
+        self.booked
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|b| b.overlaps(begins_at, ends_at))
+            .map(|b| b.title.clone())
+            .collect()
+    }
+
+    fn during_live_tv(&self, begins_at: i64, ends_at: i64) -> bool {
+        let (Some(begins_at), Some(ends_at)) = (
+            DateTime::from_timestamp(begins_at, 0),
+            DateTime::from_timestamp(ends_at, 0),
+        ) else {
+            return false;
+        };
+        self.live_tv_windows
+            .iter()
+            .any(|w| w.overlaps(begins_at, ends_at, &Local))
+    }
+
+    fn cancel(&self, channel: &str, begins_at: i64) {
+        self.booked
+            .lock()
+            .unwrap()
+            .retain(|b| !(b.channel == channel && b.begins_at == begins_at));
+    }
+}
+
+/// Whether an airing starting at `begins_at` should be subscribed to at `now`
+pub fn is_due(begins_at: i64, now: i64) -> bool {
+    begins_at - now <= PRE_SCHEDULE_TIME
+}
+
+/// How long to sleep for an airing starting at `next_time` to be due on waking
+pub fn sleep_duration(next_time: DateTime<Utc>, now: DateTime<Utc>) -> std::time::Duration {
+    (next_time - now - Duration::seconds(PRE_SCHEDULE_TIME))
+        .to_std()
+        .unwrap_or_default()
+        .max(MIN_SLEEP)
+}
+
+/// The guide days a pass looks through, yesterday's to tomorrow's, in the
+/// grid's date format. Plex lists airings by the server's calendar day, so
+/// the days are those of `tz`, stepped by date rather than by 24 hours to
+/// stay whole across daylight saving changes.
+pub fn guide_dates<Tz: TimeZone>(now: DateTime<Utc>, tz: &Tz) -> [String; 3] {
+    let today = now.with_timezone(tz).date_naive();
+    [today - Duration::days(1), today, today + Duration::days(1)]
+        .map(|d| d.format(plex::GRID_DATE_FORMAT).to_string())
+}
+
+fn calendar_entry(channel: &Channel, show: &GridMetadata, scheduled: bool) -> CalendarEntry {
+    let media = show.media.first();
+    CalendarEntry {
+        channel: channel.id.clone(),
+        channel_title: media.map_or_else(String::new, |m| m.channel_title.clone()),
+        title: show.show_title(),
+        begins_at: show.begins_at_ts(),
+        ends_at: media.map_or(0, |m| m.ends_at),
+        scheduled,
+    }
 }
 
 impl Manager {
-    pub async fn new(plex: Plex, config: ManagerConfig) -> Result<Self> {
-        let providers = plex.get_providers().await?;
-
-        let get_library_id = |library_type, default: Option<String>| {
-            let show_dirs = providers.get_dirs_of_type(library_type)?;
-            let mut show_dir_ids = show_dirs.iter().map(|d| d.id.clone().unwrap());
-            let id = default
-                .and_then(|id| show_dir_ids.find(|did| did == &id))
-                .or_else(|| show_dir_ids.next());
-            Ok::<_, ManagerError>(id)
-        };
-
-        let tv_library_id = get_library_id(ProviderDirectoryType::Show, config.tv_library_id)?
-            .ok_or_else(|| ManagerError::Config("No matching TV Show library found".into()))?;
-        let film_library_id = get_library_id(ProviderDirectoryType::Movie, config.film_library_id)?
-            .ok_or_else(|| ManagerError::Config("No matching Film library found".into()))?;
-
-        log::debug!(
-            "Using tv library {}, film library {}",
-            tv_library_id,
-            film_library_id
+    pub fn new(
+        backend: Arc<dyn DvrBackend>,
+        wake: Arc<Notify>,
+        state: Arc<State>,
+        notifiers: Arc<Notifiers>,
+        config: ManagerConfig,
+    ) -> Result<Self> {
+        let aliases = TitleAliases::new(&config.title_aliases);
+        let live_tv_limit = match (config.tuners, config.live_tv_windows.is_empty()) {
+            (_, true) => None,
+            (Some(tuners), false) => Some(tuners.saturating_sub(1) as usize),
+            (None, false) => {
+                log::warn!("Can't keep a tuner free for live TV without knowing how many tuners there are, set tuners");
+                None
+            }
+        };
+        let cleanup = Cleanup::new(
+            config.cleanup,
+            Tautulli::new(config.tautulli),
+            backend.clone(),
+            state.clone(),
+            notifiers.clone(),
+            config.dry_run,
         );
-
         Ok(Self {
-            plex,
-            tv_library_id,
-            film_library_id,
+            backend,
+            clock: Arc::new(SystemClock),
+            wake,
+            trakt: Trakt::new(&config.trakt, state.clone()),
+            tmdb: Tmdb::new(&config.tmdb, state.clone()),
+            xmltv: Xmltv::new(config.xmltv),
+            calendar_path: config.calendar_path,
+            notify_plan_changes: config.notify_plan_changes,
+            state,
+            notifiers,
             channels: config.channels,
-            limit: config.limit,
+            titles: config.titles.iter().map(|t| aliases.key(t)).collect(),
+            aliases,
+            premieres_only: config.premieres_only,
+            wishlist: Wishlist::new(&config.wishlist),
+            cleanup: Mutex::new(cleanup),
+            history_retention: config
+                .history_retention
+                .unwrap_or(retention::DEFAULT_RETENTION_DAYS),
+            heartbeat: config.heartbeat_url.map(Heartbeat::new),
+            sonarr: Sonarr::new(config.sonarr),
+            radarr: Radarr::new(config.radarr),
+            restart_delay: std::time::Duration::from_secs(
+                config.restart_delay.unwrap_or(DEFAULT_RESTART_DELAY),
+            ),
+            guide_horizon: config.guide_horizon.unwrap_or(DEFAULT_GUIDE_HORIZON) as i64 * 60 * 60,
+            poll_jitter: config.poll_jitter.unwrap_or(DEFAULT_POLL_JITTER) as i64,
+            guide_fetch_spread: std::time::Duration::from_secs(
+                config
+                    .guide_fetch_spread
+                    .unwrap_or(DEFAULT_GUIDE_FETCH_SPREAD),
+            ),
+            pass_timeout: std::time::Duration::from_secs(
+                config.pass_timeout.unwrap_or(DEFAULT_PASS_TIMEOUT),
+            ),
+            maintenance_window: config.maintenance_window,
+            idle_pass: AtomicBool::new(false),
+            retry_attempts: config.retry_attempts.unwrap_or(DEFAULT_RETRY_ATTEMPTS) as i64,
+            retry_backoff: config.retry_backoff.unwrap_or(DEFAULT_RETRY_BACKOFF) as i64,
+            max_concurrent_recordings: config.max_concurrent_recordings.map(|m| m as usize),
+            live_tv_limit,
+            live_tv_windows: config.live_tv_windows,
+            skew_allowance: config.clock_skew_allowance.unwrap_or(0) as i64,
+            skew: Mutex::new((None, Duration::zero())),
+            failing_guides: Mutex::default(),
+            enable_channels: config.enable_channels,
+            channel_map_checked: Mutex::default(),
+            decisions: Mutex::default(),
         })
     }
 
-    async fn schedule_recording(&self, metadata: GridMetadata) -> Result<()> {
-        let templates = self.plex.get_subscription_template(&metadata.guid).await?;
+    /// Runs passes by another clock, e.g. to simulate them at other times.
+    /// The state goes by it too, so what's kept is timed like the passes.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        self.state.set_clock(clock.clone());
+        Manager { clock, ..self }
+    }
 
-        println!("{:#?}", templates);
+    /// Normalized titles to restrict recording to, if any have been given
+    async fn allowlist(&self) -> Option<HashSet<String>> {
+        let trakt = match &self.trakt {
+            Some(trakt) => trakt.watchlist().await,
+            None if self.titles.is_empty() => return None,
+            None => HashSet::new(),
+        };
+        let trakt = trakt.iter().map(|t| self.aliases.key(t));
+        Some(self.titles.iter().cloned().chain(trakt).collect())
+    }
 
-        let media = metadata
-            .media
-            .first()
-            .ok_or_else(|| ManagerError::from_unknown_plex_error("Recording has no Media"))?;
+    fn skip_reason(
+        &self,
+        show: &GridMetadata,
+        allowlist: Option<&HashSet<String>>,
+    ) -> Option<SkipReason> {
+        if show.begins_at().is_none() {
+            log::warn!(
+                "{} starts at {}, which isn't a valid time, skipping it",
+                show.show_title(),
+                show.begins_at_ts()
+            );
+            return Some(SkipReason::InvalidStartTime);
+        }
 
-        let media_template = templates.first().ok_or_else(|| {
-            ManagerError::from_unknown_plex_error("Subscription template has no media")
-        })?;
-        let hints = &media_template.parameters.hints;
-        let params = &media_template.parameters.params;
-
-        let target_library = match media_template.r#type {
-            1 => &self.film_library_id,
-            _ => &self.tv_library_id,
-        };
-
-        let sub = Subscription {
-            prefs: SubscriptionPrefs {
-                min_video_quality: media_template.setting_default("minVideoQuality")?,
-                replace_lower_quality: media_template.setting_default("replaceLowerQuality")?,
-                record_partials: media_template.setting_default("recordPartials")?,
-                start_offset_minutes: 0,
-                end_offset_minutes: 4,
-                lineup_channel: media.channel_identifier.clone(),
-                start_timeslot: media.begins_at,
-                comskip_enabled: media_template.setting_default("comskipEnabled")?,
-                comskip_method: media_template.setting_default("comskipMethod")?,
-                one_shot: "true".into(),
-                remote_media: media_template.setting_default("remoteMedia")?,
-            },
-            hints: hints.clone(),
-            params: params.clone(),
-            target_library_section_id: target_library.clone(),
-            target_section_location_id: "".into(),
-            include_grabs: 1,
-            r#type: media_template.r#type.to_string(),
-        };
+        if show.is_subscribed() {
+            return Some(SkipReason::AlreadySubscribed);
+        }
 
-        self.plex.create_subscription(&sub).await?;
+        // Wishes are granted on any channel, whatever the titles
+        if self.wishlist.find(show).is_some() {
+            return None;
+        }
 
-        Ok(())
+        let selected = self.channels.is_empty()
+            || show
+                .media
+                .first()
+                .map(|m| self.channels.contains(&m.channel_identifier))
+                .unwrap_or(false);
+        if !selected {
+            return Some(SkipReason::ChannelNotSelected);
+        }
+
+        let allowed =
+            allowlist.map(|titles| titles.contains(&self.aliases.key(&show.show_title())));
+        match allowed {
+            Some(true) => {}
+            _ if self.premieres_only && !show.is_series_premiere() => {
+                return Some(SkipReason::NotAPremiere)
+            }
+            Some(false) if !self.premieres_only => return Some(SkipReason::NotInAllowlist),
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Somewhere to book airings due in a pass, if there's any limit on how
+    /// many can record at once
+    fn bookings(&self, now: i64) -> Result<Option<Bookings>> {
+        if self.max_concurrent_recordings.is_none() && self.live_tv_limit.is_none() {
+            return Ok(None);
+        }
+        let booked = self
+            .state
+            .calendar()?
+            .into_iter()
+            .filter(|e| e.scheduled && e.ends_at > now)
+            .map(|e| Booking {
+                channel: e.channel,
+                title: e.title,
+                begins_at: e.begins_at,
+                ends_at: e.ends_at,
+            })
+            .collect();
+        Ok(Some(Bookings {
+            limit: self.max_concurrent_recordings.unwrap_or(usize::MAX),
+            live_tv_limit: self.live_tv_limit.unwrap_or(usize::MAX),
+            live_tv_windows: self.live_tv_windows.clone(),
+            booked: std::sync::Mutex::new(booked),
+        }))
+    }
+
+    /// Why each airing would be skipped, or `None` if it would be recorded.
+    /// Only the rules are applied, not the clock or external services, so a
+    /// saved guide can be replayed offline.
+    pub async fn plan(&self, shows: &[GridMetadata]) -> Vec<Option<SkipReason>> {
+        let allowlist = self.allowlist().await;
+        shows
+            .iter()
+            .map(|s| self.skip_reason(s, allowlist.as_ref()))
+            .collect()
+    }
+
+    /// Like `plan`, but also checks external services for airings the rules would record
+    pub async fn preview(&self, shows: &[GridMetadata]) -> Vec<Option<SkipReason>> {
+        let mut decisions = self.plan(shows).await;
+        for (show, decision) in shows.iter().zip(decisions.iter_mut()) {
+            if decision.is_none() {
+                *decision = self.veto(show).await;
+            }
+        }
+        decisions
+    }
+
+    /// Checks external services for reasons not to record an imminent airing.
+    /// These are only consulted at scheduling time since each check is a request.
+    async fn veto(&self, show: &GridMetadata) -> Option<SkipReason> {
+        let is_film = matches!(show.r#type, GridMetadataType::Movie);
+        if let Some(tmdb) = &self.tmdb {
+            match tmdb.below_threshold(show).await {
+                Ok(true) => return Some(SkipReason::LowRating),
+                Ok(false) => (),
+                Err(e) => log::warn!("Couldn't check TMDB rating, recording anyway: {}", e),
+            }
+        }
+        if let (Some(sonarr), false) = (&self.sonarr, is_film) {
+            match sonarr.covers(show, &self.aliases).await {
+                Ok(true) => return Some(SkipReason::Sonarr),
+                Ok(false) => (),
+                Err(e) => log::warn!("Couldn't check Sonarr, recording anyway: {}", e),
+            }
+        }
+        if let (Some(radarr), true) = (&self.radarr, is_film) {
+            match radarr.covers(show, &self.aliases).await {
+                Ok(true) => return Some(SkipReason::Radarr),
+                Ok(false) => (),
+                Err(e) => log::warn!("Couldn't check Radarr, recording anyway: {}", e),
+            }
+        }
+        None
+    }
+
+    /// Finds the DVR's own airing for one picked from the XMLTV guide,
+    /// which has the GUID needed to subscribe
+    async fn resolve_airing(
+        &self,
+        channel: &Channel,
+        show: &GridMetadata,
+    ) -> Result<Option<GridMetadata>> {
+        let begins_at = show.begins_at_ts();
+        let date = show
+            .begins_at()
+            .map(|d| {
+                d.with_timezone(&Local)
+                    .format(plex::GRID_DATE_FORMAT)
+                    .to_string()
+            })
+            .unwrap_or_default();
+        let airing = self
+            .backend
+            .guide(channel, &date)
+            .await?
+            .into_iter()
+            .find(|a| (a.begins_at_ts() - begins_at).abs() <= AIRING_TOLERANCE);
+        Ok(airing)
     }
 
     /// Schedule next recording if close to start time.
     /// If a recording was scheduled, returns time of following recording.
     /// If recording was not scheduled (too far away), returns time of next recording.
     pub async fn schedule_next_recordings(&self) -> Result<DateTime<Utc>> {
-        const DATE_FORMAT: &str = "%Y-%m-%d";
+        // Left by a pass that failed or was abandoned, and not decided again yet
+        self.decisions.lock().unwrap().clear();
+        self.retry_failures().await?;
 
-        let channels = self.plex.get_channels().await?;
+        let channels = self.backend.channels().await?;
+        let allowlist = self.allowlist().await;
+        let allowlist = allowlist.as_ref();
 
-        let now = Utc::now();
+        // Airings are timed by the DVR's clock, so passes go by it too
+        let skew = self.clock_skew().await;
+        let now = self.clock.now() + skew;
         let unix_now = now.timestamp();
-        let yesterday = now - Duration::days(1);
-        let tomorrow = now + Duration::days(1);
 
-        let all_requests = channels.into_iter().map(|c| {
-            let day_requests: Vec<_> = [yesterday, now, tomorrow]
-                .iter()
-                .map(|d| {
-                    // Get shows and delete ones from the past
-                    let date = d.clone().format(DATE_FORMAT).to_string();
-                    let id = c.id.clone();
-                    async move {
-                        let shows =
-                            self.plex
-                                .get_grid(&id, &date)
-                                .await?
-                                .map_or_else(Vec::new, |s| {
-                                    s.into_iter()
-                                        .skip_while(|s| s.begins_at_ts() < unix_now)
-                                        .collect()
-                                });
-                        Ok::<Vec<_>, ManagerError>(shows)
+        let guide = match &self.xmltv {
+            Some(xmltv) => match xmltv.guide().await {
+                Ok(guide) => Some(guide),
+                Err(e) => {
+                    log::warn!("Couldn't read XMLTV guide, using Plex's: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let guide = guide.as_deref();
+
+        let from_guide: Vec<_> = channels
+            .iter()
+            .map(|c| guide.and_then(|g| g.airings(c, unix_now)))
+            .collect();
+
+        // Channels the XMLTV guide doesn't cover are fetched from the DVR a day
+        // at a time, stopping once a channel's guide reaches the horizon
+        let horizon = unix_now + self.guide_horizon;
+        let spread = if self.idle_pass.swap(false, Ordering::Relaxed) {
+            self.guide_fetch_spread
+        } else {
+            std::time::Duration::ZERO
+        };
+        let mut pending: Vec<Channel> = channels
+            .iter()
+            .zip(&from_guide)
+            .filter(|(_, shows)| shows.is_none())
+            .map(|(c, _)| c.clone())
+            .collect();
+        let fetched = pending.clone();
+        let mut grids: HashMap<String, Vec<GridMetadata>> = HashMap::new();
+        let mut failed = HashMap::new();
+        for date in guide_dates(now, &Local) {
+            if pending.is_empty() {
+                break;
+            }
+            let mut day = self.fetch_guides(&pending, &date, spread).await;
+            pending.retain(|c| {
+                // A channel missing any of its days is left out of the pass
+                // rather than planned from part of its guide
+                let shows = match day.remove(&c.id).unwrap_or(Ok(Vec::new())) {
+                    Ok(shows) => shows,
+                    Err(e) => {
+                        grids.remove(&c.id);
+                        failed.insert(c.id.clone(), e);
+                        return false;
                     }
-                })
-                .collect();
+                };
+                let covered = shows
+                    .iter()
+                    .filter_map(|s| s.media.first())
+                    .any(|m| m.ends_at >= horizon);
+                // Get shows and delete ones from the past, wherever they are
+                // in the grid as Plex doesn't promise an order
+                grids
+                    .entry(c.id.clone())
+                    .or_default()
+                    .extend(shows.into_iter().filter(|s| {
+                        let started = s.begins_at_ts() < unix_now - self.skew_allowance;
+                        if started {
+                            decision::log_skip(s, SkipReason::AlreadyStarted);
+                        }
+                        !started
+                    }));
+                !covered
+            });
+        }
 
-            async move {
-                let next_show = try_join_all(day_requests)
-                    .await?
+        // Nothing could be fetched, most likely as the DVR is down, so the
+        // pass fails and is retried
+        if failed.len() == fetched.len() {
+            if let Some((_, e)) = failed.drain().next() {
+                return Err(e.into());
+            }
+        }
+        let guide_retry = self.guides_failed(&fetched, &failed).await;
+
+        let next_shows: Vec<_> = channels
+            .into_iter()
+            .zip(from_guide)
+            .filter(|(c, _)| !failed.contains_key(&c.id))
+            .map(|(c, from_guide)| {
+                let is_from_guide = from_guide.is_some();
+                let shows: Vec<_> = match from_guide {
+                    Some(shows) => shows,
+                    // Grids for neighbouring days overlap around midnight
+                    None => grids
+                        .remove(&c.id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .unique_by(|s| (s.guid.clone(), s.begins_at_ts()))
+                        .collect(),
+                };
+
+                let mut stats = ChannelStats {
+                    channel: c.id.clone(),
+                    channel_title: shows
+                        .iter()
+                        .find_map(|s| s.media.first())
+                        .map_or_else(|| c.id.clone(), |m| m.channel_title.clone()),
+                    seen: shows.len() as i64,
+                    ..Default::default()
+                };
+
+                let candidates = shows
                     .into_iter()
-                    .flatten()
-                    // remove already set to record
-                    .filter(|s| {
-                        s.subscription_id.is_none() && s.grandparent_subscription_id.is_none()
-                    })
-                    // remove not in channel list
-                    .filter(|s| {
-                        self.channels.is_empty()
-                            || s.media
-                                .first()
-                                .map(|m| self.channels.contains(&m.channel_identifier))
-                                .unwrap_or(false)
+                    .filter(|s| match self.skip_reason(s, allowlist) {
+                        Some(reason) => {
+                            self.skip(s, reason);
+                            if matches!(
+                                reason,
+                                SkipReason::ChannelNotSelected
+                                    | SkipReason::NotInAllowlist
+                                    | SkipReason::NotAPremiere
+                            ) {
+                                stats.skipped += 1;
+                            }
+                            false
+                        }
+                        None => true,
                     })
                     .sorted_by_key(|s| s.begins_at_ts())
-                    .next();
-                Ok::<_, ManagerError>((c, next_show))
-            }
-        });
-
-        let next_shows = try_join_all(all_requests).await?;
+                    .collect::<Vec<_>>();
+                (c, candidates, stats, is_from_guide)
+            })
+            .collect();
 
         let mut next_show: Option<GridMetadata> = None;
-        for (_channel, show) in next_shows {
-            let unix_now = Utc::now().timestamp();
-            if let Some(show) = show {
+        let mut upcoming = Vec::new();
+        let mut calendar = Vec::new();
+        let mut channel_stats = Vec::new();
+        let mut due = Vec::new();
+        // Fetching guides may have taken a while
+        let unix_now = (self.clock.now() + skew).timestamp();
+        for (channel, candidates, stats, is_from_guide) in next_shows {
+            let mut candidates = candidates.into_iter();
+            for show in candidates.by_ref() {
                 let begins_at = show.begins_at_ts();
-                if (begins_at - unix_now) < PRE_SCHEDULE_TIME {
-                    log::info!("Beginning automatic recording of {}", show.show_title());
-                    self.schedule_recording(show).await?;
-                } else if let Some(prev_next) = &next_show {
-                    if begins_at < prev_next.begins_at_ts() {
+                if !is_due(begins_at, unix_now + self.skew_allowance) {
+                    calendar.push(calendar_entry(&channel, &show, false));
+                    upcoming.push(UpcomingRecording {
+                        channel: channel.id.clone(),
+                        channel_title: show
+                            .media
+                            .first()
+                            .map_or_else(String::new, |m| m.channel_title.clone()),
+                        title: show.show_title(),
+                        begins_at,
+                    });
+
+                    if next_show
+                        .as_ref()
+                        .is_none_or(|prev_next| begins_at < prev_next.begins_at_ts())
+                    {
                         next_show = Some(show);
                     }
-                } else {
-                    next_show = Some(show);
+                    break;
                 }
+
+                due.push((channel_stats.len(), channel.clone(), show, is_from_guide));
+            }
+            candidates.for_each(|s| {
+                self.skip(&s, SkipReason::LaterAiring);
+                calendar.push(calendar_entry(&channel, &s, false));
+            });
+            channel_stats.push(stats);
+        }
+
+        // Work through everything due together, soonest first across every
+        // channel, so airings about to start aren't kept waiting on lookups
+        // for ones on channels earlier in the lineup, or cut off if the pass
+        // runs out of time
+        due.sort_by_key(|(_, _, show, _)| show.begins_at_ts());
+        let bookings = self.bookings(unix_now)?;
+        let bookings = bookings.as_ref();
+        let outcomes: Vec<_> = stream::iter(due)
+            .map(|(i, channel, show, is_from_guide)| async move {
+                let airing = format!("{} at {}", show.show_title(), show.begins_at_ts());
+                let outcome = self
+                    .record_due(&channel, show, is_from_guide, allowlist, bookings)
+                    .await;
+                (i, channel, airing, outcome)
+            })
+            .buffer_unordered(SUBSCRIBE_CONCURRENCY)
+            .collect()
+            .await;
+        for (i, channel, airing, outcome) in outcomes {
+            let stats = &mut channel_stats[i];
+            // One airing going wrong mustn't cost the rest of the pass its results
+            let (show, outcome) = match outcome {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    log::warn!("Couldn't schedule {} on {}: {}", airing, channel.id, e);
+                    stats.failed += 1;
+                    continue;
+                }
+            };
+            match outcome {
+                DueOutcome::Passed { vetoed } => stats.skipped += vetoed as i64,
+                DueOutcome::Subscribed => {
+                    stats.scheduled += 1;
+                    self.decide(&show, Verdict::Scheduled);
+                    calendar.push(calendar_entry(&channel, &show, true));
+                    self.emit(Event::scheduled(&show)).await;
+                }
+                DueOutcome::Failed(e) => {
+                    stats.failed += 1;
+                    self.journal_failure(&channel.id, &stats.channel_title, &show, 0, e)
+                        .await?;
+                }
+            }
+        }
+
+        self.state.add_channel_stats(&channel_stats)?;
+        let decisions = std::mem::take(&mut *self.decisions.lock().unwrap());
+        self.state.record_decisions(&decisions)?;
+        self.state.set_upcoming(&upcoming)?;
+        let previous = self.state.calendar()?;
+        self.state.set_calendar(&calendar)?;
+        let current = self.state.calendar()?;
+        if let Some(path) = &self.calendar_path {
+            let ics = calendar::ics(&current, self.clock.now());
+            if let Err(e) = tokio::fs::write(path, ics).await {
+                log::warn!("Couldn't write calendar to {}: {}", path, e);
             }
         }
+        self.log_plan_changes(previous, current, unix_now).await;
 
-        if let Some(show) = &next_show {
+        // Candidates all have valid start times, but a garbled one mustn't panic
+        let next_start = next_show.as_ref().and_then(|show| {
+            let begins_at = show.begins_at()?;
             log::info!(
                 "Next show is {} due to start at {}",
                 show.show_title(),
-                show.begins_at().unwrap()
+                begins_at
+            );
+            Some(begins_at)
+        });
+
+        let next_time = match next_start {
+            Some(begins_at) => begins_at - skew,
+            None => {
+                let jitter = rand::thread_rng().gen_range(0..=self.poll_jitter);
+                self.clock.now() + Duration::seconds(IDLE_POLL + jitter)
+            }
+        };
+        // Wake in time for the next retry, allowing for the pre-schedule margin
+        let next_retry = self
+            .state
+            .pending_failures()?
+            .first()
+            .and_then(|f| f.retry_at)
+            .and_then(|at| DateTime::from_timestamp(at + PRE_SCHEDULE_TIME, 0));
+        // Channels left out try again soon, in case they've an airing about to start
+        let next_retry = guide_retry
+            .and_then(|delay| Duration::from_std(delay).ok())
+            .map(|delay| self.clock.now() + delay + Duration::seconds(PRE_SCHEDULE_TIME))
+            .into_iter()
+            .chain(next_retry)
+            .min();
+        let idle = next_start.is_none() && next_retry.is_none_or(|at| at >= next_time);
+        self.idle_pass.store(idle, Ordering::Relaxed);
+        let next_wake = next_retry.map_or(next_time, |at| at.min(next_time));
+        let summary = PassSummary::new(&channel_stats, failed.len(), next_wake);
+        log::info!("Pass finished: {}", summary);
+        Ok(next_wake)
+    }
+
+    /// How far the DVR's clock is ahead of ours, compared every few hours and
+    /// left as it was when the DVR can't say
+    async fn clock_skew(&self) -> Duration {
+        let now = self.clock.now();
+        let (checked_at, previous) = *self.skew.lock().unwrap();
+        if checked_at.is_some_and(|at| now - at < Duration::seconds(SKEW_CHECK_INTERVAL)) {
+            return previous;
+        }
+        let skew = match self.backend.clock_skew().await {
+            Ok(skew) => skew.unwrap_or(previous),
+            Err(e) => {
+                log::debug!("Couldn't compare clocks with the DVR: {}", e);
+                previous
+            }
+        };
+        if skew != previous {
+            log::warn!(
+                "The DVR's clock is {}s {} this machine's, going by the DVR's",
+                skew.num_seconds().abs(),
+                if skew > Duration::zero() {
+                    "ahead of"
+                } else {
+                    "behind"
+                }
+            );
+        }
+        *self.skew.lock().unwrap() = (Some(now), skew);
+        skew
+    }
+
+    /// Fetches guides a few channels at a time, spaced evenly over `spread`, so
+    /// a refresh with nothing due doesn't hit Plex with every channel at once
+    async fn fetch_guides(
+        &self,
+        channels: &[Channel],
+        date: &str,
+        spread: std::time::Duration,
+    ) -> Guides {
+        if spread.is_zero() || channels.len() <= SPREAD_GROUP_SIZE {
+            return self.backend.guides(channels, date).await;
+        }
+        let groups = channels.chunks(SPREAD_GROUP_SIZE);
+        let gap = spread / groups.len() as u32;
+        let mut guides = Guides::new();
+        for (i, group) in groups.enumerate() {
+            if i > 0 {
+                self.clock.sleep(gap).await;
+            }
+            guides.extend(self.backend.guides(group, date).await);
+        }
+        guides
+    }
+
+    /// Logs channels whose guide couldn't be fetched, notifying only when a
+    /// channel starts failing so a broken grid doesn't alert on every pass.
+    /// Returns how soon to try them again, backing off while they keep failing.
+    async fn guides_failed(
+        &self,
+        fetched: &[Channel],
+        failed: &HashMap<String, BackendError>,
+    ) -> Option<std::time::Duration> {
+        let mut retry = None;
+        for channel in fetched {
+            let name = channel.title.as_deref().unwrap_or(&channel.id);
+            let Some(e) = failed.get(&channel.id) else {
+                if self
+                    .failing_guides
+                    .lock()
+                    .unwrap()
+                    .remove(&channel.id)
+                    .is_some()
+                {
+                    log::info!("Guide for {} can be fetched again", name);
+                }
+                continue;
+            };
+            log::warn!("Couldn't fetch the guide for {}, skipping it: {}", name, e);
+            let failures = {
+                let mut failing = self.failing_guides.lock().unwrap();
+                let failures = failing.entry(channel.id.clone()).or_default();
+                *failures += 1;
+                *failures
+            };
+            if failures == 1 {
+                self.emit(Event::Failed {
+                    title: None,
+                    channel: Some(name.to_string()),
+                    error: format!("Couldn't fetch the guide: {}", e),
+                })
+                .await;
+            }
+            let delay = self.failed_pass_delay(failures);
+            retry = Some(retry.map_or(delay, |r: std::time::Duration| r.min(delay)));
+        }
+        retry
+    }
+
+    /// Logs how the plan differs from the previous pass's, rather than the whole plan
+    async fn log_plan_changes(
+        &self,
+        previous: Vec<CalendarEntry>,
+        current: Vec<CalendarEntry>,
+        since: i64,
+    ) {
+        // Everything is new on the first pass, which isn't worth a notification
+        let first_pass = previous.is_empty();
+        let diff = PlanDiff::between(previous, current, since, &self.aliases);
+        if diff.is_empty() {
+            return;
+        }
+        diff.log();
+        if self.notify_plan_changes && !first_pass {
+            self.emit(diff.event()).await;
+        }
+    }
+
+    /// Journals a failed subscription so a later pass can retry it, backing off
+    /// between attempts and giving up after the configured number or once it ends
+    async fn journal_failure(
+        &self,
+        channel: &str,
+        channel_title: &str,
+        show: &GridMetadata,
+        previous_attempts: i64,
+        error: BackendError,
+    ) -> Result<()> {
+        let attempts = previous_attempts + 1;
+        let now = self.clock.now().timestamp();
+        let ends_at = show.media.first().map_or(0, |m| m.ends_at);
+        let backoff = self.retry_backoff << (attempts - 1).min(16);
+        // A config error, like the library to record into having gone, won't
+        // fix itself, so is given up on and alerted about straight away
+        let retryable = !matches!(error, BackendError::Config(_));
+        let retry_at = Some(now + backoff)
+            .filter(|at| retryable && attempts < self.retry_attempts && *at < ends_at);
+
+        let message = error.to_string();
+        self.decide(show, Verdict::Failed(message.clone()));
+        let err = ManagerError::Scheduling {
+            channel: channel_title.to_string(),
+            show: show.show_title(),
+            source: Box::new(error.into()),
+        };
+        self.state.record_failure(&FailedRecording {
+            guid: show.guid.clone(),
+            begins_at: show.begins_at_ts(),
+            ends_at,
+            channel: channel.to_string(),
+            channel_title: channel_title.to_string(),
+            title: show.show_title(),
+            airing: serde_json::to_string(show).unwrap_or_default(),
+            error: message,
+            attempts,
+            retry_at,
+        })?;
+
+        match retry_at {
+            Some(at) => log::warn!("{}, retrying in {}s (attempt {})", err, at - now, attempts),
+            None => {
+                log::error!("{}, giving up after {} attempts", err, attempts);
+                reporting::report_error(&err, &err.context());
+                self.emit(err.event()).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks an airing that's due against what needs the DVR's guide or other
+    /// services, then subscribes to it
+    async fn record_due(
+        &self,
+        channel: &Channel,
+        show: GridMetadata,
+        is_from_guide: bool,
+        allowlist: Option<&HashSet<String>>,
+        bookings: Option<&Bookings>,
+    ) -> Result<(GridMetadata, DueOutcome)> {
+        let passed = |vetoed| DueOutcome::Passed { vetoed };
+        let begins_at = show.begins_at_ts();
+        let show = if is_from_guide {
+            match self.resolve_airing(channel, &show).await? {
+                Some(airing) => airing,
+                None => {
+                    log::warn!(
+                        "{} at {} isn't in the DVR's guide, can't record it",
+                        show.show_title(),
+                        begins_at
+                    );
+                    return Ok((show, passed(false)));
+                }
+            }
+        } else {
+            show
+        };
+        // The guide doesn't know about subscriptions, so check again
+        if let (true, Some(reason)) = (is_from_guide, self.skip_reason(&show, allowlist)) {
+            self.skip(&show, reason);
+            return Ok((show, passed(false)));
+        }
+        if show.guid.is_empty() {
+            log::warn!(
+                "{} at {} has no guid in the DVR's guide, can't record it",
+                show.show_title(),
+                begins_at
+            );
+            self.skip(&show, SkipReason::MissingGuid);
+            return Ok((show, passed(false)));
+        }
+
+        if let Some(reason) = self.veto(&show).await {
+            self.skip(&show, reason);
+            return Ok((show, passed(true)));
+        }
+
+        // Failed airings are retried on their own schedule
+        if self.state.failure(&show.guid, begins_at)?.is_some() {
+            return Ok((show, passed(false)));
+        }
+
+        let ends_at = show.media.first().map_or(begins_at, |m| m.ends_at);
+        let title = show.show_title();
+        let newly_booked = match bookings.map(|b| b.book(&channel.id, &title, begins_at, ends_at)) {
+            Some(Err(reason)) => {
+                match reason {
+                    SkipReason::LiveTv => log::info!(
+                        "Not recording {}, leaving a tuner free for live TV, a rerun may be recorded instead",
+                        show.show_title()
+                    ),
+                    _ => log::info!(
+                        "Not recording {}, as {} recordings would already be going",
+                        show.show_title(),
+                        self.max_concurrent_recordings.unwrap_or_default()
+                    ),
+                }
+                self.skip(&show, reason);
+                let alongside = bookings.map_or_else(Vec::new, |b| b.alongside(begins_at, ends_at));
+                self.dropped(&show, reason, alongside).await;
+                return Ok((show, passed(true)));
+            }
+            Some(Ok(new)) => new,
+            None => false,
+        };
+
+        log::info!("Beginning automatic recording of {}", show.show_title());
+        let outcome = match self.subscribe_once(&show).await? {
+            None => {
+                self.skip(&show, SkipReason::AlreadySubscribed);
+                passed(false)
+            }
+            Some(Ok(())) => DueOutcome::Subscribed,
+            Some(Err(e)) => DueOutcome::Failed(e),
+        };
+        // Failed airings keep their place for their retries
+        if let (Some(bookings), true, DueOutcome::Passed { .. }) =
+            (bookings, newly_booked, &outcome)
+        {
+            bookings.cancel(&channel.id, begins_at);
+        }
+        Ok((show, outcome))
+    }
+
+    /// Logs why an airing is being skipped, and keeps it in the decision log
+    /// if it's a decision about the airing
+    fn skip(&self, show: &GridMetadata, reason: SkipReason) {
+        decision::log_skip(show, reason);
+        if reason.is_decision() {
+            self.decide(show, Verdict::Skipped(reason));
+        }
+    }
+
+    /// Notes what was made of an airing, for the decision log once the pass ends
+    fn decide(&self, show: &GridMetadata, verdict: Verdict) {
+        self.decisions.lock().unwrap().push(Decision {
+            at: self.clock.now().timestamp(),
+            guid: show.guid.clone(),
+            begins_at: show.begins_at_ts(),
+            channel_title: show
+                .media
+                .first()
+                .map_or_else(String::new, |m| m.channel_title.clone()),
+            title: show.show_title(),
+            outcome: verdict.outcome().to_string(),
+            reason: verdict.reason(),
+        });
+    }
+
+    /// Notifies that an airing was left out for lack of a tuner, once however
+    /// many passes it's due in, so the user can make room for it
+    async fn dropped(&self, show: &GridMetadata, reason: SkipReason, alongside: Vec<String>) {
+        let title = show.show_title();
+        let channel = show
+            .media
+            .first()
+            .map_or_else(String::new, |m| m.channel_title.clone());
+        let begins_at = show.begins_at_ts();
+        // Kept in the history, so it's once across restarts too
+        match self.state.was_dropped(&title, &channel, begins_at) {
+            Ok(true) => return,
+            Ok(false) => (),
+            Err(e) => log::warn!("Couldn't check whether {} was dropped: {}", title, e),
+        }
+        self.emit(Event::Dropped {
+            title,
+            channel,
+            begins_at,
+            reason: reason.to_string(),
+            alongside,
+        })
+        .await;
+    }
+
+    /// Subscribes to an airing unless it's already been, or is being,
+    /// subscribed to, as the same airing can turn up in more than one day's
+    /// guide or in a pass overlapping another
+    async fn subscribe_once(
+        &self,
+        show: &GridMetadata,
+    ) -> Result<Option<Result<(), BackendError>>> {
+        let begins_at = show.begins_at_ts();
+        let now = self.clock.now().timestamp();
+        if !self.state.claim_airing(&show.guid, begins_at, now)? {
+            log::info!(
+                "{} at {} is already subscribed to, not subscribing again",
+                show.show_title(),
+                begins_at
+            );
+            return Ok(None);
+        }
+        let result = self.backend.subscribe(show).await;
+        self.state
+            .finish_claim(&show.guid, begins_at, result.is_ok())?;
+        Ok(Some(result))
+    }
+
+    /// Subscribes to journalled airings whose retry is due
+    async fn retry_failures(&self) -> Result<()> {
+        let now = self.clock.now().timestamp();
+        for failure in self.state.pending_failures()? {
+            if failure.retry_at.is_some_and(|at| at > now) {
+                break;
+            }
+            let show: GridMetadata = match serde_json::from_str(&failure.airing) {
+                Ok(show) => show,
+                Err(e) => {
+                    log::warn!(
+                        "Can't retry {}, its airing is unreadable: {}",
+                        failure.title,
+                        e
+                    );
+                    self.state.record_failure(&FailedRecording {
+                        retry_at: None,
+                        ..failure
+                    })?;
+                    continue;
+                }
+            };
+
+            log::info!("Retrying recording of {}", failure.title);
+            match self.subscribe_once(&show).await? {
+                None => {}
+                Some(Ok(())) => {
+                    self.state
+                        .remove_failure(&failure.guid, failure.begins_at)?;
+                    self.decide(&show, Verdict::Scheduled);
+                    self.emit(Event::scheduled(&show)).await;
+                }
+                Some(Err(e)) => {
+                    self.journal_failure(
+                        &failure.channel,
+                        &failure.channel_title,
+                        &show,
+                        failure.attempts,
+                        e,
+                    )
+                    .await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn pass_failed(&self, started_at: DateTime<Utc>, message: &str, event: Event) {
+        if let Err(e) = self
+            .state
+            .record_pass(started_at, self.clock.now(), Some(message))
+        {
+            log::warn!("Couldn't record failed pass: {}", e);
+        }
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.fail(message).await;
+        }
+        self.emit(event).await;
+    }
+
+    /// Enables configured channels the DVR's channel mapping has disabled,
+    /// every so often as only lineup rescans disable them. Each channel that
+    /// can't be enabled is alerted about once, until it's been enabled.
+    async fn ensure_channels_enabled(&self) {
+        let now = self.clock.now();
+        {
+            let checked = self.channel_map_checked.lock().unwrap();
+            if !self.enable_channels
+                || self.channels.is_empty()
+                || checked
+                    .0
+                    .is_some_and(|at| now - at < Duration::seconds(CHANNEL_MAP_INTERVAL))
+            {
+                return;
+            }
+        }
+        let result = tokio::time::timeout(
+            self.pass_timeout,
+            self.backend.enable_channels(&self.channels),
+        )
+        .await;
+        let stuck = match result {
+            Ok(Ok(stuck)) => stuck,
+            Ok(Err(e)) => {
+                log::warn!("Couldn't check the DVR's channel mapping: {}", e);
+                return;
+            }
+            Err(_) => {
+                log::warn!("Timed out checking the DVR's channel mapping");
+                return;
+            }
+        };
+        let newly_stuck: Vec<_> = {
+            let mut checked = self.channel_map_checked.lock().unwrap();
+            let newly_stuck = stuck
+                .iter()
+                .filter(|c| !checked.1.contains(*c))
+                .cloned()
+                .collect();
+            *checked = (Some(now), stuck.into_iter().collect());
+            newly_stuck
+        };
+        for channel in newly_stuck {
+            let error = format!(
+                "Couldn't enable channel {} in the DVR's channel mapping",
+                channel
             );
+            log::error!("{}", error);
+            self.emit(Event::Failed {
+                title: None,
+                channel: Some(channel),
+                error,
+            })
+            .await;
         }
+    }
 
-        Ok(next_show.map_or_else(
-            || Utc::now() + Duration::hours(1),
-            |s| s.begins_at().unwrap(),
-        ))
+    /// Checks the configured channels against the DVR's lineup, failing if
+    /// none are in it rather than running passes that can never record
+    async fn check_channels(&self) -> Result<()> {
+        let lineup = match tokio::time::timeout(self.pass_timeout, self.backend.channels()).await {
+            Ok(Ok(lineup)) => lineup,
+            Ok(Err(e)) => {
+                // Left to the first pass, which retries reaching the DVR
+                log::debug!("Couldn't check channels against the lineup: {}", e);
+                return Ok(());
+            }
+            Err(_) => {
+                log::debug!("Timed out checking channels against the lineup");
+                return Ok(());
+            }
+        };
+        let identifiers: Vec<&str> = lineup
+            .iter()
+            .filter_map(|c| c.identifier.as_deref())
+            .collect();
+        if identifiers.is_empty() {
+            return Err(ManagerError::EmptyLineup);
+        }
+        if self.channels.is_empty() {
+            log::info!(
+                "No channels configured, recording from all {} in the lineup",
+                identifiers.len()
+            );
+            return Ok(());
+        }
+
+        let missing: Vec<&str> = self
+            .channels
+            .iter()
+            .map(String::as_str)
+            .filter(|c| !identifiers.contains(c))
+            .collect();
+        if missing.len() < self.channels.len() {
+            for channel in missing {
+                log::warn!("Channel {} isn't in the DVR's lineup", channel);
+            }
+            return Ok(());
+        }
+
+        let lineup = lineup
+            .iter()
+            .filter_map(|c| {
+                let identifier = c.identifier.as_deref()?;
+                Some(match &c.title {
+                    Some(title) => format!("{} ({})", identifier, title),
+                    None => identifier.to_string(),
+                })
+            })
+            .join(", ");
+        Err(ManagerError::NoChannelsMatched {
+            configured: self.channels.join(", "),
+            lineup,
+        })
     }
 
-    /// Runs forever, setting everything to record just before it airs
+    /// How long the next pass may take, longer for idle passes which spread
+    /// their guide requests out
+    fn pass_budget(&self) -> std::time::Duration {
+        let spread = if self.idle_pass.load(Ordering::Relaxed) {
+            self.guide_fetch_spread
+        } else {
+            std::time::Duration::ZERO
+        };
+        self.pass_timeout + spread
+    }
+
+    /// Runs a pass, or gives up on it once it's over budget, most likely as a
+    /// request to the DVR has hung, so the retry can still catch airings
+    /// about to start
+    async fn timed_pass(&self) -> std::thread::Result<Result<DateTime<Utc>>> {
+        let budget = self.pass_budget();
+        let pass = AssertUnwindSafe(self.schedule_next_recordings()).catch_unwind();
+        let result = tokio::time::timeout(budget, pass)
+            .await
+            .unwrap_or_else(|_| {
+                // Subscriptions cut off part way are left to the next pass, which
+                // sees whether Plex made them
+                if let Err(e) = self.state.release_claims() {
+                    log::warn!("Couldn't release claims on airings: {}", e);
+                }
+                Ok(Err(ManagerError::PassTimeout(budget.as_secs())))
+            });
+        // Only a pass that finishes keeps what it decided
+        if !matches!(result, Ok(Ok(_))) {
+            self.decisions.lock().unwrap().clear();
+        }
+        result
+    }
+
+    /// When Plex's maintenance window ends, if it's in it now
+    fn maintenance_end(&self) -> Option<DateTime<Utc>> {
+        self.maintenance_window?.end_of(self.clock.now(), &Local)
+    }
+
+    /// Waits out Plex's maintenance window, if it's in it, returning whether
+    /// there was one to wait for
+    async fn wait_out_maintenance(&self) -> Result<bool> {
+        let Some(end) = self.maintenance_end() else {
+            return Ok(false);
+        };
+        log::info!("Plex is in its maintenance window, pausing until {}", end);
+        let delay = (end - self.clock.now()).to_std().unwrap_or_default();
+        self.sleep_after_failure(delay).await?;
+        log::info!("Plex's maintenance window is over, catching up");
+        Ok(true)
+    }
+
+    /// Wait before retrying after `failures` passes in a row couldn't reach
+    /// the DVR, doubling each time up to the restart delay
+    fn failed_pass_delay(&self, failures: u32) -> std::time::Duration {
+        (FAILED_PASS_DELAY * 2u32.pow(failures.saturating_sub(1).min(16))).min(self.restart_delay)
+    }
+
+    async fn sleep_after_failure(&self, delay: std::time::Duration) -> Result<()> {
+        if let Ok(delay) = Duration::from_std(delay) {
+            self.state.set_next_wake(self.clock.now() + delay)?;
+        }
+        self.clock.sleep(delay).await;
+        Ok(())
+    }
+
+    /// Keeps a record of the event for digests and tells the user about it
+    async fn emit(&self, event: Event) {
+        if let Err(e) = self.state.record_event(&event) {
+            log::warn!("Couldn't record event: {}", e);
+        }
+        // Expected while Plex restarts, so only kept for the digest
+        if matches!(event, Event::Failed { .. }) && self.maintenance_end().is_some() {
+            return;
+        }
+        self.notifiers.send(event).await;
+    }
+
+    /// Runs forever, setting everything to record just before it airs.
+    /// A panic during a pass is logged and the pass retried after a delay, as
+    /// is a pass that couldn't reach the DVR, backing off while it stays down.
     pub async fn auto_record(&self) -> Result<()> {
+        self.start_housekeeping();
+        self.auto_record_until(None).await
+    }
+
+    /// Starts pruning old state and deleting unwanted recordings in the
+    /// background, logging what each will do
+    fn start_housekeeping(&self) {
+        match self.history_retention {
+            0 => log::info!("Keeping history forever"),
+            days => {
+                log::info!("Keeping {} days of history", days);
+                tokio::spawn(retention::run(self.state.clone(), days));
+            }
+        }
+        match self.cleanup.lock().unwrap().take() {
+            Some(cleanup) => {
+                cleanup.log_policy();
+                tokio::spawn(cleanup::run(cleanup));
+            }
+            None => log::info!("Not deleting recordings, no cleanup watchers or size limit set"),
+        }
+    }
+
+    /// Like `auto_record`, but returns once the clock reaches `until`, so a
+    /// manual clock can replay a stretch of guide in moments
+    pub async fn auto_record_until(&self, until: Option<DateTime<Utc>>) -> Result<()> {
+        self.state.set_started(self.clock.now())?;
+        self.wait_out_maintenance().await?;
+        if let Err(e) = self.check_channels().await {
+            reporting::report_error(&e, &e.context());
+            self.pass_failed(self.clock.now(), &e.to_string(), e.event())
+                .await;
+            return Err(e);
+        }
+        let mut failures = 0;
         loop {
-            let next_time = self.schedule_next_recordings().await?;
-            let sleep_time = next_time - Utc::now() - Duration::seconds(PRE_SCHEDULE_TIME);
+            if until.is_some_and(|until| self.clock.now() >= until) {
+                return Ok(());
+            }
+            if self.wait_out_maintenance().await? {
+                failures = 0;
+                continue;
+            }
+            self.ensure_channels_enabled().await;
+            let started_at = self.clock.now();
+            let result = self.timed_pass().await;
+            let next_time = match result {
+                Ok(Ok(next_time)) => {
+                    failures = 0;
+                    self.state.record_pass(started_at, self.clock.now(), None)?;
+                    if let Some(heartbeat) = &self.heartbeat {
+                        heartbeat.success().await;
+                    }
+                    next_time
+                }
+                // Expected while Plex restarts, so waited out without alerting
+                Ok(Err(e)) if e.is_transient() && self.maintenance_end().is_some() => {
+                    log::info!("Scheduling pass failed in Plex's maintenance window: {}", e);
+                    continue;
+                }
+                Ok(Err(e)) if e.is_transient() => {
+                    failures += 1;
+                    let delay = self.failed_pass_delay(failures);
+                    log::warn!(
+                        "Scheduling pass failed, retrying in {}s: {}",
+                        delay.as_secs(),
+                        e
+                    );
+                    self.pass_failed(started_at, &e.to_string(), e.event())
+                        .await;
+                    self.sleep_after_failure(delay).await?;
+                    continue;
+                }
+                Ok(Err(e)) => {
+                    reporting::report_error(&e, &e.context());
+                    self.pass_failed(started_at, &e.to_string(), e.event())
+                        .await;
+                    return Err(e);
+                }
+                Err(panic) => {
+                    let message = format!("Panicked: {}", reporting::panic_message(&*panic));
+                    log::error!(
+                        "Scheduling pass failed, restarting in {}s",
+                        self.restart_delay.as_secs()
+                    );
+                    self.pass_failed(started_at, &message, Event::failed(&message))
+                        .await;
+                    self.sleep_after_failure(self.restart_delay).await?;
+                    continue;
+                }
+            };
+            let now = self.clock.now();
+            let sleep_time = sleep_duration(next_time, now);
             log::debug!(
-                "Next recording at {}, sleeping for {}",
+                "Next recording at {}, sleeping for {:?}",
                 next_time,
                 sleep_time
             );
-            sleep(
-                sleep_time
-                    .to_std()
-                    .unwrap_or_else(|_| std::time::Duration::from_secs(0)),
-            )
-            .await;
+            if let Ok(delay) = Duration::from_std(sleep_time) {
+                self.state.set_next_wake(now + delay)?;
+            }
+            tokio::select! {
+                _ = self.clock.sleep(sleep_time) => (),
+                _ = self.wake.notified() => log::info!("Woken early for a scheduling pass"),
+            }
         }
     }
 }
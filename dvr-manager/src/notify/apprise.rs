@@ -0,0 +1,42 @@
+use super::{Event, Notifier, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+/// Posts to an Apprise API server, either a stateless `/notify` endpoint
+/// (with `urls` naming the services) or a stateful `/notify/{key}` one
+pub struct Apprise {
+    client: reqwest::Client,
+    url: String,
+    urls: Option<String>,
+}
+
+impl Apprise {
+    pub fn new(client: reqwest::Client, url: String, urls: Option<String>) -> Self {
+        Apprise { client, url, urls }
+    }
+}
+
+#[async_trait]
+impl Notifier for Apprise {
+    fn name(&self) -> &'static str {
+        "apprise"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let mut body = json!({
+            "title": event.title(),
+            "body": event.summary(),
+            "type": if event.is_failure() { "failure" } else { "info" },
+        });
+        if let Some(urls) = &self.urls {
+            body["urls"] = urls.clone().into();
+        }
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
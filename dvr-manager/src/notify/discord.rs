@@ -0,0 +1,128 @@
+use super::{Event, Notifier, Result};
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+
+const COLOUR_SCHEDULED: u32 = 0x2ecc71;
+const COLOUR_RECORDED: u32 = 0x3498db;
+const COLOUR_FAILED: u32 = 0xe74c3c;
+const COLOUR_DELETED: u32 = 0x95a5a6;
+const COLOUR_DROPPED: u32 = 0xe67e22;
+
+/// Posts events to a Discord channel webhook as embeds
+pub struct Discord {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Discord {
+    pub fn new(client: reqwest::Client, url: String) -> Self {
+        Discord { client, url }
+    }
+
+    fn embed(event: &Event) -> serde_json::Value {
+        match event {
+            Event::Scheduled {
+                title,
+                channel,
+                begins_at,
+                thumb,
+            } => {
+                let mut embed = json!({
+                    "title": title,
+                    "description": "Scheduled for recording",
+                    "color": COLOUR_SCHEDULED,
+                    "fields": [
+                        { "name": "Channel", "value": channel, "inline": true },
+                        { "name": "Starts", "value": format!("<t:{}:f>", begins_at), "inline": true },
+                    ],
+                });
+                if let Some(ts) = Utc.timestamp_opt(*begins_at, 0).single() {
+                    embed["timestamp"] = ts.to_rfc3339().into();
+                }
+                // Discord can only show images it can fetch without a Plex token
+                if let Some(thumb) = thumb.as_ref().filter(|t| t.starts_with("http")) {
+                    embed["thumbnail"] = json!({ "url": thumb });
+                }
+                embed
+            }
+            Event::Recorded { title, .. } => json!({
+                "title": title,
+                "description": "Added to the library",
+                "color": COLOUR_RECORDED,
+            }),
+            Event::Deleted { title, reason } => json!({
+                "title": title,
+                "description": format!("Deleted, {}", reason),
+                "color": COLOUR_DELETED,
+            }),
+            Event::Failed {
+                title,
+                channel,
+                error,
+            } => {
+                let mut fields = Vec::new();
+                if let Some(channel) = channel {
+                    fields.push(json!({ "name": "Channel", "value": channel, "inline": true }));
+                }
+                json!({
+                    "title": title.as_deref().unwrap_or("DVR manager error"),
+                    "description": error,
+                    "color": COLOUR_FAILED,
+                    "fields": fields,
+                })
+            }
+            Event::Dropped {
+                title,
+                channel,
+                begins_at,
+                reason,
+                alongside,
+            } => {
+                let mut fields = vec![
+                    json!({ "name": "Channel", "value": channel, "inline": true }),
+                    json!({ "name": "Starts", "value": format!("<t:{}:f>", begins_at), "inline": true }),
+                ];
+                if !alongside.is_empty() {
+                    fields.push(
+                        json!({ "name": "Recording instead", "value": alongside.join("\n") }),
+                    );
+                }
+                json!({
+                    "title": title,
+                    "description": format!("Not recording, {}", reason),
+                    "color": COLOUR_DROPPED,
+                    "fields": fields,
+                })
+            }
+            Event::Report { title, body } => json!({
+                "title": title,
+                "description": body,
+                "color": COLOUR_RECORDED,
+            }),
+            Event::PlanChanged { .. } => json!({
+                "title": event.title(),
+                "description": event.summary(),
+                "color": COLOUR_SCHEDULED,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Discord {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let body = json!({ "embeds": [Self::embed(event)] });
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
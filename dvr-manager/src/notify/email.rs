@@ -0,0 +1,85 @@
+use super::{
+    Category, Event, Notifier, NotifyConfig, NotifyError, Result, Route, Severity, SmtpSecurity,
+};
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+/// Sends failures as they happen, and digests when asked
+#[derive(Clone)]
+pub struct Email {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Email {
+    /// Returns `None` if email isn't configured
+    pub fn new(config: &NotifyConfig) -> Option<Result<Self>> {
+        let host = config.smtp_host.as_ref()?;
+        let to = config.email_to.as_ref()?;
+        Some(Self::build(config, host, to))
+    }
+
+    fn build(config: &NotifyConfig, host: &str, to: &str) -> Result<Self> {
+        let mut transport = match config.smtp_security.unwrap_or_default() {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = config.smtp_port {
+            transport = transport.port(port);
+        }
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let to = to
+            .split(',')
+            .map(|a| a.trim().parse())
+            .collect::<Result<Vec<Mailbox>, _>>()?;
+        let from = match &config.email_from {
+            Some(from) => from.parse()?,
+            None => to
+                .first()
+                .cloned()
+                .ok_or_else(|| NotifyError::Config("email_to has no addresses".into()))?,
+        };
+
+        Ok(Email {
+            transport: transport.build(),
+            from,
+            to,
+        })
+    }
+
+    pub async fn send(&self, subject: &str, body: String) -> Result<()> {
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        self.transport.send(message.body(body)?).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    /// Only failures are worth an immediate email, the rest goes in the digest
+    fn default_route(&self) -> Route {
+        Route {
+            categories: Some(vec![Category::Failure]),
+            min_severity: Severity::Warning,
+        }
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        self.send(&format!("DVR: {}", event.title()), event.summary())
+            .await
+    }
+}
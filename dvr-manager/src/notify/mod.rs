@@ -0,0 +1,489 @@
+#[cfg(feature = "notifications")]
+mod apprise;
+#[cfg(feature = "notifications")]
+mod discord;
+#[cfg(feature = "notifications")]
+pub mod email;
+#[cfg(feature = "notifications")]
+mod push;
+#[cfg(feature = "notifications")]
+mod slack;
+#[cfg(feature = "notifications")]
+mod webhook;
+
+use crate::config::redacted;
+use crate::digest::DigestPeriod;
+use crate::plex::GridMetadata;
+use async_trait::async_trait;
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[cfg(feature = "notifications")]
+const SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotifyError {
+    #[error("Failed to send notification: {0}")]
+    Request(reqwest::Error),
+
+    #[cfg(feature = "notifications")]
+    #[error("Failed to send email: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+
+    #[cfg(feature = "notifications")]
+    #[error("Invalid email address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+
+    #[cfg(feature = "notifications")]
+    #[error("Couldn't build email: {0}")]
+    Message(#[from] lettre::error::Error),
+
+    #[error("Notification config error: {0}")]
+    Config(String),
+}
+
+impl From<reqwest::Error> for NotifyError {
+    /// Notification URLs usually embed credentials, so keep them out of logs
+    fn from(err: reqwest::Error) -> Self {
+        NotifyError::Request(err.without_url())
+    }
+}
+
+pub type Result<T, E = NotifyError> = std::result::Result<T, E>;
+
+/// Kinds of event, so users can choose where each kind is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    Scheduled,
+    Recorded,
+    Failure,
+    Cleanup,
+    GuideWarning,
+    Plan,
+    Conflict,
+    Report,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// TLS from the start, usually port 465
+    Tls,
+    /// Upgrade a plain connection, usually port 587
+    #[default]
+    Starttls,
+    None,
+}
+
+/// Which events a notifier receives
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    /// Categories to send, or all of them if unset
+    pub categories: Option<Vec<Category>>,
+    #[serde(default = "Route::default_severity")]
+    pub min_severity: Severity,
+}
+
+impl Route {
+    fn default_severity() -> Severity {
+        Severity::Info
+    }
+
+    pub fn all() -> Self {
+        Route {
+            categories: None,
+            min_severity: Severity::Info,
+        }
+    }
+
+    fn accepts(&self, event: &Event) -> bool {
+        event.severity() >= self.min_severity
+            && self
+                .categories
+                .as_ref()
+                .is_none_or(|c| c.contains(&event.category()))
+    }
+}
+
+/// Something that happened which users may want to hear about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Scheduled {
+        title: String,
+        channel: String,
+        begins_at: i64,
+        thumb: Option<String>,
+    },
+    Recorded {
+        title: String,
+        rating_key: String,
+    },
+    Failed {
+        title: Option<String>,
+        channel: Option<String>,
+        error: String,
+    },
+    Deleted {
+        title: String,
+        reason: String,
+    },
+    /// An airing that would have been recorded, left out as no tuner could be
+    /// spared for it
+    Dropped {
+        title: String,
+        channel: String,
+        begins_at: i64,
+        reason: String,
+        /// What's recording during it instead
+        alongside: Vec<String>,
+    },
+    /// The weekly report, in Markdown
+    Report {
+        title: String,
+        body: String,
+    },
+    /// Airings added to, removed from or moved in the plan since the last pass
+    PlanChanged {
+        added: Vec<String>,
+        removed: Vec<String>,
+        shifted: Vec<String>,
+    },
+}
+
+impl Event {
+    pub fn scheduled(show: &GridMetadata) -> Self {
+        Event::Scheduled {
+            title: show.show_title(),
+            channel: show
+                .media
+                .first()
+                .map_or_else(String::new, |m| m.channel_title.clone()),
+            begins_at: show.begins_at_ts(),
+            thumb: show.grandparent_thumb.clone(),
+        }
+    }
+
+    pub fn failed(error: impl Into<String>) -> Self {
+        Event::Failed {
+            title: None,
+            channel: None,
+            error: error.into(),
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        match self {
+            Event::Scheduled { .. } => Category::Scheduled,
+            Event::Recorded { .. } => Category::Recorded,
+            Event::Failed { .. } => Category::Failure,
+            Event::Deleted { .. } => Category::Cleanup,
+            Event::PlanChanged { .. } => Category::Plan,
+            Event::Dropped { .. } => Category::Conflict,
+            Event::Report { .. } => Category::Report,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Event::Scheduled { .. }
+            | Event::Recorded { .. }
+            | Event::Deleted { .. }
+            | Event::PlanChanged { .. }
+            | Event::Report { .. } => Severity::Info,
+            Event::Dropped { .. } => Severity::Warning,
+            Event::Failed { .. } => Severity::Error,
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Event::Failed { .. })
+    }
+
+    /// Short heading for notifiers that show a title
+    pub fn title(&self) -> &'static str {
+        match self {
+            Event::Scheduled { .. } => "Recording scheduled",
+            Event::Recorded { .. } => "Recording added",
+            Event::Failed { .. } => "Recording failed",
+            Event::Deleted { .. } => "Recording deleted",
+            Event::PlanChanged { .. } => "Plan changed",
+            Event::Dropped { .. } => "Recording dropped",
+            Event::Report { .. } => "Weekly report",
+        }
+    }
+
+    /// Description for text based notifiers, one line unless it lists plan
+    /// changes or is a report
+    pub fn summary(&self) -> String {
+        match self {
+            Event::Scheduled {
+                title,
+                channel,
+                begins_at,
+                ..
+            } => format!(
+                "Recording {} on {} at {}",
+                title,
+                channel,
+                format_time(*begins_at)
+            ),
+            Event::Recorded { title, .. } => format!("{} has been added to the library", title),
+            Event::Failed {
+                title: Some(title),
+                channel: Some(channel),
+                error,
+            } => format!("Failed to record {} on {}: {}", title, channel, error),
+            Event::Failed { error, .. } => format!("DVR manager error: {}", error),
+            Event::Deleted { title, reason } => format!("Deleted {}, {}", title, reason),
+            Event::Dropped {
+                title,
+                channel,
+                begins_at,
+                reason,
+                alongside,
+            } => {
+                let mut summary = format!(
+                    "Not recording {} on {} at {}, {}",
+                    title,
+                    channel,
+                    format_time(*begins_at),
+                    reason
+                );
+                if !alongside.is_empty() {
+                    summary += &format!(" (recording {})", alongside.join(", "));
+                }
+                summary
+            }
+            Event::Report { body, .. } => body.clone(),
+            Event::PlanChanged {
+                added,
+                removed,
+                shifted,
+            } => {
+                let mut summary = format!(
+                    "Plan changed: {} added, {} removed, {} moved",
+                    added.len(),
+                    removed.len(),
+                    shifted.len()
+                );
+                for (prefix, lines) in [("+", added), ("-", removed), ("~", shifted)] {
+                    for line in lines {
+                        summary += &format!("\n{} {}", prefix, line);
+                    }
+                }
+                summary
+            }
+        }
+    }
+}
+
+pub fn format_time(ts: i64) -> String {
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map_or_else(|| ts.to_string(), |t| t.format("%a %H:%M").to_string())
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Identifies the notifier in `notify_routes`
+    fn name(&self) -> &'static str;
+
+    /// Events sent when the user hasn't configured a route
+    fn default_route(&self) -> Route {
+        Route::all()
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()>;
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct NotifyConfig {
+    pub webhook_url: Option<String>,
+    /// Optional body for webhook requests, with `{{field}}` placeholders
+    pub webhook_template: Option<String>,
+    pub discord_webhook_url: Option<String>,
+    pub slack_webhook_url: Option<String>,
+    /// Full topic URL, e.g. `https://ntfy.sh/my-dvr`
+    pub ntfy_url: Option<String>,
+    pub ntfy_token: Option<String>,
+    pub pushover_token: Option<String>,
+    pub pushover_user: Option<String>,
+    pub gotify_url: Option<String>,
+    pub gotify_token: Option<String>,
+    /// Apprise API notify endpoint, e.g. `http://apprise:8000/notify/dvr`
+    pub apprise_url: Option<String>,
+    /// Apprise service URLs, when using the stateless endpoint
+    pub apprise_urls: Option<String>,
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_security: Option<SmtpSecurity>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub email_from: Option<String>,
+    /// Comma separated recipients
+    pub email_to: Option<String>,
+    pub email_digest: Option<DigestPeriod>,
+    /// Per-notifier routes, keyed by notifier name (`discord`, `email`, ...)
+    #[serde(default)]
+    pub notify_routes: HashMap<String, Route>,
+}
+
+impl std::fmt::Debug for NotifyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NotifyConfig")
+            .field("webhook_url", &self.webhook_url)
+            .field("webhook_template", &self.webhook_template)
+            .field("discord_webhook_url", &redacted(&self.discord_webhook_url))
+            .field("slack_webhook_url", &redacted(&self.slack_webhook_url))
+            .field("ntfy_url", &self.ntfy_url)
+            .field("ntfy_token", &redacted(&self.ntfy_token))
+            .field("pushover_token", &redacted(&self.pushover_token))
+            .field("pushover_user", &redacted(&self.pushover_user))
+            .field("gotify_url", &self.gotify_url)
+            .field("gotify_token", &redacted(&self.gotify_token))
+            .field("apprise_url", &self.apprise_url)
+            .field("apprise_urls", &redacted(&self.apprise_urls))
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("smtp_security", &self.smtp_security)
+            .field("smtp_username", &self.smtp_username)
+            .field("smtp_password", &redacted(&self.smtp_password))
+            .field("email_from", &self.email_from)
+            .field("email_to", &self.email_to)
+            .field("email_digest", &self.email_digest)
+            .field("notify_routes", &self.notify_routes)
+            .finish()
+    }
+}
+
+/// All configured notifiers, each event is sent to those whose route accepts it
+#[derive(Default)]
+pub struct Notifiers {
+    notifiers: Vec<(Route, Box<dyn Notifier>)>,
+}
+
+impl Notifiers {
+    /// The notifiers with enough config to be enabled
+    #[cfg(feature = "notifications")]
+    fn enabled(config: &NotifyConfig) -> Vec<Box<dyn Notifier>> {
+        let client = reqwest::Client::builder()
+            .timeout(SEND_TIMEOUT)
+            .build()
+            .expect("notification client is valid");
+
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+        if let Some(url) = &config.webhook_url {
+            notifiers.push(Box::new(webhook::Webhook::new(
+                client.clone(),
+                url.clone(),
+                config.webhook_template.clone(),
+            )));
+        }
+        if let Some(url) = &config.discord_webhook_url {
+            notifiers.push(Box::new(discord::Discord::new(client.clone(), url.clone())));
+        }
+        if let Some(url) = &config.slack_webhook_url {
+            notifiers.push(Box::new(slack::Slack::new(client.clone(), url.clone())));
+        }
+        if let Some(url) = &config.ntfy_url {
+            notifiers.push(Box::new(push::Ntfy::new(
+                client.clone(),
+                url.clone(),
+                config.ntfy_token.clone(),
+            )));
+        }
+        if let (Some(token), Some(user)) = (&config.pushover_token, &config.pushover_user) {
+            notifiers.push(Box::new(push::Pushover::new(
+                client.clone(),
+                token.clone(),
+                user.clone(),
+            )));
+        }
+        if let (Some(url), Some(token)) = (&config.gotify_url, &config.gotify_token) {
+            notifiers.push(Box::new(push::Gotify::new(
+                client.clone(),
+                url.clone(),
+                token.clone(),
+            )));
+        }
+        if let Some(url) = &config.apprise_url {
+            notifiers.push(Box::new(apprise::Apprise::new(
+                client.clone(),
+                url.clone(),
+                config.apprise_urls.clone(),
+            )));
+        }
+        match email::Email::new(config) {
+            Some(Ok(email)) => notifiers.push(Box::new(email)),
+            Some(Err(e)) => log::error!("Email notifications disabled: {}", e),
+            None => (),
+        }
+        notifiers
+    }
+
+    #[cfg(not(feature = "notifications"))]
+    fn enabled(config: &NotifyConfig) -> Vec<Box<dyn Notifier>> {
+        let configured = [
+            &config.webhook_url,
+            &config.discord_webhook_url,
+            &config.slack_webhook_url,
+            &config.ntfy_url,
+            &config.pushover_token,
+            &config.gotify_url,
+            &config.apprise_url,
+            &config.smtp_host,
+        ];
+        if configured.iter().any(|c| c.is_some()) {
+            log::warn!("Notifications are configured, but this build doesn't include them");
+        }
+        Vec::new()
+    }
+
+    pub fn new(config: &NotifyConfig) -> Self {
+        let notifiers = Self::enabled(config);
+
+        for name in config.notify_routes.keys() {
+            if !notifiers.iter().any(|n| n.name() == name) {
+                log::warn!("Route configured for {}, which isn't enabled", name);
+            }
+        }
+
+        let notifiers = notifiers
+            .into_iter()
+            .map(|n| {
+                let route = config
+                    .notify_routes
+                    .get(n.name())
+                    .cloned()
+                    .unwrap_or_else(|| n.default_route());
+                (route, n)
+            })
+            .collect();
+
+        Notifiers { notifiers }
+    }
+
+    /// Notification failures are logged but never interrupt scheduling
+    pub async fn send(&self, event: Event) {
+        for (route, notifier) in &self.notifiers {
+            if !route.accepts(&event) {
+                continue;
+            }
+            if let Err(e) = notifier.notify(&event).await {
+                log::warn!("{} notification failed: {}", notifier.name(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Push notification services commonly self-hosted alongside a NAS
+
+use super::{Event, Notifier, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+const PUSHOVER_URL: &str = "https://api.pushover.net/1/messages.json";
+
+/// Publishes to an ntfy topic URL, e.g. `https://ntfy.sh/my-dvr`
+pub struct Ntfy {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Ntfy {
+    pub fn new(client: reqwest::Client, url: String, token: Option<String>) -> Self {
+        Ntfy { client, url, token }
+    }
+}
+
+#[async_trait]
+impl Notifier for Ntfy {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let (priority, tags) = if event.is_failure() {
+            ("high", "warning")
+        } else {
+            ("default", "tv")
+        };
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Title", event.title())
+            .header("Priority", priority)
+            .header("Tags", tags)
+            .body(event.summary());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+pub struct Pushover {
+    client: reqwest::Client,
+    token: String,
+    user: String,
+}
+
+impl Pushover {
+    pub fn new(client: reqwest::Client, token: String, user: String) -> Self {
+        Pushover {
+            client,
+            token,
+            user,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Pushover {
+    fn name(&self) -> &'static str {
+        "pushover"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let priority = if event.is_failure() { "1" } else { "0" };
+        self.client
+            .post(PUSHOVER_URL)
+            .form(&[
+                ("token", self.token.as_str()),
+                ("user", self.user.as_str()),
+                ("title", event.title()),
+                ("message", &event.summary()),
+                ("priority", priority),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Sends to a Gotify server using an application token
+pub struct Gotify {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl Gotify {
+    pub fn new(client: reqwest::Client, url: String, token: String) -> Self {
+        Gotify {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Gotify {
+    fn name(&self) -> &'static str {
+        "gotify"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let priority = if event.is_failure() { 8 } else { 4 };
+        self.client
+            .post(format!("{}/message", self.url))
+            .header("X-Gotify-Key", &self.token)
+            .json(&json!({
+                "title": event.title(),
+                "message": event.summary(),
+                "priority": priority,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
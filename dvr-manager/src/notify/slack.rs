@@ -0,0 +1,134 @@
+use super::{Event, Notifier, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+/// Posts events to a Slack incoming webhook using Block Kit
+pub struct Slack {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Slack {
+    pub fn new(client: reqwest::Client, url: String) -> Self {
+        Slack { client, url }
+    }
+
+    fn blocks(event: &Event) -> serde_json::Value {
+        match event {
+            Event::Scheduled {
+                title,
+                channel,
+                begins_at,
+                thumb,
+            } => {
+                let mut section = json!({
+                    "type": "section",
+                    "text": {
+                        "type": "mrkdwn",
+                        "text": format!(
+                            ":red_circle: *{}*\n{} at <!date^{}^{{date_short_pretty}} {{time}}|{}>",
+                            escape(title),
+                            escape(channel),
+                            begins_at,
+                            super::format_time(*begins_at)
+                        ),
+                    },
+                });
+                if let Some(thumb) = thumb.as_ref().filter(|t| t.starts_with("http")) {
+                    section["accessory"] = json!({
+                        "type": "image",
+                        "image_url": thumb,
+                        "alt_text": title,
+                    });
+                }
+                json!([section])
+            }
+            Event::Recorded { title, .. } => json!([{
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": format!(":white_check_mark: *{}* has been added to the library", escape(title)),
+                },
+            }]),
+            Event::Deleted { title, reason } => json!([{
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": format!(":wastebasket: Deleted *{}*, {}", escape(title), escape(reason)),
+                },
+            }]),
+            Event::Failed {
+                title,
+                channel,
+                error,
+            } => {
+                let heading = match (title, channel) {
+                    (Some(title), Some(channel)) => {
+                        format!(
+                            "*Failed to record {}* on {}",
+                            escape(title),
+                            escape(channel)
+                        )
+                    }
+                    _ => "*DVR manager error*".to_string(),
+                };
+                json!([
+                    {
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": format!(":warning: {}", heading) },
+                    },
+                    {
+                        "type": "context",
+                        "elements": [{ "type": "mrkdwn", "text": escape(error) }],
+                    },
+                ])
+            }
+            Event::Dropped { .. } => json!([{
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": format!(":no_entry_sign: {}", escape(&event.summary())),
+                },
+            }]),
+            Event::Report { body, .. } => json!([{
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": escape(body) },
+            }]),
+            Event::PlanChanged { .. } => json!([{
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": format!(":calendar: {}", escape(&event.summary())),
+                },
+            }]),
+        }
+    }
+}
+
+/// Slack treats these as control characters in mrkdwn
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[async_trait]
+impl Notifier for Slack {
+    fn name(&self) -> &'static str {
+        "slack"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let body = json!({
+            "text": event.summary(),
+            "blocks": Self::blocks(event),
+        });
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use super::{Event, Notifier, Result};
+use async_trait::async_trait;
+
+/// Posts each event as JSON, or as the user's template with fields substituted
+pub struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    template: Option<String>,
+}
+
+impl Webhook {
+    pub fn new(client: reqwest::Client, url: String, template: Option<String>) -> Self {
+        Webhook {
+            client,
+            url,
+            template,
+        }
+    }
+
+    fn render(template: &str, event: &Event) -> String {
+        let mut fields = match serde_json::to_value(event) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => Default::default(),
+        };
+        fields.insert("message".into(), event.summary().into());
+
+        fields
+            .iter()
+            .fold(template.to_string(), |body, (key, value)| {
+                // Values are JSON escaped so templates can quote them safely
+                let value = match value {
+                    serde_json::Value::String(s) => {
+                        let quoted = serde_json::Value::String(s.clone()).to_string();
+                        quoted[1..quoted.len() - 1].to_string()
+                    }
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                body.replace(&format!("{{{{{}}}}}", key), &value)
+            })
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let request = self.client.post(&self.url);
+        let request = match &self.template {
+            Some(template) => request
+                .header("content-type", "application/json")
+                .body(Self::render(template, event)),
+            None => {
+                let mut body = serde_json::to_value(event).unwrap_or_default();
+                body["message"] = event.summary().into();
+                request.json(&body)
+            }
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use crate::notify::{self, Event};
+use crate::state::CalendarEntry;
+use crate::title::TitleAliases;
+
+fn describe(entry: &CalendarEntry) -> String {
+    format!(
+        "{} on {} at {}",
+        entry.title,
+        entry.channel_title,
+        notify::format_time(entry.begins_at)
+    )
+}
+
+/// On the same channel, and by the same name even if the guide's started
+/// calling it something else
+fn same_airing(a: &CalendarEntry, b: &CalendarEntry, aliases: &TitleAliases) -> bool {
+    a.channel == b.channel && aliases.same(&a.title, &b.title)
+}
+
+/// How the manager's plan changed between two passes, so only the changes need
+/// logging rather than every airing in the lineup
+#[derive(Default)]
+pub struct PlanDiff {
+    pub added: Vec<CalendarEntry>,
+    pub removed: Vec<CalendarEntry>,
+    /// Airings now starting at a different time, with when they used to start
+    pub shifted: Vec<(CalendarEntry, i64)>,
+}
+
+impl PlanDiff {
+    /// Compares calendars, ignoring airings that start before `since`
+    /// since those drop out of the plan as time passes
+    pub fn between(
+        previous: Vec<CalendarEntry>,
+        current: Vec<CalendarEntry>,
+        since: i64,
+        aliases: &TitleAliases,
+    ) -> Self {
+        let mut previous: Vec<_> = previous
+            .into_iter()
+            .filter(|e| e.begins_at >= since)
+            .collect();
+        let mut unmatched = Vec::new();
+        for entry in current.into_iter().filter(|e| e.begins_at >= since) {
+            match previous
+                .iter()
+                .position(|p| same_airing(p, &entry, aliases) && p.begins_at == entry.begins_at)
+            {
+                Some(i) => {
+                    previous.remove(i);
+                }
+                None => unmatched.push(entry),
+            }
+        }
+
+        // Whatever's left with the same title on the same channel has moved
+        let mut diff = PlanDiff::default();
+        for entry in unmatched {
+            match previous
+                .iter()
+                .position(|p| same_airing(p, &entry, aliases))
+            {
+                Some(i) => {
+                    let was = previous.remove(i).begins_at;
+                    diff.shifted.push((entry, was));
+                }
+                None => diff.added.push(entry),
+            }
+        }
+        diff.removed = previous;
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.shifted.is_empty()
+    }
+
+    pub fn log(&self) {
+        for e in &self.added {
+            log::info!("Plan added {}", describe(e));
+        }
+        for e in &self.removed {
+            log::info!("Plan removed {}", describe(e));
+        }
+        for (e, was) in &self.shifted {
+            log::info!(
+                "Plan moved {} from {}",
+                describe(e),
+                notify::format_time(*was)
+            );
+        }
+    }
+
+    pub fn event(&self) -> Event {
+        Event::PlanChanged {
+            added: self.added.iter().map(describe).collect(),
+            removed: self.removed.iter().map(describe).collect(),
+            shifted: self
+                .shifted
+                .iter()
+                .map(|(e, was)| format!("{} (was {})", describe(e), notify::format_time(*was)))
+                .collect(),
+        }
+    }
+}
@@ -1,17 +1,54 @@
+use crate::reporting;
 use async_trait::async_trait;
-use chrono::{DateTime, TimeZone, Utc};
-use reqwest::{RequestBuilder};
-use serde::{Deserialize, Serialize, Serializer};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use reqwest::RequestBuilder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_xml_rs::from_str;
+use std::io::{BufReader, Read};
 use std::sync::Arc;
-use tokio::sync::Semaphore;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+#[cfg(feature = "server")]
+use utoipa::ToSchema;
 
 const PREFS_PATH: &str = "/config/Library/Application Support/Plex Media Server/Preferences.xml";
 
+pub const PROVIDERS_RESOURCE: &str = "media/providers";
+pub const CHANNELS_RESOURCE: &str = "tv.plex.providers.epg.xmltv:2/lineups/dvr/channels";
+pub const GRID_RESOURCE: &str = "tv.plex.providers.epg.xmltv:2/grid";
+pub const IDENTITY_RESOURCE: &str = "identity";
+pub const DVRS_RESOURCE: &str = "livetv/dvrs";
+
+/// Response chunks buffered ahead of the JSON parser
+const STREAM_CHUNKS: usize = 4;
+
+/// Requests in flight to Plex at once
+const MAX_CONCURRENT_REQUESTS: usize = 5;
+/// How long an idle connection is kept for reuse. Passes fetch guides in quick
+/// succession, so this only needs to outlast the gaps between requests.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+/// Longest a request may take, body included, before it's given up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+/// Tries at a request that failed transiently, if repeating it is harmless
+const REQUEST_ATTEMPTS: u32 = 3;
+/// Wait before retrying a request, doubling after each attempt
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Most a `Retry-After` header is obeyed for
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Format of the `date` parameter to the grid endpoint
+pub const GRID_DATE_FORMAT: &str = "%Y-%m-%d";
+
+const TOKEN_PARAM: &str = "X-Plex-Token";
+pub const REDACTED: &str = "REDACTED";
+
 #[derive(Debug, thiserror::Error)]
 pub enum PlexError {
     #[error("Failed to request data from Plex: {0}")]
-    PlexRequest(#[from] reqwest::Error),
+    PlexRequest(reqwest::Error),
 
     #[error("Couldn't parse Plex response: {0}")]
     PlexXmlResponse(#[from] serde_xml_rs::Error),
@@ -25,12 +62,50 @@ pub enum PlexError {
     #[error("Couldn't parse Plex response: {0}")]
     PlexResponse(String),
 
+    #[error("The Plex token is empty")]
+    EmptyToken,
+
+    #[error("Invalid plex_url {url}: {reason}")]
+    InvalidUrl { url: String, reason: String },
+
+    /// Too many requests, so Plex didn't act on this one
+    #[error("Plex is throttling requests")]
+    Throttled,
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
 
+impl From<reqwest::Error> for PlexError {
+    fn from(mut err: reqwest::Error) -> Self {
+        if let Some(url) = err.url_mut() {
+            redact_url(url);
+        }
+        PlexError::PlexRequest(err)
+    }
+}
+
 pub type Result<T, E = PlexError> = std::result::Result<T, E>;
 
+/// Replaces the token in a request URL so it can be logged
+pub fn redact_url(url: &mut reqwest::Url) {
+    if !url.query_pairs().any(|(k, _)| k == TOKEN_PARAM) {
+        return;
+    }
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if k == TOKEN_PARAM {
+                REDACTED.into()
+            } else {
+                v.into_owned()
+            };
+            (k.into_owned(), v)
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TemplateParameters {
     pub hints: SubscriptionHints,
@@ -44,10 +119,39 @@ pub struct TemplateSetting {
     default: String,
 }
 
+/// What a subscription template records, by Plex's numeric metadata type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum SubscriptionType {
+    Movie,
+    Show,
+    Other(i64),
+}
+
+impl From<i64> for SubscriptionType {
+    fn from(value: i64) -> Self {
+        match value {
+            1 => SubscriptionType::Movie,
+            2 => SubscriptionType::Show,
+            other => SubscriptionType::Other(other),
+        }
+    }
+}
+
+impl From<SubscriptionType> for i64 {
+    fn from(value: SubscriptionType) -> Self {
+        match value {
+            SubscriptionType::Movie => 1,
+            SubscriptionType::Show => 2,
+            SubscriptionType::Other(other) => other,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TemplateSubscription<T> {
     pub parameters: T,
-    pub r#type: i64,
+    pub r#type: SubscriptionType,
     #[serde(rename = "targetSectionLocationID")]
     pub target_section_location_id: Option<i16>,
     #[serde(rename = "Setting")]
@@ -65,8 +169,38 @@ impl<T> TemplateSubscription<T> {
             .clone();
         Ok(val)
     }
+
+    /// The template's default for a setting, or Plex's usual default if this
+    /// server version's template leaves it out
+    pub fn setting_or_default(&self, id: &str) -> Result<String> {
+        match self.setting_default(id) {
+            Ok(val) => Ok(val),
+            Err(e) => {
+                let (_, fallback) = SETTING_DEFAULTS
+                    .iter()
+                    .find(|(setting, _)| *setting == id)
+                    .ok_or(e)?;
+                log::warn!(
+                    "Subscription template has no {} setting, using {}",
+                    id,
+                    fallback
+                );
+                Ok(fallback.to_string())
+            }
+        }
+    }
 }
 
+/// Defaults Plex documents for the recording settings subscriptions are made with
+const SETTING_DEFAULTS: &[(&str, &str)] = &[
+    ("minVideoQuality", "0"),
+    ("replaceLowerQuality", "false"),
+    ("recordPartials", "true"),
+    ("comskipEnabled", "-1"),
+    ("comskipMethod", "2"),
+    ("remoteMedia", "false"),
+];
+
 #[derive(Debug, Serialize, Deserialize)]
 struct TemplateTemplate {
     #[serde(rename = "MediaSubscription")]
@@ -85,12 +219,132 @@ struct TemplateResponse {
     media_container: TemplateContainer,
 }
 
+impl TemplateResponse {
+    /// The templates with their parameters decoded
+    fn into_templates(self) -> Result<Vec<TemplateSubscription<TemplateParameters>>> {
+        self.media_container
+            .subscription_template
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                PlexError::PlexResponse("Expected single SubscriptionTemplate body".into())
+            })?
+            .media_subscription
+            .into_iter()
+            .map(|s| {
+                let ts = TemplateSubscription::<TemplateParameters> {
+                    parameters: parse_template_parameters(&s.parameters)?,
+                    r#type: s.r#type,
+                    target_section_location_id: s.target_section_location_id,
+                    setting: s.setting,
+                };
+                Ok::<_, PlexError>(ts)
+            })
+            .collect()
+    }
+}
+
+/// A recording rule, either for a single airing or a whole show
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaSubscription {
+    pub key: String,
+    pub title: Option<String>,
+    pub r#type: i64,
+    pub created_at: Option<i64>,
+    pub library_section_title: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+struct SubscriptionsContainer {
+    #[serde(rename = "MediaSubscription", default)]
+    media_subscription: Vec<MediaSubscription>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SubscriptionsResponse {
+    #[serde(rename = "MediaContainer")]
+    media_container: SubscriptionsContainer,
+}
+
+/// Reads channels saved from the lineup resource, e.g. by `dump`
+pub fn parse_channels(json: &str) -> Result<Vec<Channel>> {
+    let response: ChannelResponse = serde_json::from_str(json)?;
+    Ok(response.media_container.channel)
+}
+
+/// Reads airings saved from the grid resource, e.g. by `dump`
+pub fn parse_grid(json: &str) -> Result<Vec<GridMetadata>> {
+    let response: GridResponse = serde_json::from_str(json)?;
+    Ok(response.media_container.metadata.unwrap_or_default())
+}
+
+/// Reads the libraries from a saved providers response
+pub fn parse_providers(json: &str) -> Result<Vec<ProvidersMediaProvider>> {
+    let response: ProvidersResponse = serde_json::from_str(json)?;
+    Ok(response.media_container.media_provider)
+}
+
+/// Reads a saved subscription template response, decoding its parameters
+pub fn parse_template(json: &str) -> Result<Vec<TemplateSubscription<TemplateParameters>>> {
+    let response: TemplateResponse = serde_json::from_str(json)?;
+    response.into_templates()
+}
+
+/// Decodes a template's parameters, a query string that's been URL encoded again
+pub fn parse_template_parameters(parameters: &str) -> Result<TemplateParameters> {
+    let decoded = urlencoding::decode(parameters)
+        .map_err(|_| PlexError::PlexResponse("Couldn't decode parameters".into()))?;
+    Ok(serde_qs::from_str(&decoded)?)
+}
+
+/// Query string creating a subscription. Plex silently ignores ones encoded
+/// differently to its web client's, so this is pinned by contract tests.
+/// Values are encoded once, with spaces as `%20`, the way Plex encodes the
+/// hints and params it hands out in templates.
+pub fn subscription_query(subscription: &Subscription) -> String {
+    // serde_qs encodes a literal `+` as `%2B`, so any left are spaces
+    serde_qs::to_string(subscription)
+        .expect("subscription is not serializable")
+        .replace('+', "%20")
+}
+
+pub fn parse_subscriptions(json: &str) -> Result<Vec<MediaSubscription>> {
+    let response: SubscriptionsResponse = serde_json::from_str(json)?;
+    Ok(response.media_container.media_subscription)
+}
+
+/// Reads library items from a saved metadata response
+pub fn parse_metadata(json: &str) -> Result<Vec<LibraryMetadata>> {
+    let response: MetadataResponse = serde_json::from_str(json)?;
+    Ok(response.media_container.metadata)
+}
+
+/// Reads the server's token from its `Preferences.xml`
+pub fn parse_preferences(xml: &str) -> Result<String> {
+    let prefs: Preferences = from_str(xml)?;
+    log::debug!("Prefs: {:?}", prefs);
+    // Left empty until the server is claimed
+    if prefs.plex_online_token.is_empty() {
+        return Err(PlexError::EmptyToken);
+    }
+    Ok(prefs.plex_online_token)
+}
+
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct Preferences {
     plex_online_token: String,
 }
 
+impl std::fmt::Debug for Preferences {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Preferences")
+            .field("plex_online_token", &REDACTED)
+            .finish()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct ChannelResponse {
@@ -103,9 +357,80 @@ struct ChannelContainer {
     pub channel: Vec<Channel>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+#[serde(rename_all = "camelCase")]
 pub struct Channel {
     pub id: String,
+    pub identifier: Option<String>,
+    pub title: Option<String>,
+    pub call_sign: Option<String>,
+    /// Channel number, as shown in the guide
+    pub channel_vcn: Option<String>,
+}
+
+impl Channel {
+    /// Whether the channel is selected by a list of ids or identifiers,
+    /// where an empty list selects everything
+    pub fn selected_by(&self, channels: &[String]) -> bool {
+        channels.is_empty()
+            || channels.contains(&self.id)
+            || self
+                .identifier
+                .as_ref()
+                .is_some_and(|i| channels.contains(i))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DvrsResponse {
+    media_container: DvrsContainer,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DvrsContainer {
+    #[serde(default)]
+    dvr: Vec<Dvr>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dvr {
+    pub key: String,
+    #[serde(rename = "Device", default)]
+    pub devices: Vec<Device>,
+}
+
+/// A tuner device, and which of its channels are mapped to the lineup
+#[derive(Debug, Clone, Deserialize)]
+pub struct Device {
+    pub key: String,
+    #[serde(rename = "ChannelMapping", default)]
+    pub channel_mappings: Vec<ChannelMapping>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelMapping {
+    pub channel_key: String,
+    /// The tuner's own number for the channel
+    pub device_identifier: String,
+    /// `"1"` or `"0"`
+    pub enabled: String,
+    /// The lineup's identifier for the channel, as in the grid
+    pub lineup_identifier: Option<String>,
+}
+
+impl ChannelMapping {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled == "1"
+    }
+
+    /// Whether the mapping is for a channel, given by its lineup identifier
+    pub fn is_for(&self, channel: &str) -> bool {
+        self.lineup_identifier.as_deref() == Some(channel)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -120,7 +445,7 @@ struct GridContainer {
     metadata: Option<Vec<GridMetadata>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum GridMetadataType {
     Movie,
@@ -135,10 +460,12 @@ impl From<GridMetadataType> for u8 {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GridMetadata {
     pub rating_key: String,
+    /// Needed to subscribe, but missing from the odd guide entry, so empty then
+    #[serde(default)]
     pub guid: String,
     pub title: String,
     pub grandparent_guid: Option<String>,
@@ -148,7 +475,8 @@ pub struct GridMetadata {
     pub parent_index: Option<u64>,
     pub index: Option<u64>,
     pub r#type: GridMetadataType,
-    pub duration: u32,
+    /// Milliseconds, when the guide gives it
+    pub duration: Option<u32>,
     pub on_air: Option<bool>,
     #[serde(rename = "subscriptionID")]
     pub subscription_id: Option<String>,
@@ -158,6 +486,7 @@ pub struct GridMetadata {
     pub grandparent_subscription_type: Option<String>,
     pub grandparent_thumb: Option<String>,
     pub originally_available_at: Option<String>,
+    pub summary: Option<String>,
     #[serde(rename = "Media")]
     pub media: Vec<GridMedia>,
 }
@@ -167,17 +496,40 @@ impl GridMetadata {
         self.media.first().map_or(0, |m| m.begins_at)
     }
 
+    /// `None` if there's no media, or its start is too far out to be a time
     pub fn begins_at(&self) -> Option<DateTime<Utc>> {
-        self.media.first().map(|m| Utc.timestamp(m.begins_at, 0))
+        self.media
+            .first()
+            .and_then(|m| DateTime::from_timestamp(m.begins_at, 0))
     }
 
     pub fn show_title(&self) -> String {
         let gt = &self.grandparent_title;
         gt.clone().unwrap_or_else(|| self.title.clone())
     }
+
+    /// Whether the airing or its show already has a subscription
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription_id.is_some() || self.grandparent_subscription_id.is_some()
+    }
+
+    /// Whether it's the first episode of a series, S01E01
+    pub fn is_series_premiere(&self) -> bool {
+        !matches!(self.r#type, GridMetadataType::Movie)
+            && self.parent_index == Some(1)
+            && self.index == Some(1)
+    }
+
+    /// Release year, from the original air date
+    pub fn year(&self) -> Option<i32> {
+        self.originally_available_at
+            .as_ref()
+            .and_then(|d| d.get(..4))
+            .and_then(|y| y.parse().ok())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GridMedia {
     pub id: u64,
@@ -187,6 +539,83 @@ pub struct GridMedia {
     pub channel_title: String,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetadataResponse {
+    media_container: MetadataContainer,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetadataContainer {
+    #[serde(default)]
+    metadata: Vec<LibraryMetadata>,
+}
+
+/// An item in a library, as opposed to an airing in the guide
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryMetadata {
+    pub rating_key: String,
+    pub r#type: Option<String>,
+    pub grandparent_rating_key: Option<String>,
+    #[serde(rename = "librarySectionID")]
+    pub library_section_id: Option<serde_json::Value>,
+    #[serde(rename = "Media", default)]
+    pub media: Vec<LibraryMedia>,
+    #[serde(rename = "Marker", default)]
+    pub markers: Vec<Marker>,
+    #[serde(rename = "Label", default)]
+    pub labels: Vec<Tag>,
+    #[serde(rename = "Collection", default)]
+    pub collections: Vec<Tag>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tag {
+    pub tag: String,
+}
+
+impl LibraryMetadata {
+    /// Total size of the files on disk
+    pub fn size(&self) -> i64 {
+        self.media
+            .iter()
+            .flat_map(|m| &m.parts)
+            .filter_map(|p| p.size)
+            .sum()
+    }
+
+    /// Path of the first file, as Plex sees it
+    pub fn file(&self) -> Option<&str> {
+        self.media
+            .iter()
+            .flat_map(|m| &m.parts)
+            .find_map(|p| p.file.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LibraryMedia {
+    #[serde(rename = "Part", default)]
+    pub parts: Vec<MediaPart>,
+}
+
+/// Intro, credits or commercial, with offsets in milliseconds
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Marker {
+    pub r#type: String,
+    pub start_time_offset: u64,
+    pub end_time_offset: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MediaPart {
+    pub file: Option<String>,
+    pub size: Option<i64>,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum ProviderDirectoryType {
@@ -229,17 +658,18 @@ pub struct ProvidersContainer {
 }
 
 pub trait ProvidersMediaProviders {
+    /// The library's directories of a type, borrowed from the providers
     fn get_dirs_of_type(
         &self,
         dir_type: ProviderDirectoryType,
-    ) -> Result<Vec<ProviderDirectory>, PlexError>;
+    ) -> Result<Vec<&ProviderDirectory>, PlexError>;
 }
 
-impl ProvidersMediaProviders for Vec<ProvidersMediaProvider> {
+impl ProvidersMediaProviders for [ProvidersMediaProvider] {
     fn get_dirs_of_type(
         &self,
         dir_type: ProviderDirectoryType,
-    ) -> Result<Vec<ProviderDirectory>, PlexError> {
+    ) -> Result<Vec<&ProviderDirectory>, PlexError> {
         let dirs = self
             .iter()
             .find(|p| p.identifier == "com.plexapp.plugins.library")
@@ -247,13 +677,12 @@ impl ProvidersMediaProviders for Vec<ProvidersMediaProvider> {
             .feature
             .first()
             .ok_or_else(|| PlexError::PlexResponse("Plex library has no features".into()))?
-            .clone()
             .directory
+            .as_ref()
             .ok_or_else(|| PlexError::PlexResponse("Plex library has no dirs".into()))?
             .iter()
-            .filter(|d| d.r#type.clone().map_or(false, |t| t == dir_type))
-            .cloned()
-            .collect::<Vec<_>>();
+            .filter(|d| d.r#type.as_ref() == Some(&dir_type))
+            .collect();
         Ok(dirs)
     }
 }
@@ -264,18 +693,6 @@ pub struct ProvidersResponse {
     media_container: ProvidersContainer,
 }
 
-fn urlencode<S: Serializer>(x: &str, s: S) -> Result<S::Ok, S::Error> {
-    let encoded = urlencoding::encode(x);
-    s.serialize_str(&encoded)
-}
-
-fn maybe_urlencode<S: Serializer>(x: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
-    match x {
-        Some(ref val) => s.serialize_str(val),
-        None => s.serialize_none()
-    }
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionPrefs {
@@ -295,35 +712,138 @@ pub struct SubscriptionPrefs {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionHints {
-    #[serde(serialize_with = "maybe_urlencode")]
     pub grandparent_guid: Option<String>,
     pub grandparent_thumb: Option<String>,
     pub grandparent_title: Option<String>,
-    #[serde(serialize_with = "urlencode")]
     pub guid: String,
     pub index: Option<String>,
     pub originally_available_at: Option<String>,
-    #[serde(serialize_with = "maybe_urlencode")]
     pub parent_guid: Option<String>,
     pub parent_index: Option<String>,
     pub parent_title: Option<String>,
-    #[serde(serialize_with = "urlencode")]
     pub rating_key: String,
     pub title: String,
     pub r#type: String,
 }
 
+/// Lineup channel identifiers a subscription records from, which Plex lists
+/// comma separated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct AiringChannels(Vec<String>);
+
+impl AiringChannels {
+    pub fn new(identifiers: Vec<String>) -> Self {
+        AiringChannels(identifiers)
+    }
+
+    pub fn identifiers(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn encode(&self) -> String {
+        self.0.join(",")
+    }
+
+    pub fn decode(encoded: &str) -> Self {
+        AiringChannels(
+            encoded
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+}
+
+impl From<String> for AiringChannels {
+    fn from(encoded: String) -> Self {
+        AiringChannels::decode(&encoded)
+    }
+}
+
+impl From<AiringChannels> for String {
+    fn from(channels: AiringChannels) -> Self {
+        channels.encode()
+    }
+}
+
+/// Timestamps of the slots a subscription records, which Plex lists comma
+/// separated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AiringTimes(Vec<i64>);
+
+impl AiringTimes {
+    pub fn new(slots: Vec<i64>) -> Self {
+        AiringTimes(slots)
+    }
+
+    pub fn slots(&self) -> &[i64] {
+        &self.0
+    }
+
+    pub fn contains(&self, slot: i64) -> bool {
+        self.0.contains(&slot)
+    }
+
+    pub fn encode(&self) -> String {
+        self.0
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self> {
+        encoded
+            .split(',')
+            .filter(|t| !t.is_empty())
+            .map(|t| {
+                t.parse()
+                    .map_err(|_| PlexError::PlexResponse(format!("Bad airing time {}", t)))
+            })
+            .collect::<Result<_>>()
+            .map(AiringTimes)
+    }
+}
+
+impl TryFrom<String> for AiringTimes {
+    type Error = PlexError;
+
+    fn try_from(encoded: String) -> Result<Self> {
+        AiringTimes::decode(&encoded)
+    }
+}
+
+impl From<AiringTimes> for String {
+    fn from(times: AiringTimes) -> Self {
+        times.encode()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscriptionParams {
-    #[serde(serialize_with = "urlencode")]
-    pub airing_channels: String,
-    pub airing_times: String,
+    pub airing_channels: AiringChannels,
+    pub airing_times: AiringTimes,
     pub library_type: String, // 2 = tv show?
     #[serde(rename = "mediaProviderID")]
     pub media_provider_id: String, // ??
 }
 
+impl SubscriptionParams {
+    /// These params, aimed at just the one airing of `media` rather than
+    /// whichever the template was fetched for
+    pub fn targeting(&self, media: &GridMedia) -> Self {
+        SubscriptionParams {
+            airing_channels: AiringChannels::new(vec![media.channel_identifier.clone()]),
+            airing_times: AiringTimes::new(vec![media.begins_at]),
+            ..self.clone()
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Subscription {
@@ -338,31 +858,212 @@ pub struct Subscription {
     pub r#type: String,
 }
 
+impl Subscription {
+    /// Records just `media`, with the template's defaults for everything else
+    pub fn one_shot(
+        template: &TemplateSubscription<TemplateParameters>,
+        media: &GridMedia,
+        library_id: &str,
+    ) -> Result<Self> {
+        Ok(Subscription {
+            prefs: SubscriptionPrefs {
+                min_video_quality: template.setting_or_default("minVideoQuality")?,
+                replace_lower_quality: template.setting_or_default("replaceLowerQuality")?,
+                record_partials: template.setting_or_default("recordPartials")?,
+                start_offset_minutes: 0,
+                end_offset_minutes: 4,
+                lineup_channel: media.channel_identifier.clone(),
+                start_timeslot: media.begins_at,
+                comskip_enabled: template.setting_or_default("comskipEnabled")?,
+                comskip_method: template.setting_or_default("comskipMethod")?,
+                one_shot: "true".into(),
+                remote_media: template.setting_or_default("remoteMedia")?,
+            },
+            hints: template.parameters.hints.clone(),
+            params: template.parameters.params.targeting(media),
+            target_library_section_id: library_id.into(),
+            target_section_location_id: "".into(),
+            include_grabs: 1,
+            r#type: i64::from(template.r#type).to_string(),
+        })
+    }
+}
+
+/// Caps the requests in flight, and paces retries of ones that failed
+struct RequestLimit {
+    permits: Semaphore,
+    retry_delay: Duration,
+}
+
+impl RequestLimit {
+    fn new(retry_delay: Duration) -> Arc<Self> {
+        Arc::new(RequestLimit {
+            permits: Semaphore::new(MAX_CONCURRENT_REQUESTS),
+            retry_delay,
+        })
+    }
+}
+
 #[async_trait]
 trait RequestBuilderLimited {
-    async fn send_limited(self, limit: Arc<Semaphore>)
-        -> Result<reqwest::Response, reqwest::Error>;
+    async fn send_limited(
+        self,
+        limit: Arc<RequestLimit>,
+    ) -> Result<reqwest::Response, reqwest::Error>;
+}
+
+/// Wait asked for by a throttled response, if it gave one in seconds
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
 }
 
 #[async_trait]
 impl RequestBuilderLimited for RequestBuilder {
+    /// Sends once a permit is free. Requests other than POSTs are retried
+    /// when Plex is throttling, unavailable or unreachable.
     async fn send_limited(
         self,
-        limit: Arc<Semaphore>,
+        limit: Arc<RequestLimit>,
     ) -> Result<reqwest::Response, reqwest::Error> {
-        let _permit = limit.acquire_owned().await.unwrap();
-        self.send().await
+        let (client, request) = self.build_split();
+        let mut request = request?;
+        let repeatable = request.method() != reqwest::Method::POST;
+        let mut delay = limit.retry_delay;
+        let mut attempt = 1;
+        loop {
+            let retry = request
+                .try_clone()
+                .filter(|_| repeatable && attempt < REQUEST_ATTEMPTS);
+            let result = {
+                let _permit = limit.permits.acquire().await.unwrap();
+                client.execute(request).await
+            };
+            let (wait, retry) = match (&result, retry) {
+                (Ok(response), Some(retry))
+                    if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        || response.status().is_server_error() =>
+                {
+                    (retry_after(response).unwrap_or(delay), retry)
+                }
+                (Err(e), Some(retry)) if e.is_connect() || e.is_timeout() => (delay, retry),
+                _ => return result,
+            };
+            log::debug!(
+                "Plex request failed ({}), retrying in {:?}",
+                match &result {
+                    Ok(response) => response.status().to_string(),
+                    Err(e) => e.to_string(),
+                },
+                wait
+            );
+            tokio::time::sleep(wait).await;
+            request = retry;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// Hands response chunks to a blocking reader, so JSON can be parsed as it
+/// arrives rather than after the whole body has been buffered
+struct ChunkReader {
+    chunks: mpsc::Receiver<std::io::Result<Bytes>>,
+    current: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.current = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.current.len());
+        buf[..n].copy_from_slice(&self.current.split_to(n));
+        Ok(n)
     }
 }
 
+/// Deserializes a large JSON response while it downloads. Only a few chunks are
+/// held at once, which keeps peak memory down on busy channels' grids.
+async fn stream_json<T: DeserializeOwned + Send + 'static>(
+    mut response: reqwest::Response,
+) -> Result<T> {
+    let (tx, rx) = mpsc::channel(STREAM_CHUNKS);
+    let parse = tokio::task::spawn_blocking(move || {
+        serde_json::from_reader(BufReader::new(ChunkReader {
+            chunks: rx,
+            current: Bytes::new(),
+        }))
+    });
+    while let Some(chunk) = response.chunk().await.transpose() {
+        let chunk = chunk.map_err(|e| std::io::Error::other(PlexError::from(e)));
+        // The parser only hangs up early when it has already failed
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+    let parsed = parse
+        .await
+        .map_err(|e| PlexError::PlexResponse(e.to_string()))??;
+    Ok(parsed)
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum PlexHost {
     Localhost,
     Custom(String),
 }
+
+impl PlexHost {
+    /// Parses a configured server URL, assuming `http://` if there's no scheme,
+    /// and dropping trailing slashes so resources join onto it cleanly
+    pub fn parse(url: &str) -> Result<PlexHost> {
+        let invalid = |reason: String| PlexError::InvalidUrl {
+            url: url.to_string(),
+            reason,
+        };
+        let trimmed = url.trim();
+        if trimmed.is_empty() {
+            return Err(invalid("it's empty".into()));
+        }
+        let with_scheme = if trimmed.contains("://") {
+            trimmed.to_string()
+        } else {
+            format!("http://{}", trimmed)
+        };
+        let parsed = reqwest::Url::parse(&with_scheme).map_err(|e| invalid(e.to_string()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(invalid(format!(
+                "the scheme must be http or https, not {}",
+                parsed.scheme()
+            )));
+        }
+        if parsed.host_str().is_none_or(str::is_empty) {
+            return Err(invalid("there's no host".into()));
+        }
+        if parsed.query().is_some() || parsed.fragment().is_some() {
+            return Err(invalid("it can't have a query or fragment".into()));
+        }
+        // Kept without its trailing slash, but with any path a proxy serves Plex under
+        let normalized = parsed.as_str().trim_end_matches('/').to_string();
+        Ok(PlexHost::Custom(normalized))
+    }
+}
+
 pub struct Plex {
     token: String,
     client: reqwest::Client,
-    req_limit: Arc<Semaphore>,
+    req_limit: Arc<RequestLimit>,
     host: String,
 }
 
@@ -370,41 +1071,101 @@ impl Plex {
     pub fn new(prefs_path: Option<String>, host: PlexHost) -> Result<Plex> {
         let prefs_path = prefs_path.unwrap_or_else(|| PREFS_PATH.to_string());
         let prefs_str = std::fs::read_to_string(prefs_path)?;
-        let prefs: Preferences = from_str(&prefs_str)?;
-
-        log::debug!("Prefs: {:?}", prefs);
-
-        let client = reqwest::Client::new();
+        Ok(Plex::with_token(parse_preferences(&prefs_str)?, host))
+    }
 
-        Ok(Plex {
-            token: prefs.plex_online_token,
+    /// Connects with a token given directly, for servers whose preferences aren't mounted
+    pub fn with_token(token: String, host: PlexHost) -> Plex {
+        Plex {
+            token,
             host: match host {
                 PlexHost::Localhost => "http://localhost:32400".to_string(),
                 PlexHost::Custom(host) => host,
             },
-            client,
-            req_limit: Arc::new(Semaphore::new(5)),
-        })
+            client: reqwest::Client::builder()
+                // Enough idle connections for every permitted request to reuse one
+                .pool_max_idle_per_host(MAX_CONCURRENT_REQUESTS)
+                .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+                .tcp_keepalive(TCP_KEEPALIVE)
+                .tcp_nodelay(true)
+                // HTTPS servers offering HTTP/2 multiplex everything over one connection
+                .http2_adaptive_window(true)
+                .http2_keep_alive_interval(TCP_KEEPALIVE)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Plex client is valid"),
+            req_limit: RequestLimit::new(RETRY_DELAY),
+        }
+    }
+
+    /// Waits `delay` before retrying a failed request, doubling each attempt
+    pub fn with_retry_delay(self, delay: Duration) -> Plex {
+        Plex {
+            req_limit: RequestLimit::new(delay),
+            ..self
+        }
     }
 
     pub fn get(&self, resource: &str) -> RequestBuilder {
+        reporting::plex_request("GET", resource);
         self.client
-            .get(&format!("{}/{}", self.host, resource))
-            .query(&[("X-Plex-Token", &self.token)])
+            .get(format!("{}/{}", self.host, resource))
+            .query(&[(TOKEN_PARAM, &self.token)])
             .header("accept", "application/json")
     }
 
     pub fn post(&self, resource: &str) -> RequestBuilder {
+        reporting::plex_request("POST", resource);
         self.client
-            .post(&format!("{}/{}", self.host, resource))
-            .query(&[("X-Plex-Token", &self.token)])
+            .post(format!("{}/{}", self.host, resource))
+            .query(&[(TOKEN_PARAM, &self.token)])
             .header("accept", "application/json")
     }
 
+    pub fn put(&self, resource: &str) -> RequestBuilder {
+        reporting::plex_request("PUT", resource);
+        self.client
+            .put(format!("{}/{}", self.host, resource))
+            .query(&[(TOKEN_PARAM, &self.token)])
+            .header("accept", "application/json")
+    }
+
+    pub fn delete(&self, resource: &str) -> RequestBuilder {
+        reporting::plex_request("DELETE", resource);
+        self.client
+            .delete(format!("{}/{}", self.host, resource))
+            .query(&[(TOKEN_PARAM, &self.token)])
+            .header("accept", "application/json")
+    }
+
+    /// Fetches a resource without interpreting it, for diagnostics
+    pub async fn get_raw(
+        &self,
+        resource: &str,
+        query: &[(&str, &str)],
+    ) -> Result<serde_json::Value> {
+        let value = self
+            .get(resource)
+            .query(query)
+            .send_limited(self.req_limit.clone())
+            .await?
+            .json()
+            .await?;
+        Ok(value)
+    }
+
+    /// Removes the token from text that may be shared with others
+    pub fn redact(&self, text: &str) -> String {
+        // Replacing nothing would put the placeholder between every character
+        if self.token.is_empty() {
+            return text.to_string();
+        }
+        text.replace(&self.token, REDACTED)
+    }
+
     pub async fn get_providers(&self) -> Result<Vec<ProvidersMediaProvider>> {
-        const RESOURCE: &str = "media/providers";
         let providers: ProvidersResponse = self
-            .get(RESOURCE)
+            .get(PROVIDERS_RESOURCE)
             .send_limited(self.req_limit.clone())
             .await?
             .json()
@@ -412,10 +1173,59 @@ impl Plex {
         Ok(providers.media_container.media_provider)
     }
 
+    /// The server's time, from the `Date` header of its identity, which is
+    /// cheap enough to ask for whenever the clocks need comparing
+    pub async fn server_time(&self) -> Result<Option<DateTime<Utc>>> {
+        let response = self
+            .get(IDENTITY_RESOURCE)
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?;
+        let time = response
+            .headers()
+            .get(reqwest::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(|date| date.to_utc());
+        Ok(time)
+    }
+
+    pub async fn get_dvrs(&self) -> Result<Vec<Dvr>> {
+        let response: DvrsResponse = self
+            .get(DVRS_RESOURCE)
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.media_container.dvr)
+    }
+
+    /// Replaces a device's channel mapping, enabling only the channels with
+    /// the `enabled` device identifiers
+    pub async fn set_channel_map(&self, device: &Device, enabled: &[&str]) -> Result<()> {
+        let mut query = vec![("channelsEnabled".to_string(), enabled.join(","))];
+        for mapping in &device.channel_mappings {
+            let id = &mapping.device_identifier;
+            if let Some(lineup_identifier) = &mapping.lineup_identifier {
+                query.push((format!("channelMapping[{}]", id), lineup_identifier.clone()));
+            }
+            query.push((
+                format!("channelMappingByKey[{}]", id),
+                mapping.channel_key.clone(),
+            ));
+        }
+        self.put(&format!("media/grabbers/devices/{}/channelmap", device.key))
+            .query(&query)
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     pub async fn get_channels(&self) -> Result<Vec<Channel>> {
-        const RESOURCE: &str = "tv.plex.providers.epg.xmltv:2/lineups/dvr/channels";
         let container: ChannelResponse = self
-            .get(RESOURCE)
+            .get(CHANNELS_RESOURCE)
             .send_limited(self.req_limit.clone())
             .await?
             .json()
@@ -428,17 +1238,74 @@ impl Plex {
         channel_grid_key: &str,
         date: &str,
     ) -> Result<Option<Vec<GridMetadata>>> {
-        const RESOURCE: &str = "tv.plex.providers.epg.xmltv:2/grid";
-        let container: GridResponse = self
-            .get(RESOURCE)
+        let response = self
+            .get(GRID_RESOURCE)
             .query(&[("channelGridKey", channel_grid_key), ("date", date)])
             .send_limited(self.req_limit.clone())
-            .await?
-            .json()
             .await?;
+        let container: GridResponse = stream_json(response).await?;
         Ok(container.media_container.metadata)
     }
 
+    /// Returns `None` if the item is no longer in the library
+    pub async fn get_metadata(&self, rating_key: &str) -> Result<Option<LibraryMetadata>> {
+        let response = self
+            .get(&format!("library/metadata/{}", rating_key))
+            .query(&[("includeMarkers", "1")])
+            .send_limited(self.req_limit.clone())
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let container: MetadataResponse = response.error_for_status()?.json().await?;
+        Ok(container.media_container.metadata.into_iter().next())
+    }
+
+    /// Asks Plex to rescan part of a library, e.g. after a file was replaced
+    pub async fn scan_path(&self, section_id: &str, path: &str) -> Result<()> {
+        self.get(&format!("library/sections/{}/refresh", section_id))
+            .query(&[("path", path)])
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Replaces an item's labels and collections
+    pub async fn set_tags(
+        &self,
+        section_id: &str,
+        item_type: u8,
+        rating_key: &str,
+        labels: &[String],
+        collections: &[String],
+    ) -> Result<()> {
+        let mut query = vec![
+            ("type".to_string(), item_type.to_string()),
+            ("id".to_string(), rating_key.to_string()),
+        ];
+        for (field, tags) in [("label", labels), ("collection", collections)] {
+            for (i, tag) in tags.iter().enumerate() {
+                query.push((format!("{}[{}].tag.tag", field, i), tag.clone()));
+            }
+        }
+        self.put(&format!("library/sections/{}/all", section_id))
+            .query(&query)
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Deletes an item and its files. Plex must be set to allow media deletion.
+    pub async fn delete_metadata(&self, rating_key: &str) -> Result<()> {
+        self.delete(&format!("library/metadata/{}", rating_key))
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     pub async fn get_subscription_template(
         &self,
         guid: &str,
@@ -453,45 +1320,60 @@ impl Plex {
             .json()
             .await?;
 
-        template_response
-            .media_container
-            .subscription_template
-            .into_iter()
-            .next()
-            .ok_or_else(|| {
-                PlexError::PlexResponse("Expected single SubscriptionTemplate body".into())
-            })?
-            .media_subscription
-            .into_iter()
-            .map(|s| {
-                let decoded = urlencoding::decode(&s.parameters)
-                    .map_err(|_| PlexError::PlexResponse("Couldn't decode parameters".into()))?;
-                let ts = TemplateSubscription::<TemplateParameters> {
-                    parameters: serde_qs::from_str(&decoded)?,
-                    r#type: s.r#type,
-                    target_section_location_id: s.target_section_location_id,
-                    setting: s.setting,
-                };
-                Ok::<_, PlexError>(ts)
-            })
-            .collect()
+        template_response.into_templates()
+    }
+
+    pub async fn get_subscriptions(&self) -> Result<Vec<MediaSubscription>> {
+        let response: SubscriptionsResponse = self
+            .get("media/subscriptions")
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.media_container.media_subscription)
+    }
+
+    pub async fn delete_subscription(&self, id: &str) -> Result<()> {
+        self.delete(&format!("media/subscriptions/{}", id))
+            .send_limited(self.req_limit.clone())
+            .await?
+            .error_for_status()?;
+        Ok(())
     }
 
     pub async fn create_subscription(&self, subscription: &Subscription) -> Result<()> {
         const RESOURCE: &str = "media/subscriptions";
-        let query = serde_qs::to_string(subscription).expect("subscription is not serializable");
+        let query = subscription_query(subscription);
 
         log::debug!("Send {} to {}", query, RESOURCE);
 
-        let result = self.post(&format!("{}?{}", RESOURCE, query))
+        let result = self
+            .post(&format!("{}?{}", RESOURCE, query))
             .send_limited(self.req_limit.clone())
             .await?;
 
+        if result.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(PlexError::Throttled);
+        }
         if result.status().is_client_error() {
-            let err = format!("Plex returned an error: {}. Body: {}", result.status(), result.text().await?);
+            let err = format!(
+                "Plex returned an error: {}. Body: {}",
+                result.status(),
+                self.redact(&result.text().await?)
+            );
             log::debug!("{}", err);
             return Err(PlexError::PlexResponse(err));
         }
+        // The subscription may not have been made, so it's failed to be retried
+        if !result.status().is_success() {
+            let status = result.status();
+            result.error_for_status_ref()?;
+            return Err(PlexError::PlexResponse(format!(
+                "Plex returned {} when subscribing",
+                status
+            )));
+        }
 
         Ok(())
     }
@@ -0,0 +1,107 @@
+use super::{PostProcessError, Recording, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::process::Command;
+
+const DEFAULT_FFMPEG: &str = "ffmpeg";
+
+/// Container to remux recordings into
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mkv,
+    Mp4,
+}
+
+impl Container {
+    fn extension(self) -> &'static str {
+        match self {
+            Container::Mkv => "mkv",
+            Container::Mp4 => "mp4",
+        }
+    }
+}
+
+/// Remuxes, and optionally transcodes, recordings in place
+pub struct Ffmpeg {
+    path: String,
+    container: Container,
+    codec: Option<String>,
+    crf: Option<u8>,
+}
+
+impl Ffmpeg {
+    pub fn new(
+        path: Option<String>,
+        container: Container,
+        codec: Option<String>,
+        crf: Option<u8>,
+    ) -> Self {
+        Ffmpeg {
+            path: path.unwrap_or_else(|| DEFAULT_FFMPEG.to_string()),
+            container,
+            codec,
+            crf,
+        }
+    }
+
+    fn args(&self, input: &Path, output: &Path) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-nostdin".into(), "-y".into(), "-i".into()];
+        args.push(input.to_string_lossy().into_owned());
+
+        // MP4 can't carry DVB subtitles or teletext, so only keep audio and video
+        args.extend(["-map", "0:v", "-map", "0:a"].map(String::from));
+        if self.container == Container::Mkv {
+            args.extend(["-map", "0:s?"].map(String::from));
+        }
+
+        match &self.codec {
+            Some(codec) => {
+                args.extend(["-c:v".to_string(), codec.clone()]);
+                if let Some(crf) = self.crf {
+                    args.extend(["-crf".to_string(), crf.to_string()]);
+                }
+                args.extend(["-c:a", "copy", "-c:s", "copy"].map(String::from));
+            }
+            None => args.extend(["-c", "copy"].map(String::from)),
+        }
+        if self.container == Container::Mp4 {
+            args.extend(["-movflags", "+faststart"].map(String::from));
+        }
+
+        args.push(output.to_string_lossy().into_owned());
+        args
+    }
+
+    /// Replaces the recording with the converted file, updating its path
+    pub async fn run(&self, recording: &mut Recording) -> Result<()> {
+        let extension = self.container.extension();
+        let output = recording.path.with_extension(extension);
+        // Written alongside so a failed conversion never touches the original
+        let partial = recording
+            .path
+            .with_extension(format!("partial.{}", extension));
+
+        log::info!("Converting {} to {}", recording.title, extension);
+        let result = Command::new(&self.path)
+            .args(self.args(&recording.path, &partial))
+            .output()
+            .await?;
+        if !result.status.success() {
+            let _ = tokio::fs::remove_file(&partial).await;
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(PostProcessError::Command(format!(
+                "ffmpeg exited with {}: {}",
+                result.status,
+                stderr.lines().last().unwrap_or_default()
+            )));
+        }
+
+        tokio::fs::rename(&partial, &output).await?;
+        if output != recording.path {
+            tokio::fs::remove_file(&recording.path).await?;
+        }
+        recording.path = output;
+        Ok(())
+    }
+}
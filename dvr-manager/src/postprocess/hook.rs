@@ -0,0 +1,33 @@
+use super::{PostProcessError, Recording, Result};
+use tokio::process::Command;
+
+/// Runs a user's shell command with details of the recording in the environment
+pub struct Hook {
+    command: String,
+}
+
+impl Hook {
+    pub fn new(command: String) -> Self {
+        Hook { command }
+    }
+
+    pub async fn run(&self, recording: &Recording) -> Result<()> {
+        log::info!("Running post-record command for {}", recording.title);
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("DVR_FILE", &recording.path)
+            .env("DVR_TITLE", &recording.title)
+            .env("DVR_CHANNEL", recording.channel.as_deref().unwrap_or(""))
+            .env("DVR_RATING_KEY", &recording.rating_key)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(PostProcessError::Command(format!(
+                "Post-record command exited with {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use super::{PostProcessError, Recording, Result};
+use crate::plex::Marker;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::Path;
+use tokio::process::Command;
+
+const DEFAULT_COMSKIP: &str = "comskip";
+/// Plex's name for ad breaks found by commercial detection
+const COMMERCIAL_MARKER: &str = "commercial";
+/// EDL action that tells players to skip the segment
+const EDL_COMMERCIAL_BREAK: u8 = 3;
+
+/// Where to find the ad breaks in a recording
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerSource {
+    /// Run comskip on the file
+    Comskip,
+    /// Use the markers from Plex's own commercial detection
+    Plex,
+}
+
+/// A stretch of the recording, in seconds
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: f64,
+    end: f64,
+}
+
+/// Writes EDL and chapter files next to recordings so players can skip ads
+pub struct SkipMarkers {
+    source: MarkerSource,
+    comskip_path: String,
+    comskip_ini: Option<String>,
+    channels: Vec<String>,
+}
+
+impl SkipMarkers {
+    pub fn new(
+        source: MarkerSource,
+        comskip_path: Option<String>,
+        comskip_ini: Option<String>,
+        channels: Vec<String>,
+    ) -> Self {
+        SkipMarkers {
+            source,
+            comskip_path: comskip_path.unwrap_or_else(|| DEFAULT_COMSKIP.to_string()),
+            comskip_ini,
+            channels: channels.iter().map(|c| c.to_lowercase()).collect(),
+        }
+    }
+
+    /// Whether recordings from this channel should be marked, all are if no channels are set
+    pub fn wants(&self, recording: &Recording) -> bool {
+        self.channels.is_empty()
+            || recording
+                .channel
+                .as_ref()
+                .is_some_and(|c| self.channels.contains(&c.to_lowercase()))
+    }
+
+    pub async fn run(&self, recording: &Recording, markers: &[Marker]) -> Result<()> {
+        let breaks = match self.source {
+            MarkerSource::Comskip => self.comskip(&recording.path).await?,
+            MarkerSource::Plex => markers
+                .iter()
+                .filter(|m| m.r#type == COMMERCIAL_MARKER)
+                .map(|m| Segment {
+                    start: m.start_time_offset as f64 / 1000.0,
+                    end: m.end_time_offset as f64 / 1000.0,
+                })
+                .collect(),
+        };
+        log::info!("Found {} ad breaks in {}", breaks.len(), recording.title);
+
+        tokio::fs::write(recording.path.with_extension("edl"), edl(&breaks)).await?;
+        tokio::fs::write(
+            recording.path.with_extension("chapters.txt"),
+            chapters(&breaks),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn comskip(&self, path: &Path) -> Result<Vec<Segment>> {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut command = Command::new(&self.comskip_path);
+        command.arg(format!("--output={}", dir.display()));
+        if let Some(ini) = &self.comskip_ini {
+            command.arg(format!("--ini={}", ini));
+        }
+        let status = command.arg(path).status().await?;
+        // comskip exits with 1 when it finds no commercials
+        match status.code() {
+            Some(0) => (),
+            Some(1) => return Ok(Vec::new()),
+            _ => {
+                return Err(PostProcessError::Command(format!(
+                    "comskip exited with {}",
+                    status
+                )))
+            }
+        }
+
+        let edl = tokio::fs::read_to_string(path.with_extension("edl")).await?;
+        Ok(parse_edl(&edl))
+    }
+}
+
+fn parse_edl(edl: &str) -> Vec<Segment> {
+    edl.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().map(|f| f.parse::<f64>());
+            match (fields.next(), fields.next()) {
+                (Some(Ok(start)), Some(Ok(end))) => Some(Segment { start, end }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn edl(breaks: &[Segment]) -> String {
+    breaks.iter().fold(String::new(), |mut edl, b| {
+        let _ = writeln!(
+            edl,
+            "{:.2}\t{:.2}\t{}",
+            b.start, b.end, EDL_COMMERCIAL_BREAK
+        );
+        edl
+    })
+}
+
+/// OGM style chapters, alternating programme and advert, which mkvmerge and most players read
+fn chapters(breaks: &[Segment]) -> String {
+    let boundaries = breaks
+        .iter()
+        .flat_map(|b| [(b.start, "Advert"), (b.end, "Programme")]);
+    let mut starts: Vec<(f64, &str)> = Vec::new();
+    for (start, name) in [(0.0, "Programme")].into_iter().chain(boundaries) {
+        match starts.last_mut() {
+            // An advert right at a boundary replaces the chapter rather than adding an empty one
+            Some(last) if start - last.0 < 1.0 => last.1 = name,
+            _ => starts.push((start, name)),
+        }
+    }
+
+    let mut out = String::new();
+    for (i, (start, name)) in starts.iter().enumerate() {
+        let ms = (start * 1000.0) as u64;
+        let _ = writeln!(
+            out,
+            "CHAPTER{:02}={:02}:{:02}:{:02}.{:03}\nCHAPTER{:02}NAME={}",
+            i + 1,
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            ms % 1000,
+            i + 1,
+            name
+        );
+    }
+    out
+}
@@ -0,0 +1,220 @@
+mod ffmpeg;
+mod hook;
+mod markers;
+
+use crate::backend::{BackendError, DvrBackend};
+use crate::state::{self, State};
+use crate::title::TitleAliases;
+use ffmpeg::{Container, Ffmpeg};
+use futures::StreamExt;
+use hook::Hook;
+use markers::{MarkerSource, SkipMarkers};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Completed recordings waiting to be processed, beyond which new ones are dropped
+const DEFAULT_QUEUE_SIZE: usize = 32;
+/// Conversions are heavy, so by default only one recording is worked on at a time
+const DEFAULT_WORKERS: usize = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PostProcessError {
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+
+    #[error(transparent)]
+    State(#[from] state::StateError),
+
+    #[error("Couldn't run command: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Command(String),
+}
+
+pub type Result<T, E = PostProcessError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct PostProcessConfig {
+    /// Shell command run after each recording reaches the library
+    pub post_record_command: Option<String>,
+    /// Remux recordings into this container, replacing the original
+    pub remux_container: Option<Container>,
+    /// Video codec to transcode to while remuxing, e.g. `libx265`
+    pub transcode_codec: Option<String>,
+    pub transcode_crf: Option<u8>,
+    pub ffmpeg_path: Option<String>,
+    /// Write EDL and chapter files marking ad breaks
+    pub skip_markers: Option<MarkerSource>,
+    /// Only mark recordings from these channels, or all if empty
+    #[serde(default)]
+    pub skip_marker_channels: Vec<String>,
+    pub comskip_path: Option<String>,
+    pub comskip_ini: Option<String>,
+    /// Labels added to recordings in the library
+    #[serde(default)]
+    pub dvr_labels: Vec<String>,
+    /// Collections recordings are added to, `{channel}` is replaced with the channel name
+    #[serde(default)]
+    pub dvr_collections: Vec<String>,
+    pub postprocess_queue_size: Option<usize>,
+    /// Recordings processed at once
+    pub postprocess_workers: Option<usize>,
+}
+
+impl PostProcessConfig {
+    pub fn queue_size(&self) -> usize {
+        self.postprocess_queue_size.unwrap_or(DEFAULT_QUEUE_SIZE)
+    }
+}
+
+/// A recording that has just reached the library
+#[derive(Debug)]
+pub struct Completed {
+    pub rating_key: String,
+    pub title: String,
+    pub show_title: Option<String>,
+}
+
+/// A completed recording with the details steps need to work on it
+#[derive(Debug)]
+pub struct Recording {
+    pub path: PathBuf,
+    pub title: String,
+    pub channel: Option<String>,
+    pub rating_key: String,
+}
+
+/// Works through completed recordings, a few at a time
+pub struct PostProcessor {
+    backend: Arc<dyn DvrBackend>,
+    state: Arc<State>,
+    workers: usize,
+    ffmpeg: Option<Ffmpeg>,
+    markers: Option<SkipMarkers>,
+    labels: Vec<String>,
+    collections: Vec<String>,
+    hook: Option<Hook>,
+    aliases: TitleAliases,
+}
+
+impl PostProcessor {
+    /// Returns `None` if there's nothing to do with completed recordings
+    pub fn new(
+        config: PostProcessConfig,
+        backend: Arc<dyn DvrBackend>,
+        state: Arc<State>,
+        aliases: TitleAliases,
+    ) -> Option<Self> {
+        let ffmpeg = config.remux_container.map(|container| {
+            Ffmpeg::new(
+                config.ffmpeg_path,
+                container,
+                config.transcode_codec,
+                config.transcode_crf,
+            )
+        });
+        let markers = config.skip_markers.map(|source| {
+            SkipMarkers::new(
+                source,
+                config.comskip_path,
+                config.comskip_ini,
+                config.skip_marker_channels,
+            )
+        });
+        let hook = config.post_record_command.map(Hook::new);
+        let tagging = !config.dvr_labels.is_empty() || !config.dvr_collections.is_empty();
+        (ffmpeg.is_some() || markers.is_some() || tagging || hook.is_some()).then_some(
+            PostProcessor {
+                backend,
+                state,
+                workers: config.postprocess_workers.unwrap_or(DEFAULT_WORKERS).max(1),
+                ffmpeg,
+                markers,
+                labels: config.dvr_labels,
+                collections: config.dvr_collections,
+                hook,
+                aliases,
+            },
+        )
+    }
+
+    async fn process(&self, completed: Completed) -> Result<()> {
+        let metadata = match self.backend.recording(&completed.rating_key).await? {
+            Some(metadata) => metadata,
+            None => {
+                log::warn!("{} has already left the library", completed.title);
+                return Ok(());
+            }
+        };
+        // Scheduled events are named after the show, not the episode
+        let show_title = completed.show_title.as_deref();
+        let channel = self
+            .state
+            .scheduled_channel(show_title.unwrap_or(&completed.title), &self.aliases)?;
+
+        if !self.labels.is_empty() || !self.collections.is_empty() {
+            let channel = channel.as_deref().unwrap_or_default();
+            let collections: Vec<_> = self
+                .collections
+                .iter()
+                .map(|c| c.replace("{channel}", channel))
+                .filter(|c| !c.is_empty())
+                .collect();
+            self.backend
+                .tag(&metadata, &self.labels, &collections)
+                .await?;
+        }
+
+        let path = match metadata.file() {
+            Some(file) => PathBuf::from(file),
+            None => {
+                log::warn!("The DVR has no file for {}", completed.title);
+                return Ok(());
+            }
+        };
+
+        let mut recording = Recording {
+            path,
+            title: completed.title,
+            channel,
+            rating_key: completed.rating_key,
+        };
+
+        if let Some(ffmpeg) = &self.ffmpeg {
+            ffmpeg.run(&mut recording).await?;
+            // The DVR only notices the new file once it rescans the folder
+            if let Some(dir) = recording.path.parent() {
+                self.backend
+                    .rescan(&metadata, &dir.to_string_lossy())
+                    .await?;
+            }
+        }
+
+        if let Some(markers) = self.markers.as_ref().filter(|m| m.wants(&recording)) {
+            markers.run(&recording, &metadata.markers).await?;
+        }
+
+        if let Some(hook) = &self.hook {
+            hook.run(&recording).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs until the sending side is dropped, processing each completed recording
+pub async fn run(processor: PostProcessor, completed: mpsc::Receiver<Completed>) {
+    let completed = futures::stream::unfold(completed, |mut rx| async move {
+        rx.recv().await.map(|c| (c, rx))
+    });
+    completed
+        .for_each_concurrent(processor.workers, |recording| async {
+            let title = recording.title.clone();
+            if let Err(e) = processor.process(recording).await {
+                log::error!("Post-processing {} failed: {}", title, e);
+            }
+        })
+        .await;
+}
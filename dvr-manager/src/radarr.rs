@@ -0,0 +1,172 @@
+use crate::config::redacted;
+use crate::plex::GridMetadata;
+use crate::title::TitleAliases;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const QUEUE_PAGE_SIZE: &str = "1000";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RadarrError {
+    #[error("Failed to request data from Radarr: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+pub type Result<T, E = RadarrError> = std::result::Result<T, E>;
+
+/// What to do with a film airing that Radarr knows about
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RadarrPolicy {
+    /// Skip if Radarr has the film or is downloading it
+    #[default]
+    Skip,
+    /// Record regardless of Radarr
+    RecordAnyway,
+    /// Record only if Radarr has no copy, or its copy hasn't met the quality cutoff
+    RecordIfMissing,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct RadarrConfig {
+    pub radarr_url: Option<String>,
+    pub radarr_api_key: Option<String>,
+    pub radarr_policy: Option<RadarrPolicy>,
+}
+
+impl std::fmt::Debug for RadarrConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RadarrConfig")
+            .field("radarr_url", &self.radarr_url)
+            .field("radarr_api_key", &redacted(&self.radarr_api_key))
+            .field("radarr_policy", &self.radarr_policy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlternateTitle {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MovieFile {
+    #[serde(default)]
+    quality_cutoff_not_met: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Movie {
+    id: u64,
+    title: String,
+    year: Option<i32>,
+    has_file: bool,
+    movie_file: Option<MovieFile>,
+    #[serde(default)]
+    alternate_titles: Vec<AlternateTitle>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueItem {
+    movie_id: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Queue {
+    records: Vec<QueueItem>,
+}
+
+pub struct Radarr {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    policy: RadarrPolicy,
+}
+
+impl Radarr {
+    /// Returns `None` if Radarr isn't configured, or wouldn't affect anything
+    pub fn new(config: RadarrConfig) -> Option<Self> {
+        let policy = config.radarr_policy.unwrap_or_default();
+        if policy == RadarrPolicy::RecordAnyway {
+            return None;
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Radarr client is valid");
+        Some(Radarr {
+            client,
+            url: config.radarr_url?.trim_end_matches('/').to_string(),
+            api_key: config.radarr_api_key?,
+            policy,
+        })
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        resource: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T> {
+        let value = self
+            .client
+            .get(format!("{}/api/v3/{}", self.url, resource))
+            .header("X-Api-Key", &self.api_key)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(value)
+    }
+
+    /// Whether Radarr will take care of this film, according to the policy
+    pub async fn covers(&self, show: &GridMetadata, aliases: &TitleAliases) -> Result<bool> {
+        let wanted = aliases.key(&show.title);
+        let year = show.year();
+
+        let movies: Vec<Movie> = self.get("movie", &[]).await?;
+        let movie = match movies.into_iter().find(|m| {
+            let title_matches = aliases.key(&m.title) == wanted
+                || m.alternate_titles
+                    .iter()
+                    .any(|a| aliases.key(&a.title) == wanted);
+            // Guide years are sometimes missing, but when both are known they must agree
+            let year_matches = match (year, m.year) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            };
+            title_matches && year_matches
+        }) {
+            Some(movie) => movie,
+            None => return Ok(false),
+        };
+
+        let covered = match self.policy {
+            RadarrPolicy::RecordIfMissing => {
+                movie.has_file
+                    && !movie
+                        .movie_file
+                        .as_ref()
+                        .is_some_and(|f| f.quality_cutoff_not_met)
+            }
+            RadarrPolicy::Skip => {
+                movie.has_file || {
+                    let queue: Queue = self.get("queue", &[("pageSize", QUEUE_PAGE_SIZE)]).await?;
+                    queue.records.iter().any(|q| q.movie_id == Some(movie.id))
+                }
+            }
+            RadarrPolicy::RecordAnyway => false,
+        };
+
+        if covered {
+            log::info!("Radarr already covers {}", movie.title);
+        }
+        Ok(covered)
+    }
+}
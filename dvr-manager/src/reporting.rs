@@ -0,0 +1,60 @@
+use sentry::ClientInitGuard;
+use std::any::Any;
+use std::backtrace::Backtrace;
+
+/// Logs panics with a backtrace, since stderr is easily missed in container logs.
+/// Must be installed before `init` so Sentry's own hook can chain to it.
+pub fn install_panic_hook() {
+    let next = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        log::error!("{}\n{}", info, Backtrace::force_capture());
+        next(info);
+    }));
+}
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".into())
+}
+
+/// Starts Sentry (or a compatible service) if a DSN is configured.
+/// Panics are captured automatically once this has been called;
+/// the returned guard flushes pending events when dropped.
+pub fn init(dsn: Option<&str>) -> Option<ClientInitGuard> {
+    let mut options = sentry::ClientOptions::default();
+    options.release = sentry::release_name!();
+    options.attach_stacktrace = true;
+
+    let guard = sentry::init((dsn?, options));
+    if guard.is_enabled() {
+        log::info!("Error reporting enabled");
+    } else {
+        log::warn!("Error reporting DSN is invalid, reports will not be sent");
+    }
+    Some(guard)
+}
+
+/// Reports an unexpected error, tagged with whatever context is known
+pub fn report_error(err: &(dyn std::error::Error + 'static), tags: &[(&str, &str)]) {
+    sentry::with_scope(
+        |scope| {
+            for (key, value) in tags {
+                scope.set_tag(key, value);
+            }
+        },
+        || sentry::capture_error(err),
+    );
+}
+
+/// Leaves a trail of Plex requests so reports show which endpoint failed
+pub fn plex_request(method: &str, resource: &str) {
+    sentry::add_breadcrumb(sentry::Breadcrumb {
+        ty: "http".into(),
+        category: Some("plex".into()),
+        message: Some(format!("{} {}", method, resource)),
+        ..Default::default()
+    });
+}
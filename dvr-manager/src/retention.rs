@@ -0,0 +1,23 @@
+use crate::state::State;
+use chrono::Duration;
+use std::sync::Arc;
+use tokio::time::sleep;
+
+/// Days of history kept when no retention is configured
+pub const DEFAULT_RETENTION_DAYS: u64 = 90;
+
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Runs forever, pruning state older than the retention period once a day
+/// so the database doesn't grow over years of operation
+pub async fn run(state: Arc<State>, days: u64) {
+    loop {
+        let before = (state.now() - Duration::days(days as i64)).timestamp();
+        match state.prune(before) {
+            Ok(0) => (),
+            Ok(deleted) => log::info!("Pruned {} state rows older than {} days", deleted, days),
+            Err(e) => log::warn!("Couldn't prune old state: {}", e),
+        }
+        sleep(PRUNE_INTERVAL).await;
+    }
+}
@@ -0,0 +1,238 @@
+use super::AppState;
+use crate::backend::{self, BackendError};
+use crate::notify::Event;
+use crate::plex::Channel;
+use crate::state::{CalendarEntry, Decision, StateError, StatusReport};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+/// An error reported to API clients as `{"error": "..."}`
+pub struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<BackendError> for ApiError {
+    fn from(e: BackendError) -> Self {
+        ApiError(StatusCode::BAD_GATEWAY, e.to_string())
+    }
+}
+
+impl From<StateError> for ApiError {
+    fn from(e: StateError) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+type Result<T, E = ApiError> = std::result::Result<T, E>;
+
+/// Seconds of decisions listed when no start is given
+const DEFAULT_DECISIONS_SPAN: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RecordRequest {
+    /// Plex guid of the airing, as listed in the guide
+    guid: String,
+    /// Channel id or identifier, to avoid searching every channel's guide
+    channel: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecisionsQuery {
+    since: Option<i64>,
+}
+
+async fn auth(State(app): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let expected = app.api_token.as_deref().map(|t| format!("Bearer {}", t));
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok());
+    if expected.is_none() || given != expected.as_deref() {
+        return ApiError(StatusCode::UNAUTHORIZED, "Invalid API token".into()).into_response();
+    }
+    next.run(request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/channels",
+    responses((status = 200, description = "Channels in the DVR lineup", body = [Channel]))
+)]
+async fn channels(State(app): State<Arc<AppState>>) -> Result<Json<Vec<Channel>>> {
+    Ok(Json(app.backend.channels().await?))
+}
+
+/// Candidates and scheduled recordings that haven't finished yet
+#[utoipa::path(
+    get,
+    path = "/api/upcoming",
+    responses((status = 200, description = "Airings that haven't ended", body = [CalendarEntry]))
+)]
+async fn upcoming(State(app): State<Arc<AppState>>) -> Result<Json<Vec<CalendarEntry>>> {
+    let now = app.state.now().timestamp();
+    let entries = app
+        .state
+        .calendar()?
+        .into_iter()
+        .filter(|e| e.ends_at >= now)
+        .collect();
+    Ok(Json(entries))
+}
+
+/// What passes decided about airings, and why, each listed when it changed
+#[utoipa::path(
+    get,
+    path = "/api/decisions",
+    params((
+        "since" = Option<i64>,
+        Query,
+        description = "Unix time to list decisions from, a day ago by default"
+    )),
+    responses((status = 200, description = "Decisions, oldest first", body = [Decision]))
+)]
+async fn decisions(
+    State(app): State<Arc<AppState>>,
+    Query(query): Query<DecisionsQuery>,
+) -> Result<Json<Vec<Decision>>> {
+    let since = query
+        .since
+        .unwrap_or_else(|| app.state.now().timestamp() - DEFAULT_DECISIONS_SPAN);
+    Ok(Json(app.state.decisions_since(since)?))
+}
+
+/// Records an airing straight away, whatever the manager's rules say
+#[utoipa::path(
+    post,
+    path = "/api/record",
+    request_body = RecordRequest,
+    responses(
+        (status = 201, description = "Recording scheduled"),
+        (status = 404, description = "Airing isn't in today's or tomorrow's guide"),
+    )
+)]
+async fn record(
+    State(app): State<Arc<AppState>>,
+    Json(request): Json<RecordRequest>,
+) -> Result<StatusCode> {
+    let airing = backend::find_airing(
+        app.backend.as_ref(),
+        request.channel.as_deref(),
+        app.state.now(),
+        |a| a.guid == request.guid,
+    )
+    .await?
+    .ok_or_else(|| {
+        ApiError(
+            StatusCode::NOT_FOUND,
+            format!("{} isn't in the guide", request.guid),
+        )
+    })?;
+
+    log::info!(
+        "Recording {} as requested over the API",
+        airing.show_title()
+    );
+    app.backend.subscribe(&airing).await?;
+    app.emit(Event::scheduled(&airing)).await;
+    Ok(StatusCode::CREATED)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/subscriptions/{id}",
+    params(("id" = String, Path, description = "Plex subscription id")),
+    responses((status = 204, description = "Subscription cancelled"))
+)]
+async fn cancel_subscription(
+    State(app): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode> {
+    app.backend.cancel_subscription(&id).await?;
+    log::info!("Cancelled subscription {} as requested over the API", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Wakes the manager for a scheduling pass now rather than at the next airing
+#[utoipa::path(
+    post,
+    path = "/api/rescan",
+    responses((status = 202, description = "Scheduling pass requested"))
+)]
+async fn rescan(State(app): State<Arc<AppState>>) -> StatusCode {
+    app.wake.notify_one();
+    StatusCode::ACCEPTED
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    responses((status = 200, description = "Manager status", body = StatusReport))
+)]
+async fn status(State(app): State<Arc<AppState>>) -> Result<Json<StatusReport>> {
+    Ok(Json(app.state.status()?))
+}
+
+/// Declares the bearer token that every endpoint needs
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        channels,
+        upcoming,
+        decisions,
+        record,
+        cancel_subscription,
+        rescan,
+        status
+    ),
+    modifiers(&BearerAuth),
+    security(("api_token" = []))
+)]
+struct ApiDoc;
+
+/// The OpenAPI document describing the API
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI for trying out the API, loaded from a CDN to keep the binary small
+pub async fn docs() -> Html<&'static str> {
+    Html(include_str!("docs.html"))
+}
+
+/// Endpoints for controlling the manager, which all need the API token
+pub fn router(app: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/channels", get(channels))
+        .route("/upcoming", get(upcoming))
+        .route("/decisions", get(decisions))
+        .route("/record", post(record))
+        .route("/subscriptions/{id}", delete(cancel_subscription))
+        .route("/rescan", post(rescan))
+        .route("/status", get(status))
+        .route_layer(middleware::from_fn_with_state(app, auth))
+}
@@ -0,0 +1,28 @@
+use super::{AppState, TokenQuery};
+use crate::calendar;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// Upcoming and recently scheduled recordings, for calendar apps to subscribe to
+pub async fn calendar(
+    State(app): State<Arc<AppState>>,
+    Query(query): Query<TokenQuery>,
+) -> Response {
+    // Calendar apps can only subscribe to a URL, so the token comes in it
+    if !query.authorized(&app) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    match app.state.calendar() {
+        Ok(entries) => (
+            [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+            calendar::ics(&entries, app.state.now()),
+        )
+            .into_response(),
+        Err(e) => {
+            log::error!("Couldn't read calendar: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
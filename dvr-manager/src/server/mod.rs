@@ -0,0 +1,73 @@
+mod api;
+mod calendar;
+mod webhook;
+
+use crate::backend::DvrBackend;
+use crate::notify::{Event, Notifiers};
+#[cfg(feature = "postprocess")]
+use crate::postprocess::Completed;
+use crate::state::State;
+use axum::routing::{get, post};
+use axum::Router;
+use serde::Deserialize;
+use std::sync::Arc;
+#[cfg(feature = "postprocess")]
+use tokio::sync::mpsc;
+use tokio::sync::Notify;
+
+pub struct AppState {
+    pub backend: Arc<dyn DvrBackend>,
+    pub state: Arc<State>,
+    pub notifiers: Arc<Notifiers>,
+    pub webhook_token: Option<String>,
+    /// Bearer token for the API, which is disabled without one
+    pub api_token: Option<String>,
+    pub api_docs: bool,
+    /// Wakes the manager for an early scheduling pass
+    pub wake: Arc<Notify>,
+    /// Where to send finished recordings for post-processing
+    #[cfg(feature = "postprocess")]
+    pub completed: Option<mpsc::Sender<Completed>>,
+}
+
+impl AppState {
+    fn emit(&self, event: Event) -> impl std::future::Future<Output = ()> + '_ {
+        if let Err(e) = self.state.record_event(&event) {
+            log::warn!("Couldn't record event: {}", e);
+        }
+        self.notifiers.send(event)
+    }
+}
+
+/// Token passed in the URL by clients that can't set headers
+#[derive(Debug, Deserialize)]
+pub struct TokenQuery {
+    token: Option<String>,
+}
+
+impl TokenQuery {
+    fn authorized(&self, app: &AppState) -> bool {
+        app.webhook_token.is_none() || self.token == app.webhook_token
+    }
+}
+
+/// Serves HTTP endpoints until the process exits
+pub async fn serve(addr: String, app: AppState) -> std::io::Result<()> {
+    let app = Arc::new(app);
+    let mut router = Router::new()
+        .route("/plex/webhook", post(webhook::plex_webhook))
+        .route("/calendar.ics", get(calendar::calendar));
+    if app.api_token.is_some() {
+        router = router
+            .nest("/api", api::router(app.clone()))
+            .route("/openapi.json", get(api::openapi));
+        if app.api_docs {
+            router = router.route("/docs", get(api::docs));
+        }
+    }
+    let router = router.with_state(app);
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    log::info!("Listening on {}", addr);
+    axum::serve(listener, router).await
+}
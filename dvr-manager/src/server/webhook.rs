@@ -0,0 +1,105 @@
+use super::{AppState, TokenQuery};
+use crate::notify::Event;
+#[cfg(feature = "postprocess")]
+use crate::postprocess::Completed;
+use axum::extract::{Multipart, Query, State};
+use axum::http::StatusCode;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookMetadata {
+    rating_key: Option<String>,
+    title: String,
+    grandparent_title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookPayload {
+    event: String,
+    #[serde(rename = "Metadata")]
+    metadata: Option<WebhookMetadata>,
+}
+
+/// Receives Plex webhooks, which arrive as multipart forms with a JSON `payload` part.
+/// A grab means a recording has started; a new library item with the same
+/// title means it has finished.
+pub async fn plex_webhook(
+    State(app): State<Arc<AppState>>,
+    Query(query): Query<TokenQuery>,
+    mut form: Multipart,
+) -> StatusCode {
+    // Plex can't send headers, so the token has to come in the URL
+    if !query.authorized(&app) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let mut payload = None;
+    while let Ok(Some(field)) = form.next_field().await {
+        if field.name() == Some("payload") {
+            payload = field.text().await.ok();
+            break;
+        }
+    }
+    let payload: WebhookPayload = match payload.map(|p| serde_json::from_str(&p)) {
+        Some(Ok(payload)) => payload,
+        Some(Err(e)) => {
+            log::warn!("Couldn't parse Plex webhook: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+        None => return StatusCode::BAD_REQUEST,
+    };
+    log::debug!("Plex webhook: {:?}", payload);
+
+    let metadata = match payload.metadata {
+        Some(metadata) => metadata,
+        None => return StatusCode::OK,
+    };
+    let show_title = metadata.grandparent_title.as_deref();
+
+    let result = match payload.event.as_str() {
+        "media.grab" => {
+            log::info!("Plex started recording {}", metadata.title);
+            app.state.record_grab(&metadata.title, show_title)
+        }
+        "library.new" => {
+            let rating_key = metadata.rating_key.unwrap_or_default();
+            match app
+                .state
+                .record_added(&metadata.title, show_title, &rating_key)
+            {
+                Ok(true) => {
+                    let title = match show_title {
+                        Some(show) => format!("{} - {}", show, metadata.title),
+                        None => metadata.title,
+                    };
+                    log::info!("Recording of {} is in the library", title);
+                    #[cfg(feature = "postprocess")]
+                    if let Some(completed) = &app.completed {
+                        let recording = Completed {
+                            rating_key: rating_key.clone(),
+                            title: title.clone(),
+                            show_title: metadata.grandparent_title.clone(),
+                        };
+                        if completed.try_send(recording).is_err() {
+                            log::warn!("Post-processing queue is full, skipping {}", title);
+                        }
+                    }
+                    app.emit(Event::Recorded { title, rating_key }).await;
+                    Ok(())
+                }
+                other => other.map(|_| ()),
+            }
+        }
+        _ => Ok(()),
+    };
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(e) => {
+            log::error!("Couldn't record webhook: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
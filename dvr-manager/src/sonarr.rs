@@ -0,0 +1,152 @@
+use crate::config::redacted;
+use crate::plex::GridMetadata;
+use crate::title::TitleAliases;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SonarrError {
+    #[error("Failed to request data from Sonarr: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+pub type Result<T, E = SonarrError> = std::result::Result<T, E>;
+
+/// When an episode known to Sonarr should be left to Sonarr
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SonarrPolicy {
+    /// Skip only if Sonarr already has the file
+    #[default]
+    HasFile,
+    /// Skip if Sonarr has the file or is monitoring the episode to download it
+    Monitored,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SonarrConfig {
+    pub sonarr_url: Option<String>,
+    pub sonarr_api_key: Option<String>,
+    pub sonarr_policy: Option<SonarrPolicy>,
+}
+
+impl std::fmt::Debug for SonarrConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SonarrConfig")
+            .field("sonarr_url", &self.sonarr_url)
+            .field("sonarr_api_key", &redacted(&self.sonarr_api_key))
+            .field("sonarr_policy", &self.sonarr_policy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlternateTitle {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Series {
+    id: u64,
+    title: String,
+    monitored: bool,
+    #[serde(default)]
+    alternate_titles: Vec<AlternateTitle>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Episode {
+    season_number: u64,
+    episode_number: u64,
+    has_file: bool,
+    monitored: bool,
+}
+
+pub struct Sonarr {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    policy: SonarrPolicy,
+}
+
+impl Sonarr {
+    /// Returns `None` if Sonarr isn't configured
+    pub fn new(config: SonarrConfig) -> Option<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Sonarr client is valid");
+        Some(Sonarr {
+            client,
+            url: config.sonarr_url?.trim_end_matches('/').to_string(),
+            api_key: config.sonarr_api_key?,
+            policy: config.sonarr_policy.unwrap_or_default(),
+        })
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        resource: &str,
+        query: &[(&str, String)],
+    ) -> Result<T> {
+        let value = self
+            .client
+            .get(format!("{}/api/v3/{}", self.url, resource))
+            .header("X-Api-Key", &self.api_key)
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(value)
+    }
+
+    /// Whether Sonarr will take care of this episode, according to the policy
+    pub async fn covers(&self, show: &GridMetadata, aliases: &TitleAliases) -> Result<bool> {
+        let (title, season, episode) =
+            match (&show.grandparent_title, show.parent_index, show.index) {
+                (Some(title), Some(season), Some(episode)) => (title, season, episode),
+                // Without an episode number there's nothing to compare against
+                _ => return Ok(false),
+            };
+
+        let wanted = aliases.key(title);
+        let series: Vec<Series> = self.get("series", &[]).await?;
+        let series = match series.into_iter().find(|s| {
+            aliases.key(&s.title) == wanted
+                || s.alternate_titles
+                    .iter()
+                    .any(|a| aliases.key(&a.title) == wanted)
+        }) {
+            Some(series) => series,
+            None => return Ok(false),
+        };
+
+        let episodes: Vec<Episode> = self
+            .get("episode", &[("seriesId", series.id.to_string())])
+            .await?;
+        let covered = episodes
+            .iter()
+            .find(|e| e.season_number == season && e.episode_number == episode)
+            .is_some_and(|e| match self.policy {
+                SonarrPolicy::HasFile => e.has_file,
+                SonarrPolicy::Monitored => e.has_file || (series.monitored && e.monitored),
+            });
+
+        if covered {
+            log::info!(
+                "Sonarr already covers {} S{:02}E{:02}",
+                series.title,
+                season,
+                episode
+            );
+        }
+        Ok(covered)
+    }
+}
@@ -0,0 +1,1146 @@
+use crate::clock::{Clock, SystemClock};
+use crate::notify::Event;
+use crate::title::TitleAliases;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::json;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+#[cfg(feature = "server")]
+use utoipa::ToSchema;
+
+/// The database's name within the data directory
+pub const STATE_FILE: &str = "dvr-manager.db";
+
+const RECENT_ERRORS: i64 = 5;
+/// Bumped when an export from an older build can't be imported as-is
+const EXPORT_VERSION: u32 = 1;
+/// Tables included in exports
+const TABLES: &[&str] = &[
+    "passes",
+    "daemon",
+    "upcoming",
+    "calendar",
+    "channel_stats",
+    "history",
+    "recordings",
+    "tokens",
+    "ratings",
+    "cleanups",
+    "grid_cache",
+    "failures",
+    "claims",
+    "decisions",
+];
+/// How long recordings stay in the calendar after they finish
+const CALENDAR_HISTORY: i64 = 7 * 24 * 60 * 60;
+/// Seconds before an unfinished claim on an airing can be taken over
+const CLAIM_TIMEOUT: i64 = 10 * 60;
+/// How long unused guides stay cached before they're dropped
+const GRID_CACHE_HISTORY: i64 = 2 * 24 * 60 * 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error("State database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("State database is from a newer version (schema {0}), upgrade dvr-manager")]
+    NewerSchema(i64),
+
+    #[error("Invalid state export: {0}")]
+    InvalidDump(String),
+}
+
+pub type Result<T, E = StateError> = std::result::Result<T, E>;
+
+/// The next airing the manager intends to record on a channel
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct UpcomingRecording {
+    pub channel: String,
+    pub channel_title: String,
+    pub title: String,
+    pub begins_at: i64,
+}
+
+/// An airing in the calendar feed, either scheduled or a candidate for recording
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct CalendarEntry {
+    pub channel: String,
+    pub channel_title: String,
+    pub title: String,
+    pub begins_at: i64,
+    pub ends_at: i64,
+    pub scheduled: bool,
+}
+
+/// Running totals of how a channel's airings were handled
+#[derive(Debug, Serialize, Default)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct ChannelStats {
+    pub channel: String,
+    pub channel_title: String,
+    pub seen: i64,
+    pub scheduled: i64,
+    pub skipped: i64,
+    pub failed: i64,
+}
+
+/// OAuth tokens for an external service
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: i64,
+}
+
+/// A recording that has reached the library
+#[derive(Debug)]
+pub struct LibraryRecording {
+    pub id: i64,
+    pub title: String,
+    pub rating_key: String,
+}
+
+/// A notable event, kept for digests
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub at: i64,
+    pub kind: String,
+    pub title: Option<String>,
+    pub channel: Option<String>,
+    pub begins_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// What a pass decided to do with an airing, kept as an audit trail
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct Decision {
+    pub at: i64,
+    pub guid: String,
+    pub begins_at: i64,
+    pub channel_title: String,
+    pub title: String,
+    /// `scheduled`, `skipped` or `failed`
+    pub outcome: String,
+    /// Why it was skipped, or the error it failed with
+    pub reason: Option<String>,
+}
+
+/// An airing that couldn't be scheduled, kept so it can be retried
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct FailedRecording {
+    pub guid: String,
+    pub begins_at: i64,
+    pub ends_at: i64,
+    pub channel: String,
+    pub channel_title: String,
+    pub title: String,
+    /// The guide's airing as JSON, needed to subscribe again
+    #[serde(skip)]
+    pub airing: String,
+    pub error: String,
+    pub attempts: i64,
+    /// When it'll next be tried, or `None` once it's been given up on
+    pub retry_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct PassError {
+    pub at: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Default)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct CleanupStats {
+    pub runs: i64,
+    pub last_run: Option<i64>,
+    pub deleted: i64,
+    pub bytes_freed: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(ToSchema))]
+pub struct StatusReport {
+    pub started_at: Option<i64>,
+    pub last_pass: Option<i64>,
+    pub next_wake: Option<i64>,
+    pub upcoming: Vec<UpcomingRecording>,
+    pub channels: Vec<ChannelStats>,
+    pub errors: Vec<PassError>,
+    /// Recordings being retried, or given up on within the past week
+    pub failures: Vec<FailedRecording>,
+    pub cleanup: CleanupStats,
+}
+
+/// Schema changes, applied in order to bring older databases up to date.
+/// Append new ones and never edit those already released.
+const MIGRATIONS: &[&str] = &[
+    // Tables were created on demand before versioning, so this must stay idempotent
+    "CREATE TABLE IF NOT EXISTS passes (
+        id INTEGER PRIMARY KEY,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE TABLE IF NOT EXISTS daemon (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS upcoming (
+        channel TEXT PRIMARY KEY,
+        channel_title TEXT NOT NULL,
+        title TEXT NOT NULL,
+        begins_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS calendar (
+        channel TEXT NOT NULL,
+        channel_title TEXT NOT NULL,
+        title TEXT NOT NULL,
+        begins_at INTEGER NOT NULL,
+        ends_at INTEGER NOT NULL,
+        scheduled INTEGER NOT NULL,
+        PRIMARY KEY (channel, begins_at)
+    );
+    CREATE TABLE IF NOT EXISTS channel_stats (
+        channel TEXT PRIMARY KEY,
+        channel_title TEXT NOT NULL,
+        seen INTEGER NOT NULL DEFAULT 0,
+        scheduled INTEGER NOT NULL DEFAULT 0,
+        skipped INTEGER NOT NULL DEFAULT 0,
+        failed INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        title TEXT,
+        channel TEXT,
+        begins_at INTEGER,
+        error TEXT
+    );
+    CREATE TABLE IF NOT EXISTS recordings (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        show_title TEXT,
+        grabbed_at INTEGER NOT NULL,
+        rating_key TEXT,
+        added_at INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS tokens (
+        service TEXT PRIMARY KEY,
+        access_token TEXT NOT NULL,
+        refresh_token TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS ratings (
+        key TEXT PRIMARY KEY,
+        rating REAL,
+        fetched_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS cleanups (
+        id INTEGER PRIMARY KEY,
+        ran_at INTEGER NOT NULL,
+        deleted INTEGER NOT NULL,
+        bytes_freed INTEGER NOT NULL
+    );",
+    "CREATE TABLE grid_cache (
+        channel TEXT NOT NULL,
+        date TEXT NOT NULL,
+        airings TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        PRIMARY KEY (channel, date)
+    );",
+    "CREATE TABLE failures (
+        guid TEXT NOT NULL,
+        begins_at INTEGER NOT NULL,
+        ends_at INTEGER NOT NULL,
+        channel TEXT NOT NULL,
+        channel_title TEXT NOT NULL,
+        title TEXT NOT NULL,
+        airing TEXT NOT NULL,
+        error TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        retry_at INTEGER,
+        PRIMARY KEY (guid, begins_at)
+    );",
+    "CREATE TABLE claims (
+        guid TEXT NOT NULL,
+        begins_at INTEGER NOT NULL,
+        claimed_at INTEGER NOT NULL,
+        scheduled INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (guid, begins_at)
+    );",
+    "CREATE TABLE decisions (
+        at INTEGER NOT NULL,
+        guid TEXT NOT NULL,
+        begins_at INTEGER NOT NULL,
+        channel_title TEXT NOT NULL,
+        title TEXT NOT NULL,
+        outcome TEXT NOT NULL,
+        reason TEXT
+    );
+    CREATE INDEX decisions_airing ON decisions (guid, begins_at);",
+];
+
+/// The `user_version` of a database with every migration applied
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// Applies the migrations a database hasn't had yet, tracked in `user_version`
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(StateError::NewerSchema(version));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i as i64 + 1)?;
+        tx.commit()?;
+        log::debug!("Migrated state to version {}", i + 1);
+    }
+    Ok(())
+}
+
+/// Persistent store shared by the daemon and the CLI
+pub struct State {
+    conn: Mutex<Connection>,
+    /// What's kept is timed by this, the manager's clock once it has one
+    clock: RwLock<Arc<dyn Clock>>,
+}
+
+impl State {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(State {
+            conn: Mutex::new(conn),
+            clock: RwLock::new(Arc::new(SystemClock)),
+        })
+    }
+
+    /// Times what's kept by another clock, e.g. to simulate passes at other times
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap() = clock;
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.read().unwrap().now()
+    }
+
+    pub fn record_pass(
+        &self,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO passes (started_at, finished_at, error) VALUES (?1, ?2, ?3)",
+            params![started_at.timestamp(), finished_at.timestamp(), error],
+        )?;
+        Ok(())
+    }
+
+    fn set_daemon_value(&self, key: &str, value: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO daemon (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    fn daemon_value(&self, key: &str) -> Result<Option<i64>> {
+        let value = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT value FROM daemon WHERE key = ?1", [key], |r| {
+                r.get(0)
+            })
+            .optional()?;
+        Ok(value)
+    }
+
+    pub fn last_digest(&self) -> Result<Option<i64>> {
+        self.daemon_value("last_digest")
+    }
+
+    pub fn set_last_digest(&self, at: DateTime<Utc>) -> Result<()> {
+        self.set_daemon_value("last_digest", at.timestamp())
+    }
+
+    pub fn last_weekly_report(&self) -> Result<Option<i64>> {
+        self.daemon_value("last_weekly_report")
+    }
+
+    pub fn set_last_weekly_report(&self, at: DateTime<Utc>) -> Result<()> {
+        self.set_daemon_value("last_weekly_report", at.timestamp())
+    }
+
+    pub fn set_started(&self, at: DateTime<Utc>) -> Result<()> {
+        self.set_daemon_value("started_at", at.timestamp())
+    }
+
+    /// Records when the loop intends to wake, so a sleeping daemon can be told apart from a hung one
+    pub fn set_next_wake(&self, at: DateTime<Utc>) -> Result<()> {
+        self.set_daemon_value("next_wake", at.timestamp())
+    }
+
+    /// Replaces the planned recordings with those found in the latest pass
+    pub fn set_upcoming(&self, upcoming: &[UpcomingRecording]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM upcoming", [])?;
+        for u in upcoming {
+            tx.execute(
+                "INSERT INTO upcoming (channel, channel_title, title, begins_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![u.channel, u.channel_title, u.title, u.begins_at],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replaces the candidates in the calendar with those from the latest pass.
+    /// Scheduled recordings are kept for a while after they finish.
+    pub fn set_calendar(&self, entries: &[CalendarEntry]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM calendar WHERE scheduled = 0 OR ends_at < ?1",
+            [self.now().timestamp() - CALENDAR_HISTORY],
+        )?;
+        for e in entries {
+            tx.execute(
+                "INSERT OR REPLACE INTO calendar
+                    (channel, channel_title, title, begins_at, ends_at, scheduled)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    e.channel,
+                    e.channel_title,
+                    e.title,
+                    e.begins_at,
+                    e.ends_at,
+                    e.scheduled
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn calendar(&self) -> Result<Vec<CalendarEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT channel, channel_title, title, begins_at, ends_at, scheduled
+             FROM calendar ORDER BY begins_at",
+        )?;
+        let entries = stmt
+            .query_map([], |r| {
+                Ok(CalendarEntry {
+                    channel: r.get(0)?,
+                    channel_title: r.get(1)?,
+                    title: r.get(2)?,
+                    begins_at: r.get(3)?,
+                    ends_at: r.get(4)?,
+                    scheduled: r.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+
+    pub fn token(&self, service: &str) -> Result<Option<Token>> {
+        let token = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT access_token, refresh_token, expires_at FROM tokens WHERE service = ?1",
+                [service],
+                |r| {
+                    Ok(Token {
+                        access_token: r.get(0)?,
+                        refresh_token: r.get(1)?,
+                        expires_at: r.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(token)
+    }
+
+    pub fn set_token(&self, service: &str, token: &Token) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO tokens (service, access_token, refresh_token, expires_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (service) DO UPDATE SET
+                access_token = excluded.access_token,
+                refresh_token = excluded.refresh_token,
+                expires_at = excluded.expires_at",
+            params![
+                service,
+                token.access_token,
+                token.refresh_token,
+                token.expires_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// A cached external rating and when it was fetched. The rating is `None`
+    /// if the lookup found nothing.
+    pub fn cached_rating(&self, key: &str) -> Result<Option<(Option<f64>, i64)>> {
+        let rating = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT rating, fetched_at FROM ratings WHERE key = ?1",
+                [key],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?;
+        Ok(rating)
+    }
+
+    pub fn set_cached_rating(&self, key: &str, rating: Option<f64>) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO ratings (key, rating, fetched_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (key) DO UPDATE SET
+                rating = excluded.rating,
+                fetched_at = excluded.fetched_at",
+            params![key, rating, self.now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// A channel's guide for a day as JSON, with when it was fetched
+    pub fn cached_grid(&self, channel: &str, date: &str) -> Result<Option<(String, i64)>> {
+        let grid = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT airings, fetched_at FROM grid_cache WHERE channel = ?1 AND date = ?2",
+                [channel, date],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?;
+        Ok(grid)
+    }
+
+    /// Caches a channel's guide for a day, dropping days that have long passed
+    pub fn set_cached_grid(&self, channel: &str, date: &str, airings: &str) -> Result<()> {
+        let now = self.now().timestamp();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO grid_cache (channel, date, airings, fetched_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (channel, date) DO UPDATE SET
+                airings = excluded.airings,
+                fetched_at = excluded.fetched_at",
+            params![channel, date, airings, now],
+        )?;
+        conn.execute(
+            "DELETE FROM grid_cache WHERE fetched_at < ?1",
+            [now - GRID_CACHE_HISTORY],
+        )?;
+        Ok(())
+    }
+
+    /// Forgets cached guides, whose subscription flags go stale when recordings change
+    pub fn clear_grid_cache(&self) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM grid_cache", [])?;
+        Ok(())
+    }
+
+    /// Journals a failed scheduling attempt, replacing earlier attempts at the same airing
+    pub fn record_failure(&self, failure: &FailedRecording) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO failures (guid, begins_at, ends_at, channel, channel_title, title,
+                                   airing, error, attempts, retry_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (guid, begins_at) DO UPDATE SET
+                error = excluded.error,
+                attempts = excluded.attempts,
+                retry_at = excluded.retry_at",
+            params![
+                failure.guid,
+                failure.begins_at,
+                failure.ends_at,
+                failure.channel,
+                failure.channel_title,
+                failure.title,
+                failure.airing,
+                failure.error,
+                failure.attempts,
+                failure.retry_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The journalled failure for an airing, if scheduling it has failed before
+    pub fn failure(&self, guid: &str, begins_at: i64) -> Result<Option<FailedRecording>> {
+        Ok(self
+            .query_failures(
+                "WHERE guid = ?1 AND begins_at = ?2",
+                params![guid, begins_at],
+            )?
+            .pop())
+    }
+
+    /// Failures still waiting to be retried, soonest first
+    pub fn pending_failures(&self) -> Result<Vec<FailedRecording>> {
+        self.query_failures("WHERE retry_at IS NOT NULL ORDER BY retry_at", [])
+    }
+
+    /// Failures for airings ending after the given time, latest first
+    pub fn failures(&self, since: i64) -> Result<Vec<FailedRecording>> {
+        self.query_failures("WHERE ends_at >= ?1 ORDER BY begins_at DESC", [since])
+    }
+
+    /// Forgets a failure once the airing has been scheduled
+    pub fn remove_failure(&self, guid: &str, begins_at: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM failures WHERE guid = ?1 AND begins_at = ?2",
+            params![guid, begins_at],
+        )?;
+        Ok(())
+    }
+
+    fn query_failures(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<FailedRecording>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT guid, begins_at, ends_at, channel, channel_title, title,
+                    airing, error, attempts, retry_at
+             FROM failures {}",
+            filter
+        ))?;
+        let failures = stmt
+            .query_map(params, |r| {
+                Ok(FailedRecording {
+                    guid: r.get(0)?,
+                    begins_at: r.get(1)?,
+                    ends_at: r.get(2)?,
+                    channel: r.get(3)?,
+                    channel_title: r.get(4)?,
+                    title: r.get(5)?,
+                    airing: r.get(6)?,
+                    error: r.get(7)?,
+                    attempts: r.get(8)?,
+                    retry_at: r.get(9)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(failures)
+    }
+
+    pub fn record_event(&self, event: &Event) -> Result<()> {
+        let (kind, title, channel, begins_at, error) = match event {
+            Event::Scheduled {
+                title,
+                channel,
+                begins_at,
+                ..
+            } => (
+                "scheduled",
+                Some(title),
+                Some(channel),
+                Some(begins_at),
+                None,
+            ),
+            Event::Recorded { title, .. } => ("recorded", Some(title), None, None, None),
+            Event::Deleted { title, reason } => ("deleted", Some(title), None, None, Some(reason)),
+            Event::Dropped {
+                title,
+                channel,
+                begins_at,
+                reason,
+                ..
+            } => (
+                "dropped",
+                Some(title),
+                Some(channel),
+                Some(begins_at),
+                Some(reason),
+            ),
+            // Digests list what was scheduled rather than every change to the plan
+            Event::PlanChanged { .. } | Event::Report { .. } => return Ok(()),
+            Event::Failed {
+                title,
+                channel,
+                error,
+            } => (
+                "failed",
+                title.as_ref(),
+                channel.as_ref(),
+                None,
+                Some(error),
+            ),
+        };
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO history (at, kind, title, channel, begins_at, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.now().timestamp(),
+                kind,
+                title,
+                channel,
+                begins_at,
+                error
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Notes that Plex has started recording something
+    pub fn record_grab(&self, title: &str, show_title: Option<&str>) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO recordings (title, show_title, grabbed_at) VALUES (?1, ?2, ?3)",
+            params![title, show_title, self.now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Matches a new library item to the recording that produced it.
+    /// Returns false if nothing was being recorded with that title.
+    pub fn record_added(
+        &self,
+        title: &str,
+        show_title: Option<&str>,
+        rating_key: &str,
+    ) -> Result<bool> {
+        let updated = self.conn.lock().unwrap().execute(
+            "UPDATE recordings SET rating_key = ?3, added_at = ?4 WHERE id = (
+                SELECT id FROM recordings
+                WHERE title = ?1 AND IFNULL(show_title, '') = IFNULL(?2, '') AND added_at IS NULL
+                ORDER BY grabbed_at DESC LIMIT 1
+            )",
+            params![title, show_title, rating_key, self.now().timestamp()],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Whether an airing has already been noted as left out for lack of a tuner
+    pub fn was_dropped(&self, title: &str, channel: &str, begins_at: i64) -> Result<bool> {
+        let dropped = self.conn.lock().unwrap().query_row(
+            "SELECT EXISTS (SELECT 1 FROM history
+             WHERE kind = 'dropped' AND title = ?1 AND channel = ?2 AND begins_at = ?3)",
+            params![title, channel, begins_at],
+            |r| r.get(0),
+        )?;
+        Ok(dropped)
+    }
+
+    pub fn history_since(&self, since: i64) -> Result<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let history = conn
+            .prepare(
+                "SELECT at, kind, title, channel, begins_at, error FROM history
+                 WHERE at >= ?1 ORDER BY at, rowid",
+            )?
+            .query_map([since], |r| {
+                Ok(HistoryEntry {
+                    at: r.get(0)?,
+                    kind: r.get(1)?,
+                    title: r.get(2)?,
+                    channel: r.get(3)?,
+                    begins_at: r.get(4)?,
+                    error: r.get(5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(history)
+    }
+
+    /// Adds to the decision log, leaving out decisions that are the same as
+    /// the last one made about their airing, as most passes decide the same
+    pub fn record_decisions(&self, decisions: &[Decision]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut last = tx.prepare(
+                "SELECT outcome, reason FROM decisions WHERE guid = ?1 AND begins_at = ?2
+                 ORDER BY at DESC, rowid DESC LIMIT 1",
+            )?;
+            let mut insert = tx.prepare(
+                "INSERT INTO decisions (at, guid, begins_at, channel_title, title, outcome, reason)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for d in decisions {
+                let previous: Option<(String, Option<String>)> = last
+                    .query_row(params![d.guid, d.begins_at], |r| Ok((r.get(0)?, r.get(1)?)))
+                    .optional()?;
+                if previous
+                    .is_some_and(|(outcome, reason)| outcome == d.outcome && reason == d.reason)
+                {
+                    continue;
+                }
+                insert.execute(params![
+                    d.at,
+                    d.guid,
+                    d.begins_at,
+                    d.channel_title,
+                    d.title,
+                    d.outcome,
+                    d.reason
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Decisions made since a time, oldest first
+    pub fn decisions_since(&self, since: i64) -> Result<Vec<Decision>> {
+        let conn = self.conn.lock().unwrap();
+        let decisions = conn
+            .prepare(
+                "SELECT at, guid, begins_at, channel_title, title, outcome, reason FROM decisions
+                 WHERE at >= ?1 ORDER BY at, rowid",
+            )?
+            .query_map([since], |r| {
+                Ok(Decision {
+                    at: r.get(0)?,
+                    guid: r.get(1)?,
+                    begins_at: r.get(2)?,
+                    channel_title: r.get(3)?,
+                    title: r.get(4)?,
+                    outcome: r.get(5)?,
+                    reason: r.get(6)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(decisions)
+    }
+
+    /// Channel of the latest scheduled airing with this title, or an alias
+    pub fn scheduled_channel(&self, title: &str, aliases: &TitleAliases) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT title, channel FROM history WHERE kind = 'scheduled'
+             ORDER BY at DESC, rowid DESC",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let scheduled: Option<String> = row.get(0)?;
+            if scheduled.is_some_and(|s| aliases.same(&s, title)) {
+                return Ok(row.get(1)?);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Recordings that are in the library and may be cleaned up
+    pub fn library_recordings(&self) -> Result<Vec<LibraryRecording>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, IFNULL(show_title || ' - ', '') || title, rating_key
+             FROM recordings WHERE rating_key IS NOT NULL ORDER BY added_at, id",
+        )?;
+        let recordings = stmt
+            .query_map([], |r| {
+                Ok(LibraryRecording {
+                    id: r.get(0)?,
+                    title: r.get(1)?,
+                    rating_key: r.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(recordings)
+    }
+
+    /// Stops tracking a recording once it has left the library
+    pub fn remove_recording(&self, id: i64) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM recordings WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    pub fn record_cleanup(&self, deleted: i64, bytes_freed: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO cleanups (ran_at, deleted, bytes_freed) VALUES (?1, ?2, ?3)",
+            params![self.now().timestamp(), deleted, bytes_freed],
+        )?;
+        Ok(())
+    }
+
+    pub fn cleanup_since(&self, since: i64) -> Result<CleanupStats> {
+        let conn = self.conn.lock().unwrap();
+        let stats = conn.query_row(
+            "SELECT COUNT(*), MAX(ran_at), IFNULL(SUM(deleted), 0), IFNULL(SUM(bytes_freed), 0)
+             FROM cleanups WHERE ran_at >= ?1",
+            [since],
+            |r| {
+                Ok(CleanupStats {
+                    runs: r.get(0)?,
+                    last_run: r.get(1)?,
+                    deleted: r.get(2)?,
+                    bytes_freed: r.get(3)?,
+                })
+            },
+        )?;
+        Ok(stats)
+    }
+
+    /// Adds the counts from a pass to each channel's running totals
+    pub fn add_channel_stats(&self, stats: &[ChannelStats]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for s in stats {
+            tx.execute(
+                "INSERT INTO channel_stats (channel, channel_title, seen, scheduled, skipped, failed)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (channel) DO UPDATE SET
+                    channel_title = excluded.channel_title,
+                    seen = seen + excluded.seen,
+                    scheduled = scheduled + excluded.scheduled,
+                    skipped = skipped + excluded.skipped,
+                    failed = failed + excluded.failed",
+                params![s.channel, s.channel_title, s.seen, s.scheduled, s.skipped, s.failed],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn status(&self) -> Result<StatusReport> {
+        let started_at = self.daemon_value("started_at")?;
+        let next_wake = self.daemon_value("next_wake")?;
+        let cleanup = self.cleanup_since(0)?;
+        let failures = self.failures(self.now().timestamp() - CALENDAR_HISTORY)?;
+
+        let conn = self.conn.lock().unwrap();
+
+        let last_pass = conn
+            .query_row(
+                "SELECT finished_at FROM passes WHERE error IS NULL ORDER BY id DESC LIMIT 1",
+                [],
+                |r| r.get(0),
+            )
+            .optional()?;
+
+        let upcoming = conn
+            .prepare(
+                "SELECT channel, channel_title, title, begins_at FROM upcoming ORDER BY begins_at",
+            )?
+            .query_map([], |r| {
+                Ok(UpcomingRecording {
+                    channel: r.get(0)?,
+                    channel_title: r.get(1)?,
+                    title: r.get(2)?,
+                    begins_at: r.get(3)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        let channels = conn
+            .prepare(
+                "SELECT channel, channel_title, seen, scheduled, skipped, failed
+                 FROM channel_stats ORDER BY channel_title",
+            )?
+            .query_map([], |r| {
+                Ok(ChannelStats {
+                    channel: r.get(0)?,
+                    channel_title: r.get(1)?,
+                    seen: r.get(2)?,
+                    scheduled: r.get(3)?,
+                    skipped: r.get(4)?,
+                    failed: r.get(5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        let errors = conn
+            .prepare(
+                "SELECT finished_at, error FROM passes WHERE error IS NOT NULL
+                 ORDER BY id DESC LIMIT ?1",
+            )?
+            .query_map([RECENT_ERRORS], |r| {
+                Ok(PassError {
+                    at: r.get(0)?,
+                    error: r.get(1)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(StatusReport {
+            started_at,
+            last_pass,
+            next_wake,
+            upcoming,
+            channels,
+            errors,
+            failures,
+            cleanup,
+        })
+    }
+
+    /// Claims an airing for subscribing to, returning false if it's already
+    /// been subscribed to or is being subscribed to now. Claims left unfinished
+    /// for a while, by a pass that died part way, can be taken over.
+    pub fn claim_airing(&self, guid: &str, begins_at: i64, now: i64) -> Result<bool> {
+        let claimed = self.conn.lock().unwrap().execute(
+            "INSERT INTO claims (guid, begins_at, claimed_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (guid, begins_at) DO UPDATE SET claimed_at = excluded.claimed_at
+             WHERE scheduled = 0 AND claimed_at < ?4",
+            params![guid, begins_at, now, now - CLAIM_TIMEOUT],
+        )?;
+        Ok(claimed > 0)
+    }
+
+    /// Finishes a claim, keeping it if the airing was subscribed to so it
+    /// isn't again, otherwise releasing it to be tried again
+    pub fn finish_claim(&self, guid: &str, begins_at: i64, scheduled: bool) -> Result<()> {
+        let sql = if scheduled {
+            "UPDATE claims SET scheduled = 1 WHERE guid = ?1 AND begins_at = ?2"
+        } else {
+            "DELETE FROM claims WHERE guid = ?1 AND begins_at = ?2"
+        };
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(sql, params![guid, begins_at])?;
+        Ok(())
+    }
+
+    /// Releases every unfinished claim, once the subscriptions they were for
+    /// have been abandoned
+    pub fn release_claims(&self) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM claims WHERE scheduled = 0", [])?;
+        Ok(())
+    }
+
+    /// Deletes history, journalled failures and cached data from before a time,
+    /// returning how many rows went. Recordings still in the library are kept
+    /// since cleanup needs them.
+    pub fn prune(&self, before: i64) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut deleted = 0;
+        for sql in [
+            "DELETE FROM passes WHERE finished_at < ?1",
+            "DELETE FROM history WHERE at < ?1",
+            "DELETE FROM decisions WHERE at < ?1",
+            "DELETE FROM cleanups WHERE ran_at < ?1",
+            "DELETE FROM failures WHERE ends_at < ?1",
+            "DELETE FROM claims WHERE begins_at < ?1",
+            "DELETE FROM recordings WHERE rating_key IS NULL AND grabbed_at < ?1",
+            "DELETE FROM ratings WHERE fetched_at < ?1",
+            "DELETE FROM grid_cache WHERE fetched_at < ?1",
+        ] {
+            deleted += tx.execute(sql, [before])?;
+        }
+        tx.commit()?;
+        // Give the freed pages back so the file actually shrinks
+        if deleted > 0 {
+            conn.execute("VACUUM", [])?;
+        }
+        Ok(deleted)
+    }
+
+    /// Every table's rows as JSON, for backups and moving hosts.
+    /// This includes OAuth tokens, so the dump should be kept private.
+    pub fn export(&self) -> Result<serde_json::Value> {
+        let conn = self.conn.lock().unwrap();
+        let mut tables = serde_json::Map::new();
+        for table in TABLES {
+            let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table))?;
+            let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+            let rows = stmt
+                .query_map([], |r| {
+                    let mut row = serde_json::Map::new();
+                    for (i, column) in columns.iter().enumerate() {
+                        let value = match r.get::<_, SqlValue>(i)? {
+                            SqlValue::Null | SqlValue::Blob(_) => serde_json::Value::Null,
+                            SqlValue::Integer(n) => n.into(),
+                            SqlValue::Real(n) => n.into(),
+                            SqlValue::Text(s) => s.into(),
+                        };
+                        row.insert(column.clone(), value);
+                    }
+                    Ok(serde_json::Value::Object(row))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            tables.insert(table.to_string(), rows.into());
+        }
+        Ok(json!({ "version": EXPORT_VERSION, "tables": tables }))
+    }
+
+    /// Replaces the contents of every table in an export, leaving the rest alone
+    pub fn import(&self, dump: &serde_json::Value) -> Result<()> {
+        let invalid = |msg: String| StateError::InvalidDump(msg);
+        if dump["version"] != EXPORT_VERSION {
+            return Err(invalid(format!("unsupported version {}", dump["version"])));
+        }
+        let tables = dump["tables"]
+            .as_object()
+            .ok_or_else(|| invalid("missing tables".into()))?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (table, rows) in tables {
+            if !TABLES.contains(&table.as_str()) {
+                return Err(invalid(format!("unknown table {}", table)));
+            }
+            // Columns come from the schema rather than the dump, so nothing
+            // from the file ends up in the SQL itself
+            let columns: Vec<String> = tx
+                .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?
+                .query_map([], |r| r.get(0))?
+                .collect::<Result<_, _>>()?;
+
+            tx.execute(&format!("DELETE FROM {}", table), [])?;
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                columns.join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            let mut insert = tx.prepare(&sql)?;
+            for row in rows
+                .as_array()
+                .ok_or_else(|| invalid(format!("{} isn't a list of rows", table)))?
+            {
+                let values = columns
+                    .iter()
+                    .map(|c| match &row[c] {
+                        serde_json::Value::Null => Ok(SqlValue::Null),
+                        serde_json::Value::Bool(b) => Ok(SqlValue::Integer(*b as i64)),
+                        serde_json::Value::Number(n) => Ok(n
+                            .as_i64()
+                            .map(SqlValue::Integer)
+                            .unwrap_or_else(|| SqlValue::Real(n.as_f64().unwrap_or_default()))),
+                        serde_json::Value::String(s) => Ok(SqlValue::Text(s.clone())),
+                        other => Err(invalid(format!("{}.{} can't be {}", table, c, other))),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                insert.execute(params_from_iter(values))?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config::redacted;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const HISTORY_LENGTH: &str = "1000";
+/// Tautulli marks a play as watched once it passes the user's watched percentage
+const WATCHED: f64 = 1.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TautulliError {
+    #[error("Failed to request data from Tautulli: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Tautulli returned an error: {0}")]
+    Api(String),
+}
+
+pub type Result<T, E = TautulliError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct TautulliConfig {
+    pub tautulli_url: Option<String>,
+    pub tautulli_api_key: Option<String>,
+}
+
+impl std::fmt::Debug for TautulliConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TautulliConfig")
+            .field("tautulli_url", &self.tautulli_url)
+            .field("tautulli_api_key", &redacted(&self.tautulli_api_key))
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Play {
+    user: String,
+    friendly_name: Option<String>,
+    watched_status: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct History {
+    data: Vec<Play>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    result: String,
+    message: Option<String>,
+    data: Option<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope<T> {
+    response: Response<T>,
+}
+
+pub struct Tautulli {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+}
+
+impl Tautulli {
+    pub fn new(config: TautulliConfig) -> Option<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Tautulli client is valid");
+        Some(Tautulli {
+            client,
+            url: config.tautulli_url?.trim_end_matches('/').to_string(),
+            api_key: config.tautulli_api_key?,
+        })
+    }
+
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        cmd: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T> {
+        let envelope: Envelope<T> = self
+            .client
+            .get(format!("{}/api/v2", self.url))
+            .query(&[("apikey", self.api_key.as_str()), ("cmd", cmd)])
+            .query(query)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.without_url())?
+            .json()
+            .await?;
+        let response = envelope.response;
+        match response.data {
+            Some(data) if response.result == "success" => Ok(data),
+            _ => Err(TautulliError::Api(
+                response.message.unwrap_or(response.result),
+            )),
+        }
+    }
+
+    /// Lowercased usernames and friendly names of everyone who has finished
+    /// watching the item
+    pub async fn watched_by(&self, rating_key: &str) -> Result<HashSet<String>> {
+        let history: History = self
+            .call(
+                "get_history",
+                &[("rating_key", rating_key), ("length", HISTORY_LENGTH)],
+            )
+            .await?;
+        let users = history
+            .data
+            .into_iter()
+            .filter(|p| p.watched_status >= WATCHED)
+            .flat_map(|p| [Some(p.user), p.friendly_name])
+            .flatten()
+            .map(|u| u.to_lowercase())
+            .collect();
+        Ok(users)
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+/// Compares titles ignoring case and punctuation, since guide data and
+/// other services rarely agree on exact names
+pub fn normalize(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Other names shows and films go by in guides, e.g. "NCIS: LA" for "NCIS:
+/// Los Angeles", so they're recognised under any of them
+#[derive(Debug, Clone, Default)]
+pub struct TitleAliases(HashMap<String, String>);
+
+impl TitleAliases {
+    /// From a map of each alias to the title it stands for
+    pub fn new(aliases: &HashMap<String, String>) -> Self {
+        TitleAliases(
+            aliases
+                .iter()
+                .map(|(alias, title)| (normalize(alias), title.clone()))
+                .collect(),
+        )
+    }
+
+    /// The title the given one is an alias for, or itself if it isn't one
+    pub fn canonical<'a>(&'a self, title: &'a str) -> &'a str {
+        self.0.get(&normalize(title)).map_or(title, String::as_str)
+    }
+
+    /// Normalized canonical title, the same for a title and all its aliases
+    pub fn key(&self, title: &str) -> String {
+        normalize(self.canonical(title))
+    }
+
+    /// Whether two titles are the same show or film
+    pub fn same(&self, a: &str, b: &str) -> bool {
+        self.key(a) == self.key(b)
+    }
+}
@@ -0,0 +1,160 @@
+use crate::config::redacted;
+use crate::plex::{GridMetadata, GridMetadataType};
+use crate::state::{self, State};
+use crate::title::normalize;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+const API_URL: &str = "https://api.themoviedb.org/3";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Ratings drift slowly, so cached lookups are reused for a week
+const CACHE_TTL: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TmdbError {
+    #[error("Failed to request data from TMDB: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    State(#[from] state::StateError),
+}
+
+pub type Result<T, E = TmdbError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct TmdbConfig {
+    pub tmdb_api_key: Option<String>,
+    /// Only record films rated at least this on TMDB (0-10)
+    pub min_film_rating: Option<f64>,
+    /// Only record shows rated at least this on TMDB (0-10)
+    pub min_show_rating: Option<f64>,
+}
+
+impl std::fmt::Debug for TmdbConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TmdbConfig")
+            .field("tmdb_api_key", &redacted(&self.tmdb_api_key))
+            .field("min_film_rating", &self.min_film_rating)
+            .field("min_show_rating", &self.min_show_rating)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    vote_average: f64,
+    #[serde(default)]
+    vote_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Search {
+    results: Vec<SearchResult>,
+}
+
+pub struct Tmdb {
+    client: reqwest::Client,
+    api_key: String,
+    state: Arc<State>,
+    min_film_rating: Option<f64>,
+    min_show_rating: Option<f64>,
+}
+
+impl Tmdb {
+    /// Returns `None` unless an API key and at least one threshold are set
+    pub fn new(config: &TmdbConfig, state: Arc<State>) -> Option<Self> {
+        if config.min_film_rating.is_none() && config.min_show_rating.is_none() {
+            return None;
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("TMDB client is valid");
+        Some(Tmdb {
+            client,
+            api_key: config.tmdb_api_key.clone()?,
+            state,
+            min_film_rating: config.min_film_rating,
+            min_show_rating: config.min_show_rating,
+        })
+    }
+
+    /// Whether the airing falls below the configured rating.
+    /// Titles TMDB doesn't know, or that nobody has rated, aren't held back.
+    pub async fn below_threshold(&self, show: &GridMetadata) -> Result<bool> {
+        let is_film = matches!(show.r#type, GridMetadataType::Movie);
+        let threshold = if is_film {
+            self.min_film_rating
+        } else {
+            self.min_show_rating
+        };
+        let threshold = match threshold {
+            Some(threshold) => threshold,
+            None => return Ok(false),
+        };
+
+        let below = match self.rating(show, is_film).await? {
+            Some(rating) if rating < threshold => {
+                log::info!(
+                    "{} is rated {:.1} on TMDB, below {:.1}",
+                    show.show_title(),
+                    rating,
+                    threshold
+                );
+                true
+            }
+            _ => false,
+        };
+        Ok(below)
+    }
+
+    async fn rating(&self, show: &GridMetadata, is_film: bool) -> Result<Option<f64>> {
+        let title = show.show_title();
+        // Shows span years, so only films are narrowed down by release year
+        let year = show.year().filter(|_| is_film);
+        let key = match year {
+            Some(year) => format!("movie:{}:{}", normalize(&title), year),
+            None if is_film => format!("movie:{}", normalize(&title)),
+            None => format!("tv:{}", normalize(&title)),
+        };
+
+        if let Some((rating, fetched_at)) = self.state.cached_rating(&key)? {
+            if Utc::now().timestamp() - fetched_at < CACHE_TTL {
+                return Ok(rating);
+            }
+        }
+
+        let year = year.map(|y| y.to_string());
+        let mut query = vec![("api_key", self.api_key.as_str()), ("query", &title)];
+        let resource = if is_film {
+            if let Some(year) = &year {
+                query.push(("year", year));
+            }
+            "search/movie"
+        } else {
+            "search/tv"
+        };
+
+        let search: Search = self
+            .client
+            .get(format!("{}/{}", API_URL, resource))
+            .query(&query)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.without_url())?
+            .json()
+            .await?;
+        let rating = search
+            .results
+            .first()
+            .filter(|r| r.vote_count > 0)
+            .map(|r| r.vote_average);
+
+        log::debug!("TMDB rating for {}: {:?}", title, rating);
+        self.state.set_cached_rating(&key, rating)?;
+        Ok(rating)
+    }
+}
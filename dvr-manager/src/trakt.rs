@@ -0,0 +1,248 @@
+use crate::config::redacted;
+use crate::state::{self, State, Token};
+use crate::title::normalize;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+const API_URL: &str = "https://api.trakt.tv";
+const TOKEN_SERVICE: &str = "trakt";
+const REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The watchlist changes rarely, so it's only refetched this often
+const WATCHLIST_TTL: Duration = Duration::from_secs(60 * 60);
+/// Refresh tokens a little before Trakt would reject them
+const REFRESH_MARGIN: i64 = 24 * 60 * 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TraktError {
+    #[error("Failed to request data from Trakt: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    State(#[from] state::StateError),
+
+    #[error("Trakt hasn't been authorized, run `dvr-manager trakt-auth`")]
+    NotAuthorized,
+
+    #[error("Trakt authorization failed: {0}")]
+    Auth(String),
+}
+
+pub type Result<T, E = TraktError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct TraktConfig {
+    pub trakt_client_id: Option<String>,
+    pub trakt_client_secret: Option<String>,
+}
+
+impl std::fmt::Debug for TraktConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraktConfig")
+            .field("trakt_client_id", &self.trakt_client_id)
+            .field("trakt_client_secret", &redacted(&self.trakt_client_secret))
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_url: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: i64,
+    created_at: i64,
+}
+
+impl From<TokenResponse> for Token {
+    fn from(t: TokenResponse) -> Self {
+        Token {
+            access_token: t.access_token,
+            refresh_token: t.refresh_token,
+            expires_at: t.created_at + t.expires_in,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchlistTitle {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchlistItem {
+    show: Option<WatchlistTitle>,
+    movie: Option<WatchlistTitle>,
+}
+
+/// Reads the user's Trakt watchlist so listed titles are recorded when they air
+pub struct Trakt {
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: String,
+    state: Arc<State>,
+    watchlist: Mutex<Option<(Instant, HashSet<String>)>>,
+}
+
+impl Trakt {
+    /// Returns `None` if Trakt isn't configured
+    pub fn new(config: &TraktConfig, state: Arc<State>) -> Option<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Trakt client is valid");
+        Some(Trakt {
+            client,
+            client_id: config.trakt_client_id.clone()?,
+            client_secret: config.trakt_client_secret.clone()?,
+            state,
+            watchlist: Mutex::new(None),
+        })
+    }
+
+    /// Runs the device flow, asking the user to approve access in a browser
+    pub async fn authorize(&self) -> Result<()> {
+        let code: DeviceCode = self
+            .client
+            .post(format!("{}/oauth/device/code", API_URL))
+            .json(&json!({ "client_id": self.client_id }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        println!(
+            "Go to {} and enter the code {}",
+            code.verification_url, code.user_code
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+        while Instant::now() < deadline {
+            sleep(interval).await;
+            let response = self
+                .client
+                .post(format!("{}/oauth/device/token", API_URL))
+                .json(&json!({
+                    "code": code.device_code,
+                    "client_id": self.client_id,
+                    "client_secret": self.client_secret,
+                }))
+                .send()
+                .await?;
+            match response.status().as_u16() {
+                200 => {
+                    let token: TokenResponse = response.json().await?;
+                    self.state.set_token(TOKEN_SERVICE, &token.into())?;
+                    println!("Trakt authorized");
+                    return Ok(());
+                }
+                // Still waiting for the user
+                400 => (),
+                429 => interval += Duration::from_secs(1),
+                404 => return Err(TraktError::Auth("invalid device code".into())),
+                409 => return Err(TraktError::Auth("code already used".into())),
+                410 => break,
+                418 => return Err(TraktError::Auth("access denied".into())),
+                status => return Err(TraktError::Auth(format!("unexpected status {}", status))),
+            }
+        }
+        Err(TraktError::Auth("code expired".into()))
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        let token = self
+            .state
+            .token(TOKEN_SERVICE)?
+            .ok_or(TraktError::NotAuthorized)?;
+        if token.expires_at - REFRESH_MARGIN > Utc::now().timestamp() {
+            return Ok(token.access_token);
+        }
+
+        log::info!("Refreshing Trakt token");
+        let refreshed: TokenResponse = self
+            .client
+            .post(format!("{}/oauth/token", API_URL))
+            .json(&json!({
+                "refresh_token": token.refresh_token,
+                "client_id": self.client_id,
+                "client_secret": self.client_secret,
+                "redirect_uri": REDIRECT_URI,
+                "grant_type": "refresh_token",
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let token: Token = refreshed.into();
+        self.state.set_token(TOKEN_SERVICE, &token)?;
+        Ok(token.access_token)
+    }
+
+    async fn fetch_watchlist(&self) -> Result<HashSet<String>> {
+        let token = self.access_token().await?;
+        let mut titles = HashSet::new();
+        for kind in ["shows", "movies"] {
+            let items: Vec<WatchlistItem> = self
+                .client
+                .get(format!("{}/sync/watchlist/{}", API_URL, kind))
+                .bearer_auth(&token)
+                .header("trakt-api-version", "2")
+                .header("trakt-api-key", &self.client_id)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            titles.extend(
+                items
+                    .into_iter()
+                    .filter_map(|i| i.show.or(i.movie))
+                    .map(|t| normalize(&t.title)),
+            );
+        }
+        Ok(titles)
+    }
+
+    /// Normalized titles on the watchlist. If Trakt can't be reached the
+    /// last known list is used, so a blip doesn't stop recordings.
+    pub async fn watchlist(&self) -> HashSet<String> {
+        let mut cached = self.watchlist.lock().await;
+        if let Some((fetched, titles)) = cached.as_ref() {
+            if fetched.elapsed() < WATCHLIST_TTL {
+                return titles.clone();
+            }
+        }
+
+        match self.fetch_watchlist().await {
+            Ok(titles) => {
+                log::debug!("Trakt watchlist has {} titles", titles.len());
+                *cached = Some((Instant::now(), titles.clone()));
+                titles
+            }
+            Err(e) => {
+                log::warn!("Couldn't fetch Trakt watchlist: {}", e);
+                cached
+                    .as_ref()
+                    .map(|(_, titles)| titles.clone())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
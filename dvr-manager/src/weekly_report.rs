@@ -0,0 +1,217 @@
+use crate::digest::{Digest, DigestPeriod, Periodic};
+use crate::notify::{self, Event, Notifiers};
+use crate::state::{self, CalendarEntry, State};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Upcoming recordings listed in a report
+const HIGHLIGHTS: usize = 10;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct WeeklyReportConfig {
+    /// Send a report on the past week through the notifiers
+    #[serde(default)]
+    pub weekly_report: bool,
+    /// Also write the report here, e.g. `/config/weekly-report.md`, as HTML
+    /// if the file ends in `.html` and Markdown otherwise
+    pub weekly_report_path: Option<String>,
+}
+
+impl WeeklyReportConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.weekly_report || self.weekly_report_path.is_some()
+    }
+}
+
+/// What was recorded over the past week, what went wrong and what's coming up
+pub struct WeeklyReport {
+    /// Seconds recorded, by channel
+    recorded: BTreeMap<String, i64>,
+    /// Problems and cleanup over the week
+    digest: Digest,
+    highlights: Vec<CalendarEntry>,
+}
+
+impl WeeklyReport {
+    /// Recordings count once they've finished. Finished ones stay in the
+    /// calendar for a week, so are all still there.
+    pub fn build(state: &State, now: DateTime<Utc>) -> state::Result<Self> {
+        let digest = Digest::build_at(state, DigestPeriod::Weekly, now)?;
+        let now = now.timestamp();
+        let since = now - Duration::weeks(1).num_seconds();
+        let calendar = state.calendar()?;
+
+        let mut recorded = BTreeMap::new();
+        for e in calendar
+            .iter()
+            .filter(|e| e.scheduled && e.ends_at >= since && e.ends_at <= now)
+        {
+            *recorded.entry(e.channel_title.clone()).or_default() += e.ends_at - e.begins_at;
+        }
+
+        Ok(WeeklyReport {
+            recorded,
+            digest,
+            highlights: calendar
+                .into_iter()
+                .filter(|e| e.begins_at >= now)
+                .take(HIGHLIGHTS)
+                .collect(),
+        })
+    }
+
+    fn hours(seconds: i64) -> String {
+        format!("{:.1}", seconds as f64 / 3600.0)
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "Weekly DVR report: {} hours recorded, {} problems",
+            Self::hours(self.recorded.values().sum()),
+            self.digest.problems().count()
+        )
+    }
+
+    fn highlight_lines(&self) -> Vec<String> {
+        self.highlights
+            .iter()
+            .map(|e| {
+                format!(
+                    "{}  {} ({})",
+                    notify::format_time(e.begins_at),
+                    e.title,
+                    e.channel_title
+                )
+            })
+            .collect()
+    }
+
+    pub fn markdown(&self) -> String {
+        let list = |lines: Vec<String>, empty: &str| {
+            if lines.is_empty() {
+                format!("{}\n", empty)
+            } else {
+                lines.iter().map(|l| format!("- {}\n", l)).collect()
+            }
+        };
+        let recorded = self
+            .recorded
+            .iter()
+            .map(|(channel, seconds)| format!("{}: {} hours", channel, Self::hours(*seconds)))
+            .collect();
+
+        let mut body = format!("# {}\n", self.title());
+        body += "\n## Recorded\n\n";
+        body += &list(recorded, "Nothing was recorded.");
+        let problems = self.digest.problem_lines();
+        body += &format!("\n## Problems ({})\n\n", problems.len());
+        body += &list(problems, "None.");
+        body += "\n## Cleanup\n\n";
+        body += &format!("{}\n", self.digest.cleanup_line());
+        body += "\n## Coming up\n\n";
+        body += &list(self.highlight_lines(), "Nothing yet.");
+        body
+    }
+
+    pub fn html(&self) -> String {
+        let list = |lines: Vec<String>, empty: &str| {
+            if lines.is_empty() {
+                format!("<p>{}</p>\n", empty)
+            } else {
+                let items: String = lines
+                    .iter()
+                    .map(|l| format!("<li>{}</li>\n", escape(l)))
+                    .collect();
+                format!("<ul>\n{}</ul>\n", items)
+            }
+        };
+        let recorded: String = self
+            .recorded
+            .iter()
+            .map(|(channel, seconds)| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td></tr>\n",
+                    escape(channel),
+                    Self::hours(*seconds)
+                )
+            })
+            .collect();
+
+        let title = escape(&self.title());
+        let mut body = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+            title, title
+        );
+        body += "<h2>Recorded</h2>\n";
+        if recorded.is_empty() {
+            body += "<p>Nothing was recorded.</p>\n";
+        } else {
+            body += &format!(
+                "<table>\n<tr><th>Channel</th><th>Hours</th></tr>\n{}</table>\n",
+                recorded
+            );
+        }
+        let problems = self.digest.problem_lines();
+        body += &format!("<h2>Problems ({})</h2>\n", problems.len());
+        body += &list(problems, "None.");
+        body += "<h2>Cleanup</h2>\n";
+        body += &format!("<p>{}</p>\n", self.digest.cleanup_line());
+        body += "<h2>Coming up</h2>\n";
+        body += &list(self.highlight_lines(), "Nothing yet.");
+        body += "</body>\n</html>\n";
+        body
+    }
+
+    /// Writes the report as HTML or Markdown, going by the file's extension
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let html = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("html"));
+        std::fs::write(path, if html { self.html() } else { self.markdown() })
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Runs forever, making a report each week
+pub async fn run(state: Arc<State>, notifiers: Arc<Notifiers>, config: WeeklyReportConfig) {
+    let periodic = Periodic {
+        period: Duration::weeks(1),
+        last: State::last_weekly_report,
+        set_last: State::set_last_weekly_report,
+    };
+    let config = Arc::new(config);
+    periodic
+        .run(state.clone(), "the weekly report", |now| {
+            let state = state.clone();
+            let notifiers = notifiers.clone();
+            let config = config.clone();
+            async move {
+                let report = WeeklyReport::build(&state, now).map_err(|e| e.to_string())?;
+                if let Some(path) = &config.weekly_report_path {
+                    report
+                        .write(Path::new(path))
+                        .map_err(|e| format!("couldn't write {}: {}", path, e))?;
+                }
+                if config.weekly_report {
+                    notifiers
+                        .send(Event::Report {
+                            title: report.title(),
+                            body: report.markdown(),
+                        })
+                        .await;
+                }
+                log::info!("Made the weekly report");
+                Ok(())
+            }
+        })
+        .await
+}
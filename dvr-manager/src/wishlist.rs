@@ -0,0 +1,53 @@
+use crate::plex::GridMetadata;
+
+/// Phrases, e.g. a keyword, actor or title, for which any airing mentioning
+/// one is recorded, whichever channel it's on
+#[derive(Debug, Default)]
+pub struct Wishlist {
+    phrases: Vec<(String, Vec<String>)>,
+}
+
+impl Wishlist {
+    pub fn new(phrases: &[String]) -> Self {
+        let phrases = phrases
+            .iter()
+            .map(|p| (p.trim().to_string(), words(p)))
+            .filter(|(_, words)| !words.is_empty())
+            .collect();
+        Wishlist { phrases }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    /// The first phrase found in the airing's show or episode title, or its
+    /// summary. Whole words are matched ignoring case and punctuation, so
+    /// "art" doesn't pick up "Smart Homes".
+    pub fn find(&self, show: &GridMetadata) -> Option<&str> {
+        if self.is_empty() {
+            return None;
+        }
+        let texts = [
+            show.grandparent_title.as_deref(),
+            Some(show.title.as_str()),
+            show.summary.as_deref(),
+        ];
+        let texts: Vec<_> = texts.into_iter().flatten().map(words).collect();
+        self.phrases
+            .iter()
+            .find(|(_, phrase)| {
+                texts
+                    .iter()
+                    .any(|text| text.windows(phrase.len()).any(|w| w == phrase.as_slice()))
+            })
+            .map(|(phrase, _)| phrase.as_str())
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
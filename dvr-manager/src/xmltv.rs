@@ -0,0 +1,231 @@
+use crate::plex::{Channel, GridMedia, GridMetadata, GridMetadataType};
+use chrono::{DateTime, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// Guides are usually regenerated daily, so there's no point reading them every pass
+const GUIDE_TTL: Duration = Duration::from_secs(60 * 60);
+const TIME_FORMAT: &str = "%Y%m%d%H%M%S %z";
+const TIME_FORMAT_UTC: &str = "%Y%m%d%H%M%S";
+/// How far ahead to look, the same span as the grid days fetched from Plex
+const LOOKAHEAD: i64 = 48 * 60 * 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum XmltvError {
+    #[error("Failed to download XMLTV guide: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Failed to read XMLTV guide: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Couldn't parse XMLTV guide: {0}")]
+    Parse(#[from] serde_xml_rs::Error),
+}
+
+pub type Result<T, E = XmltvError> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct XmltvConfig {
+    /// Path or URL of the XMLTV guide to pick recordings from instead of Plex's grid
+    pub xmltv_source: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Text {
+    #[serde(rename = "$value", default)]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct XmltvChannel {
+    id: String,
+    #[serde(rename = "display-name", default)]
+    display_names: Vec<Text>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Programme {
+    start: String,
+    stop: Option<String>,
+    channel: String,
+    #[serde(default)]
+    title: Vec<Text>,
+    #[serde(rename = "sub-title", default)]
+    sub_title: Vec<Text>,
+    #[serde(default)]
+    category: Vec<Text>,
+    #[serde(default)]
+    desc: Vec<Text>,
+    date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tv {
+    #[serde(rename = "channel", default)]
+    channels: Vec<XmltvChannel>,
+    #[serde(rename = "programme", default)]
+    programmes: Vec<Programme>,
+}
+
+fn parse_time(time: &str) -> Option<i64> {
+    DateTime::parse_from_str(time.trim(), TIME_FORMAT)
+        .map(|t| t.timestamp())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(time.trim(), TIME_FORMAT_UTC)
+                .map(|t| t.and_utc().timestamp())
+        })
+        .ok()
+}
+
+/// Airings from an XMLTV guide, by channel id
+pub struct Guide {
+    names: HashMap<String, String>,
+    airings: HashMap<String, Vec<GridMetadata>>,
+}
+
+impl Guide {
+    fn parse(xml: &str) -> Result<Self> {
+        let tv: Tv = serde_xml_rs::from_str(xml)?;
+        let names: HashMap<_, _> = tv
+            .channels
+            .into_iter()
+            .filter_map(|c| {
+                let name = c.display_names.into_iter().next()?.value;
+                Some((c.id, name))
+            })
+            .collect();
+
+        let mut airings: HashMap<String, Vec<GridMetadata>> = HashMap::new();
+        for p in tv.programmes {
+            let (Some(begins_at), Some(title)) = (parse_time(&p.start), p.title.into_iter().next())
+            else {
+                continue;
+            };
+            let ends_at = p.stop.as_deref().and_then(parse_time).unwrap_or(begins_at);
+            let is_film = p
+                .category
+                .iter()
+                .any(|c| matches!(c.value.to_lowercase().as_str(), "movie" | "film"));
+            let sub_title = p.sub_title.into_iter().next().map(|s| s.value);
+            let channel_title = names.get(&p.channel).cloned().unwrap_or_default();
+
+            // Only shape needed for candidate selection, the rest comes from Plex
+            // once an airing is close enough to record
+            let airing = GridMetadata {
+                rating_key: String::new(),
+                guid: String::new(),
+                title: sub_title.clone().unwrap_or_else(|| title.value.clone()),
+                grandparent_guid: None,
+                grandparent_title: (!is_film).then_some(title.value),
+                parent_guid: None,
+                parent_title: None,
+                parent_index: None,
+                index: None,
+                r#type: if is_film {
+                    GridMetadataType::Movie
+                } else {
+                    GridMetadataType::Other
+                },
+                duration: Some(((ends_at - begins_at).max(0) * 1000) as u32),
+                on_air: None,
+                subscription_id: None,
+                subscription_type: None,
+                grandparent_subscription_id: None,
+                grandparent_subscription_type: None,
+                grandparent_thumb: None,
+                originally_available_at: p.date,
+                summary: p.desc.into_iter().next().map(|d| d.value),
+                media: vec![GridMedia {
+                    id: 0,
+                    begins_at,
+                    ends_at,
+                    channel_identifier: p.channel.clone(),
+                    channel_title,
+                }],
+            };
+            airings.entry(p.channel).or_default().push(airing);
+        }
+
+        Ok(Guide { names, airings })
+    }
+
+    /// Upcoming airings on a Plex channel, or `None` if the guide doesn't have it
+    pub fn airings(&self, channel: &Channel, now: i64) -> Option<Vec<GridMetadata>> {
+        let id = [channel.identifier.as_ref(), Some(&channel.id)]
+            .into_iter()
+            .flatten()
+            .find(|id| self.airings.contains_key(*id))
+            .or_else(|| {
+                let title = channel.title.as_ref()?;
+                self.names
+                    .iter()
+                    .find(|(_, name)| name.eq_ignore_ascii_case(title))
+                    .map(|(id, _)| id)
+            })?;
+        let airings = self.airings.get(id)?;
+        Some(
+            airings
+                .iter()
+                .filter(|a| {
+                    let begins_at = a.begins_at_ts();
+                    begins_at >= now && begins_at < now + LOOKAHEAD
+                })
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// Reads candidate airings straight from the XMLTV guide feeding Plex
+pub struct Xmltv {
+    client: reqwest::Client,
+    source: String,
+    guide: Mutex<Option<(Instant, Arc<Guide>)>>,
+}
+
+impl Xmltv {
+    pub fn new(config: XmltvConfig) -> Option<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("XMLTV client is valid");
+        Some(Xmltv {
+            client,
+            source: config.xmltv_source?,
+            guide: Mutex::new(None),
+        })
+    }
+
+    async fn fetch(&self) -> Result<Guide> {
+        let xml = if self.source.starts_with("http://") || self.source.starts_with("https://") {
+            self.client
+                .get(&self.source)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        } else {
+            tokio::fs::read_to_string(&self.source).await?
+        };
+        Guide::parse(&xml)
+    }
+
+    /// The guide, reread once it's an hour old
+    pub async fn guide(&self) -> Result<Arc<Guide>> {
+        let mut cached = self.guide.lock().await;
+        if let Some((fetched, guide)) = cached.as_ref() {
+            if fetched.elapsed() < GUIDE_TTL {
+                return Ok(guide.clone());
+            }
+        }
+        let guide = Arc::new(self.fetch().await?);
+        log::debug!("Read XMLTV guide for {} channels", guide.airings.len());
+        *cached = Some((Instant::now(), guide.clone()));
+        Ok(guide)
+    }
+}
@@ -0,0 +1,40 @@
+//! Replays a whole broadcast day against the fake Plex server by a manual
+//! clock, so a change in which airings get picked fails here before release
+
+mod common;
+
+use axum::http::StatusCode;
+use chrono::Duration;
+use common::{day_config, day_start, day_subscriptions, start_with};
+use dvr_manager::clock::{Clock, ManualClock};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn subscribes_to_a_days_airings() {
+    let start = day_start();
+    let end = start + Duration::days(1);
+    let (fake, manager, _state) = start_with(start, "day.json", StatusCode::OK, day_config()).await;
+    let clock = Arc::new(ManualClock::new(start));
+    let manager = manager.with_clock(clock.clone());
+
+    manager.auto_record_until(Some(end)).await.unwrap();
+    assert!(clock.now() >= end);
+
+    assert_eq!(fake.subscribed(), day_subscriptions());
+}
+
+#[tokio::test]
+async fn keeps_history_by_the_days_clock() {
+    let start = day_start();
+    let end = start + Duration::days(1);
+    let (_fake, manager, state) = start_with(start, "day.json", StatusCode::OK, day_config()).await;
+    let manager = manager.with_clock(Arc::new(ManualClock::new(start)));
+
+    manager.auto_record_until(Some(end)).await.unwrap();
+
+    let history = state.history_since(0).unwrap();
+    assert!(!history.is_empty());
+    assert!(history
+        .iter()
+        .all(|h| (start.timestamp()..=end.timestamp()).contains(&h.at)));
+}
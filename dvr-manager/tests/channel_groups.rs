@@ -0,0 +1,44 @@
+//! Named channel groups referred to as `@name` in the channel list
+
+use dvr_manager::config::ChannelGroups;
+
+fn groups() -> ChannelGroups {
+    ChannelGroups::new(
+        [
+            ("freeview-hd", vec!["001", "002"]),
+            ("kids", vec!["002", "014"]),
+            ("nested", vec!["001", "@kids"]),
+        ]
+        .into_iter()
+        .map(|(name, channels)| {
+            let channels = channels.into_iter().map(String::from).collect();
+            (name.to_string(), channels)
+        })
+        .collect(),
+    )
+}
+
+fn channels(channels: &[&str]) -> Vec<String> {
+    channels.iter().map(|c| c.to_string()).collect()
+}
+
+#[test]
+fn expands_groups_in_place() {
+    assert_eq!(
+        groups().expand(&channels(&["@freeview-hd", "013", "@kids"])),
+        Ok(channels(&["001", "002", "013", "014"]))
+    );
+    assert_eq!(groups().expand(&channels(&["013"])), Ok(channels(&["013"])));
+}
+
+#[test]
+fn rejects_unknown_and_nested_groups() {
+    assert_eq!(
+        groups().expand(&channels(&["@sport"])),
+        Err("There's no channel group called sport".to_string())
+    );
+    assert_eq!(
+        groups().expand(&channels(&["@nested"])),
+        Err("Channel group @nested refers to @kids, but groups can't contain groups".to_string())
+    );
+}
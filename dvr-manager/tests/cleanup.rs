@@ -0,0 +1,63 @@
+//! Deleting recordings from a fake Plex library once they're over the size limit
+
+mod common;
+
+use common::start_cleanup;
+use dvr_manager::cleanup::CleanupConfig;
+
+const GB: i64 = 1_000_000_000;
+
+fn limit(gb: u64) -> CleanupConfig {
+    CleanupConfig {
+        size_limit: Some(gb),
+        ..CleanupConfig::default()
+    }
+}
+
+fn kept(state: &dvr_manager::state::State) -> Vec<String> {
+    state
+        .library_recordings()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.rating_key)
+        .collect()
+}
+
+#[tokio::test]
+async fn deletes_the_oldest_over_the_limit() {
+    let library = [("101", 3 * GB), ("102", 2 * GB), ("103", 2 * GB)];
+    let (fake, cleanup, state) = start_cleanup(&library, limit(5)).await;
+
+    cleanup.clean().await.unwrap();
+
+    let mut left: Vec<_> = fake.recordings.lock().unwrap().keys().cloned().collect();
+    left.sort();
+    assert_eq!(left, ["102", "103"]);
+    assert_eq!(kept(&state), ["102", "103"]);
+    let stats = state.cleanup_since(0).unwrap();
+    assert_eq!((stats.deleted, stats.bytes_freed), (1, 3 * GB));
+}
+
+#[tokio::test]
+async fn keeps_everything_within_the_limit() {
+    let library = [("101", 3 * GB), ("102", 2 * GB)];
+    let (fake, cleanup, state) = start_cleanup(&library, limit(5)).await;
+
+    cleanup.clean().await.unwrap();
+
+    assert_eq!(fake.recordings.lock().unwrap().len(), 2);
+    assert_eq!(kept(&state), ["101", "102"]);
+}
+
+#[tokio::test]
+async fn forgets_recordings_deleted_elsewhere() {
+    let library = [("101", 3 * GB), ("102", 4 * GB)];
+    let (fake, cleanup, state) = start_cleanup(&library, limit(5)).await;
+    fake.recordings.lock().unwrap().remove("101");
+
+    cleanup.clean().await.unwrap();
+
+    // Only what's still there counts towards the limit
+    assert_eq!(fake.recordings.lock().unwrap().len(), 1);
+    assert_eq!(kept(&state), ["102"]);
+}
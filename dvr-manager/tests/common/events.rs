@@ -0,0 +1,69 @@
+//! One of each event notifiers are sent, with the awkward characters they
+//! have to escape
+
+use dvr_manager::notify::Event;
+
+pub fn events() -> Vec<(&'static str, Event)> {
+    vec![
+        (
+            "scheduled",
+            Event::Scheduled {
+                title: "Fair Go".into(),
+                channel: "TVNZ 1".into(),
+                begins_at: 1_791_795_600,
+                thumb: Some("https://images.plex.tv/photo?url=fair-go.jpg".into()),
+            },
+        ),
+        (
+            "recorded",
+            Event::Recorded {
+                title: "Fair Go".into(),
+                rating_key: "4512".into(),
+            },
+        ),
+        (
+            "failed",
+            Event::Failed {
+                title: Some("Country Calendar".into()),
+                channel: Some("TVNZ 1".into()),
+                error: "Plex error: \"Subscription\" refused".into(),
+            },
+        ),
+        (
+            "failed_pass",
+            Event::failed("Plex error: error sending request for url"),
+        ),
+        (
+            "deleted",
+            Event::Deleted {
+                title: "Law & Order: <Special Victims Unit>".into(),
+                reason: "older than 30 days".into(),
+            },
+        ),
+        (
+            "dropped",
+            Event::Dropped {
+                title: "Shortland Street".into(),
+                channel: "TVNZ 2".into(),
+                begins_at: 1_791_795_600,
+                reason: "too many recordings at once".into(),
+                alongside: vec!["Fair Go".into(), "Country Calendar".into()],
+            },
+        ),
+        (
+            "report",
+            Event::Report {
+                title: "Weekly DVR report: 3.5 hours recorded, 2 problems".into(),
+                body: "# Weekly DVR report\n\n## Recorded\n\n- TVNZ 1: 3.5 hours\n".into(),
+            },
+        ),
+        (
+            "plan_changed",
+            Event::PlanChanged {
+                added: vec!["Whale Rider (TVNZ 2)".into()],
+                removed: vec!["The Chase (TVNZ 1)".into()],
+                shifted: vec!["Shortland Street (TVNZ 2)".into()],
+            },
+        ),
+    ]
+}
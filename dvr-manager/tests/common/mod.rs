@@ -0,0 +1,569 @@
+//! A fake Plex server serving the fixtures, and events to notify of, shared
+//! by the integration tests
+
+#![allow(dead_code)]
+
+pub mod events;
+
+use axum::body::Body;
+use axum::extract::{Request, State as Fake};
+use axum::http::{header, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use dvr_manager::backend::{PlexBackend, UnknownTypePolicy};
+use dvr_manager::cleanup::{Cleanup, CleanupConfig};
+use dvr_manager::manager::{Manager, ManagerConfig};
+use dvr_manager::notify::Notifiers;
+use dvr_manager::plex::{self, Plex, PlexHost};
+use dvr_manager::state::State;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+
+pub const TOKEN: &str = "test-token";
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/plex");
+/// Wait before the manager retries a failed request, short to keep tests quick
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+/// How long a slow response is held back for
+const SLOW_RESPONSE: Duration = Duration::from_millis(50);
+/// How long a hung request is held for
+const HANG: Duration = Duration::from_secs(60 * 60);
+const HOUR: i64 = 60 * 60;
+
+fn fixture(name: &str) -> Value {
+    let path = Path::new(FIXTURES).join(name);
+    let json = std::fs::read_to_string(&path).expect("fixture exists");
+    serde_json::from_str(&json).expect("fixture is JSON")
+}
+
+/// A request the fake received, with its query decoded
+pub struct Received {
+    pub method: Method,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub status: StatusCode,
+}
+
+/// Ways the fake can misbehave
+#[derive(Clone, Copy, Debug)]
+pub enum Fault {
+    /// 429, asking for the request to be retried straight away
+    Throttled,
+    /// 503, as while Plex is restarting
+    Unavailable,
+    /// Half the body, with the whole body's length, as when a connection drops
+    Truncated,
+    /// The response, after a delay
+    Slow,
+    /// Nothing, for far longer than any pass should take
+    Hang,
+}
+
+pub const ALL_FAULTS: [Fault; 4] = [
+    Fault::Throttled,
+    Fault::Unavailable,
+    Fault::Truncated,
+    Fault::Slow,
+];
+
+/// Which requests to misbehave on, picked by a seeded generator so a test
+/// misbehaves the same way every run
+struct Faults {
+    percent: u32,
+    kinds: Vec<Fault>,
+    rng: StdRng,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Faults {
+            percent: 0,
+            kinds: Vec::new(),
+            rng: StdRng::seed_from_u64(441),
+        }
+    }
+}
+
+/// Serves the fixtures, with the grid's times taken as seconds from `now`
+pub struct FakePlex {
+    pub now: i64,
+    /// Grid fixture to serve airings from
+    pub grid: &'static str,
+    /// Status returned when subscribing, to simulate Plex refusing
+    pub subscribe_status: Mutex<StatusCode>,
+    /// Providers fixture to serve, swapped to move libraries around
+    pub providers: Mutex<&'static str>,
+    /// Subscription template fixture to serve
+    pub template: Mutex<&'static str>,
+    /// Seconds the server's clock is ahead of this machine's
+    pub clock_ahead: Mutex<i64>,
+    pub received: Mutex<Vec<Received>>,
+    faults: Mutex<Faults>,
+    /// Requests that have been misbehaved on
+    pub injected: AtomicUsize,
+    /// Sizes of the recordings in the library, by rating key
+    pub recordings: Mutex<HashMap<String, i64>>,
+    /// The DVRs and their channel mappings
+    pub dvrs: Mutex<Value>,
+    /// Whether subscribing hangs, after the pass has decided what to record
+    pub hang_subscriptions: AtomicBool,
+    /// Whether grid requests for several channels fail, as a blip would
+    pub fail_batches: AtomicBool,
+    /// Whether grid requests for several channels are served only the first,
+    /// as by providers that only support one key at a time
+    pub single_key_grids: AtomicBool,
+}
+
+impl FakePlex {
+    /// Misbehaves in one of `kinds` of ways on `percent` of later requests
+    pub fn inject(&self, percent: u32, kinds: &[Fault]) {
+        let mut faults = self.faults.lock().unwrap();
+        faults.percent = percent;
+        faults.kinds = kinds.to_vec();
+    }
+
+    fn fault(&self) -> Option<Fault> {
+        let mut faults = self.faults.lock().unwrap();
+        if faults.kinds.is_empty() || faults.rng.gen_range(0..100) >= faults.percent {
+            return None;
+        }
+        let kinds = faults.kinds.len();
+        let pick = faults.rng.gen_range(0..kinds);
+        let kind = faults.kinds[pick];
+        self.injected.fetch_add(1, Ordering::Relaxed);
+        Some(kind)
+    }
+
+    /// Subscriptions created, as the channel and seconds from `now` they start at
+    pub fn subscribed(&self) -> Vec<(String, i64)> {
+        let mut subscribed: Vec<_> = self
+            .subscriptions()
+            .iter()
+            .map(|s| {
+                let begins_at: i64 = s["prefs[startTimeslot]"].parse().unwrap();
+                (s["prefs[lineupChannel]"].clone(), begins_at - self.now)
+            })
+            .collect();
+        subscribed.sort_by_key(|(channel, offset)| (*offset, channel.clone()));
+        subscribed
+    }
+
+    pub fn subscriptions(&self) -> Vec<HashMap<String, String>> {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == Method::POST && r.path == "/media/subscriptions")
+            .map(|r| r.query.clone())
+            .collect()
+    }
+
+    /// Grid requests served for several channels at once
+    pub fn batched_grids(&self) -> usize {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.path == format!("/{}", plex::GRID_RESOURCE) && r.status.is_success())
+            .filter(|r| r.query["channelGridKey"].contains(','))
+            .count()
+    }
+
+    /// Whether an airing has been subscribed to, as Plex then marks it in the grid
+    fn is_subscribed(&self, media: &Value) -> bool {
+        let channel = media["channelIdentifier"].as_str().unwrap();
+        let begins_at = media["beginsAt"].to_string();
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method == Method::POST && r.path == "/media/subscriptions")
+            .filter(|r| r.status.is_success())
+            .any(|r| {
+                r.query["prefs[lineupChannel]"] == channel
+                    && r.query["prefs[startTimeslot]"] == begins_at
+            })
+    }
+
+    /// Plex refuses subscriptions into a library it doesn't have
+    fn subscribe(&self, query: &HashMap<String, String>) -> StatusCode {
+        let providers = fixture(&self.providers.lock().unwrap());
+        let library = &query["targetLibrarySectionID"];
+        let has_library = providers["MediaContainer"]["MediaProvider"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|p| p["Feature"].as_array().unwrap())
+            .filter_map(|f| f["Directory"].as_array())
+            .flatten()
+            .any(|d| d["id"] == **library);
+        if has_library {
+            *self.subscribe_status.lock().unwrap()
+        } else {
+            StatusCode::BAD_REQUEST
+        }
+    }
+
+    /// Whether the DVR has a channel enabled, by lineup identifier
+    pub fn channel_enabled(&self, channel: &str) -> bool {
+        self.dvrs.lock().unwrap()["MediaContainer"]["Dvr"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|d| d["Device"].as_array().unwrap())
+            .flat_map(|d| d["ChannelMapping"].as_array().unwrap())
+            .any(|m| m["lineupIdentifier"] == channel && m["enabled"] == "1")
+    }
+
+    /// Enables just the device's channels listed in `channelsEnabled`
+    fn set_channel_map(&self, device: &str, query: &HashMap<String, String>) -> StatusCode {
+        let enabled: Vec<&str> = query["channelsEnabled"].split(',').collect();
+        let mut dvrs = self.dvrs.lock().unwrap();
+        let Some(device) = dvrs["MediaContainer"]["Dvr"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .flat_map(|d| d["Device"].as_array_mut().unwrap())
+            .find(|d| d["key"] == device)
+        else {
+            return StatusCode::NOT_FOUND;
+        };
+        for mapping in device["ChannelMapping"].as_array_mut().unwrap() {
+            let id = mapping["deviceIdentifier"].as_str().unwrap();
+            mapping["enabled"] = if enabled.contains(&id) { "1" } else { "0" }.into();
+        }
+        StatusCode::OK
+    }
+
+    /// Airings on the requested channels starting on the requested date
+    fn grid(&self, query: &HashMap<String, String>) -> Value {
+        let channels = fixture("channels.json");
+        let mut keys: Vec<&str> = query["channelGridKey"].split(',').collect();
+        if self.single_key_grids.load(Ordering::Relaxed) {
+            keys.truncate(1);
+        }
+        let identifiers: Vec<&Value> = channels["MediaContainer"]["Channel"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|c| keys.contains(&c["id"].as_str().unwrap()))
+            .map(|c| &c["identifier"])
+            .collect();
+
+        let mut grid = fixture(self.grid);
+        let airings = grid["MediaContainer"]["Metadata"].as_array_mut().unwrap();
+        for airing in airings.iter_mut() {
+            let media = &mut airing["Media"][0];
+            for field in ["beginsAt", "endsAt"] {
+                media[field] = self
+                    .now
+                    .saturating_add(media[field].as_i64().unwrap())
+                    .into();
+            }
+            if self.is_subscribed(&airing["Media"][0]) {
+                airing["subscriptionID"] = "1".into();
+            }
+        }
+        airings.retain(|a| {
+            let media = &a["Media"][0];
+            let begins_at = media["beginsAt"].as_i64().unwrap();
+            let ends_at = media["endsAt"].as_i64().unwrap();
+            let date = |at: i64| {
+                Utc.timestamp_opt(at, 0).single().map(|t| {
+                    t.with_timezone(&Local)
+                        .format(plex::GRID_DATE_FORMAT)
+                        .to_string()
+                })
+            };
+            // Airings with garbled times turn up whichever date is asked for,
+            // and those running past midnight in both days' grids
+            let on_date = match (date(begins_at), date(ends_at.max(begins_at + 1) - 1)) {
+                (Some(begins), Some(ends)) => begins == query["date"] || ends == query["date"],
+                _ => true,
+            };
+            identifiers.contains(&&media["channelIdentifier"]) && on_date
+        });
+        grid
+    }
+}
+
+const METADATA_PATH: &str = "library/metadata/";
+const DEVICES_PATH: &str = "media/grabbers/devices/";
+
+/// A recorded episode in the library, taking up `size` bytes
+fn recording(key: &str, size: i64) -> Value {
+    serde_json::json!({ "MediaContainer": { "size": 1, "Metadata": [{
+        "ratingKey": key,
+        "type": "episode",
+        "title": key,
+        "librarySectionID": "2",
+        "Media": [{ "id": 1, "Part": [{ "id": 1, "file": format!("/data/tv/{}.ts", key), "size": size }] }],
+    }]}})
+}
+
+async fn handle(Fake(fake): Fake<Arc<FakePlex>>, request: Request<Body>) -> Response {
+    let url = format!("http://plex{}", request.uri());
+    let url = reqwest::Url::parse(&url).unwrap();
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    if query.get("X-Plex-Token").map(String::as_str) != Some(TOKEN) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let method = request.method().clone();
+    let path = url.path().to_string();
+
+    if method == Method::POST && fake.hang_subscriptions.load(Ordering::Relaxed) {
+        tokio::time::sleep(HANG).await;
+    }
+    let fault = fake.fault();
+    match fault {
+        Some(Fault::Throttled) => {
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "0")]).into_response()
+        }
+        Some(Fault::Unavailable) => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        Some(Fault::Slow) => tokio::time::sleep(SLOW_RESPONSE).await,
+        Some(Fault::Hang) => tokio::time::sleep(HANG).await,
+        Some(Fault::Truncated) | None => (),
+    }
+
+    let response = match (&method, path.trim_start_matches('/')) {
+        (&Method::GET, plex::PROVIDERS_RESOURCE) => {
+            let providers = *fake.providers.lock().unwrap();
+            axum::Json(fixture(providers)).into_response()
+        }
+        (&Method::GET, plex::IDENTITY_RESOURCE) => {
+            let time = Utc::now() + chrono::Duration::seconds(*fake.clock_ahead.lock().unwrap());
+            let date = time.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            let identity = serde_json::json!({ "MediaContainer": { "size": 0 } });
+            ([(header::DATE, date)], axum::Json(identity)).into_response()
+        }
+        (&Method::GET, plex::CHANNELS_RESOURCE) => {
+            axum::Json(fixture("channels.json")).into_response()
+        }
+        (&Method::GET, plex::GRID_RESOURCE)
+            if fake.fail_batches.load(Ordering::Relaxed)
+                && query["channelGridKey"].contains(',') =>
+        {
+            StatusCode::SERVICE_UNAVAILABLE.into_response()
+        }
+        (&Method::GET, plex::GRID_RESOURCE) => axum::Json(fake.grid(&query)).into_response(),
+        (&Method::GET, "media/subscriptions/template") => {
+            axum::Json(fixture(&fake.template.lock().unwrap())).into_response()
+        }
+        (&Method::POST, "media/subscriptions") => fake.subscribe(&query).into_response(),
+        (&Method::GET, plex::DVRS_RESOURCE) => {
+            axum::Json(fake.dvrs.lock().unwrap().clone()).into_response()
+        }
+        (&Method::PUT, path) if path.starts_with(DEVICES_PATH) => {
+            let device = path[DEVICES_PATH.len()..].trim_end_matches("/channelmap");
+            fake.set_channel_map(device, &query).into_response()
+        }
+        (&Method::GET, path) if path.starts_with(METADATA_PATH) => {
+            let key = &path[METADATA_PATH.len()..];
+            match fake.recordings.lock().unwrap().get(key) {
+                Some(size) => axum::Json(recording(key, *size)).into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+        (&Method::DELETE, path) if path.starts_with(METADATA_PATH) => {
+            let key = &path[METADATA_PATH.len()..];
+            match fake.recordings.lock().unwrap().remove(key) {
+                Some(_) => StatusCode::OK.into_response(),
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let response = match fault {
+        Some(Fault::Truncated) if method == Method::GET => truncate(response).await,
+        _ => response,
+    };
+    fake.received.lock().unwrap().push(Received {
+        method,
+        path,
+        query,
+        status: response.status(),
+    });
+    response
+}
+
+async fn truncate(response: Response) -> Response {
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    // Streamed, so its length is taken from the header rather than checked
+    let half = Ok::<_, std::io::Error>(body.slice(..body.len() / 2));
+    let mut response =
+        Response::from_parts(parts, Body::from_stream(futures::stream::iter([half])));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LENGTH, body.len().into());
+    response
+}
+
+/// Starts the fake serving `grid` relative to `now`, and a manager with
+/// `config` connected to it
+pub async fn start_with(
+    now: DateTime<Utc>,
+    grid: &'static str,
+    subscribe_status: StatusCode,
+    config: ManagerConfig,
+) -> (Arc<FakePlex>, Manager, Arc<State>) {
+    launch(
+        now,
+        grid,
+        subscribe_status,
+        config,
+        UnknownTypePolicy::default(),
+    )
+    .await
+}
+
+/// Starts the fake and a manager whose backend routes templates of unknown
+/// types by `policy`
+pub async fn start_routing(policy: UnknownTypePolicy) -> (Arc<FakePlex>, Manager, Arc<State>) {
+    let config = ManagerConfig::default();
+    launch(Utc::now(), "grid.json", StatusCode::OK, config, policy).await
+}
+
+/// Starts the fake, with a library of recordings by rating key and size,
+/// and a cleanup connected to it
+pub async fn start_cleanup(
+    recordings: &[(&str, i64)],
+    config: CleanupConfig,
+) -> (Arc<FakePlex>, Cleanup, Arc<State>) {
+    let fake = fake_plex(Utc::now(), "grid.json", StatusCode::OK);
+    *fake.recordings.lock().unwrap() = recordings
+        .iter()
+        .map(|(key, size)| (key.to_string(), *size))
+        .collect();
+    let backend = serve(fake.clone(), UnknownTypePolicy::default()).await;
+    let state = Arc::new(State::open(":memory:").unwrap());
+    for (key, _) in recordings {
+        state.record_grab(key, None).unwrap();
+        state.record_added(key, None, key).unwrap();
+    }
+    let cleanup = Cleanup::new(
+        config,
+        None,
+        Arc::new(backend),
+        state.clone(),
+        Arc::new(Notifiers::default()),
+        false,
+    )
+    .expect("cleanup is configured");
+    (fake, cleanup, state)
+}
+
+fn fake_plex(
+    now: DateTime<Utc>,
+    grid: &'static str,
+    subscribe_status: StatusCode,
+) -> Arc<FakePlex> {
+    Arc::new(FakePlex {
+        now: now.timestamp(),
+        grid,
+        subscribe_status: Mutex::new(subscribe_status),
+        providers: Mutex::new("providers.json"),
+        template: Mutex::new("template.json"),
+        clock_ahead: Mutex::default(),
+        received: Mutex::default(),
+        faults: Mutex::default(),
+        injected: AtomicUsize::new(0),
+        recordings: Mutex::default(),
+        dvrs: Mutex::new(fixture("dvrs.json")),
+        hang_subscriptions: AtomicBool::new(false),
+        fail_batches: AtomicBool::new(false),
+        single_key_grids: AtomicBool::new(false),
+    })
+}
+
+/// Serves the fake, returning a backend connected to it
+async fn serve(fake: Arc<FakePlex>, policy: UnknownTypePolicy) -> PlexBackend {
+    let app = Router::new().fallback(handle).with_state(fake);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let plex = Plex::with_token(TOKEN.into(), PlexHost::Custom(format!("http://{}", addr)))
+        .with_retry_delay(RETRY_DELAY);
+    PlexBackend::new(plex, None, None, Duration::from_secs(600))
+        .await
+        .expect("backend connects to the fake")
+        .with_unknown_types(policy)
+}
+
+async fn launch(
+    now: DateTime<Utc>,
+    grid: &'static str,
+    subscribe_status: StatusCode,
+    config: ManagerConfig,
+    policy: UnknownTypePolicy,
+) -> (Arc<FakePlex>, Manager, Arc<State>) {
+    let fake = fake_plex(now, grid, subscribe_status);
+    let backend = serve(fake.clone(), policy).await;
+    let state = Arc::new(State::open(":memory:").unwrap());
+    let manager = Manager::new(
+        Arc::new(backend),
+        Arc::new(Notify::new()),
+        state.clone(),
+        Arc::new(Notifiers::default()),
+        config,
+    )
+    .unwrap();
+    (fake, manager, state)
+}
+
+/// Starts the fake, with airings relative to `now`, and a manager connected to it
+pub async fn start_at(
+    now: DateTime<Utc>,
+    subscribe_status: StatusCode,
+) -> (Arc<FakePlex>, Manager, Arc<State>) {
+    start_with(now, "grid.json", subscribe_status, ManagerConfig::default()).await
+}
+
+pub async fn start(subscribe_status: StatusCode) -> (Arc<FakePlex>, Manager, Arc<State>) {
+    start_at(Utc::now(), subscribe_status).await
+}
+
+/// Start of the day `day.json` describes
+pub fn day_start() -> DateTime<Utc> {
+    "2031-06-01T00:00:00Z".parse().unwrap()
+}
+
+/// Records a few shows on the first two channels through `day.json`
+pub fn day_config() -> ManagerConfig {
+    ManagerConfig {
+        channels: vec!["001".into(), "002".into()],
+        titles: vec![
+            "Fair Go".into(),
+            "country calendar".into(),
+            "Shortland Street".into(),
+            "Whale Rider".into(),
+        ],
+        poll_jitter: Some(0),
+        ..ManagerConfig::default()
+    }
+}
+
+/// What `day_config` should subscribe to through the day. Not the Country
+/// Calendar already subscribed to at 9am, nor the one on DUKE, nor the Fair Go
+/// just after midnight.
+pub fn day_subscriptions() -> Vec<(String, i64)> {
+    [
+        ("002", 12 * HOUR + 30 * 60), // Shortland Street
+        ("001", 19 * HOUR),           // Fair Go
+        ("002", 19 * HOUR),           // Shortland Street
+        ("001", 19 * HOUR + 30 * 60), // Country Calendar
+        ("002", 20 * HOUR + 30 * 60), // Whale Rider
+        ("001", 23 * HOUR + 45 * 60), // Fair Go
+    ]
+    .map(|(channel, offset)| (channel.to_string(), offset))
+    .to_vec()
+}
@@ -0,0 +1,62 @@
+//! The config is logged at debug level, so secrets must only show whether
+//! they're set
+
+use dvr_manager::config::Config;
+use dvr_manager::notify::NotifyConfig;
+use dvr_manager::radarr::RadarrConfig;
+use dvr_manager::sonarr::SonarrConfig;
+use dvr_manager::tautulli::TautulliConfig;
+use dvr_manager::tmdb::TmdbConfig;
+use dvr_manager::trakt::TraktConfig;
+
+const SECRET: &str = "hunter2";
+
+fn secret() -> Option<String> {
+    Some(SECRET.into())
+}
+
+#[test]
+fn leaves_secrets_out() {
+    let config = Config {
+        plex_token: secret(),
+        webhook_token: secret(),
+        api_token: secret(),
+        sentry_dsn: secret(),
+        notify: NotifyConfig {
+            discord_webhook_url: secret(),
+            slack_webhook_url: secret(),
+            ntfy_token: secret(),
+            pushover_token: secret(),
+            pushover_user: secret(),
+            gotify_token: secret(),
+            apprise_urls: secret(),
+            smtp_password: secret(),
+            ..NotifyConfig::default()
+        },
+        sonarr: SonarrConfig {
+            sonarr_api_key: secret(),
+            ..SonarrConfig::default()
+        },
+        radarr: RadarrConfig {
+            radarr_api_key: secret(),
+            ..RadarrConfig::default()
+        },
+        trakt: TraktConfig {
+            trakt_client_secret: secret(),
+            ..TraktConfig::default()
+        },
+        tmdb: TmdbConfig {
+            tmdb_api_key: secret(),
+            ..TmdbConfig::default()
+        },
+        tautulli: TautulliConfig {
+            tautulli_api_key: secret(),
+            ..TautulliConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let debug = format!("{:#?}", config);
+    assert!(!debug.contains(SECRET), "{}", debug);
+    assert!(debug.contains("plex_token: Some(\n        \"REDACTED\""));
+}
@@ -0,0 +1,29 @@
+//! Snapshot of the digest email, so a change to how it reads is reviewed
+
+mod common;
+
+use common::events::events;
+use dvr_manager::digest::{Digest, DigestPeriod};
+use dvr_manager::state::State;
+
+#[test]
+fn digest() {
+    let state = State::open(":memory:").unwrap();
+    for (_, event) in events() {
+        state.record_event(&event).unwrap();
+    }
+    state.record_cleanup(3, 4_500_000_000).unwrap();
+
+    let digest = Digest::build(&state, DigestPeriod::Daily).unwrap();
+
+    let mut settings = insta::Settings::clone_current();
+    // Local times depend on where, and when, the tests run
+    settings.add_filter(r"\b(Mon|Tue|Wed|Thu|Fri|Sat|Sun) \d\d:\d\d\b", "[time]");
+    settings.bind(|| {
+        insta::assert_snapshot!(format!(
+            "Subject: {}\n\n{}",
+            digest.subject(),
+            digest.body()
+        ))
+    });
+}
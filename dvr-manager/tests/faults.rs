@@ -0,0 +1,145 @@
+//! Scheduling against a fake Plex server that throttles, fails, cuts off and
+//! stalls responses, which the manager must ride out without missing airings
+
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use common::{day_config, day_start, day_subscriptions, start_with, Fault, ALL_FAULTS};
+use dvr_manager::clock::{Clock, ManualClock};
+use dvr_manager::manager::ManagerConfig;
+use dvr_manager::manager::ManagerError;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+fn config() -> ManagerConfig {
+    ManagerConfig {
+        // Retry refused subscriptions well before their airings end
+        retry_backoff: Some(5),
+        ..day_config()
+    }
+}
+
+#[tokio::test]
+async fn rides_out_a_flaky_day() {
+    let start = day_start();
+    let (fake, manager, _state) = start_with(start, "day.json", StatusCode::OK, config()).await;
+    let manager = manager.with_clock(Arc::new(ManualClock::new(start)));
+    fake.inject(30, &ALL_FAULTS);
+
+    manager
+        .auto_record_until(Some(start + Duration::days(1)))
+        .await
+        .expect("manager keeps running");
+
+    assert!(fake.injected.load(Ordering::Relaxed) > 0);
+    assert_eq!(fake.subscribed(), day_subscriptions());
+}
+
+#[tokio::test]
+async fn backs_off_through_an_outage() {
+    let start = day_start();
+    let (fake, manager, _state) = start_with(start, "day.json", StatusCode::OK, config()).await;
+    let manager = manager.with_clock(Arc::new(ManualClock::new(start)));
+
+    fake.inject(100, &[Fault::Unavailable]);
+    manager
+        .auto_record_until(Some(start + Duration::hours(1)))
+        .await
+        .expect("manager keeps running");
+    // Three tries at a request each pass, with passes slowing to once a minute
+    let requests = fake.injected.load(Ordering::Relaxed);
+    assert!(requests < 3 * 70, "{} requests in an hour", requests);
+
+    fake.inject(0, &[]);
+    manager
+        .auto_record_until(Some(start + Duration::days(1)))
+        .await
+        .unwrap();
+    assert_eq!(fake.subscribed(), day_subscriptions());
+}
+
+#[tokio::test]
+async fn abandons_a_hung_pass() {
+    let start = day_start();
+    let config = ManagerConfig {
+        pass_timeout: Some(1),
+        guide_fetch_spread: Some(0),
+        ..config()
+    };
+    let (fake, manager, state) = start_with(start, "day.json", StatusCode::OK, config).await;
+    let manager = manager.with_clock(Arc::new(ManualClock::new(start)));
+
+    fake.inject(100, &[Fault::Hang]);
+    let hung = tokio::time::Instant::now();
+    manager
+        .auto_record_until(Some(start + Duration::seconds(1)))
+        .await
+        .expect("manager keeps running");
+    assert!(hung.elapsed() < std::time::Duration::from_secs(10));
+    let errors = state.status().unwrap().errors;
+    assert_eq!(errors[0].error, ManagerError::PassTimeout(1).to_string());
+
+    fake.inject(0, &[]);
+    manager
+        .auto_record_until(Some(start + Duration::days(1)))
+        .await
+        .unwrap();
+    assert_eq!(fake.subscribed(), day_subscriptions());
+}
+
+#[tokio::test]
+async fn forgets_what_a_hung_pass_decided() {
+    let start = Utc::now();
+    let config = ManagerConfig {
+        titles: vec!["Fair Go".into()],
+        pass_timeout: Some(1),
+        guide_fetch_spread: Some(0),
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, state) =
+        start_with(start, "grid-premieres.json", StatusCode::OK, config).await;
+    let clock = Arc::new(ManualClock::new(start));
+    let manager = manager.with_clock(clock.clone());
+
+    // The other airings are skipped before subscribing to Fair Go hangs
+    fake.hang_subscriptions.store(true, Ordering::Relaxed);
+    manager
+        .auto_record_until(Some(start + Duration::seconds(1)))
+        .await
+        .expect("manager keeps running");
+    assert!(state.decisions_since(0).unwrap().is_empty());
+
+    fake.hang_subscriptions.store(false, Ordering::Relaxed);
+    let resumed = clock.now().timestamp();
+    assert!(resumed > start.timestamp());
+    manager.schedule_next_recordings().await.unwrap();
+    let decisions = state.decisions_since(0).unwrap();
+    assert_eq!(decisions.len(), 3);
+    assert!(decisions.iter().all(|d| d.at >= resumed));
+}
+
+#[tokio::test]
+async fn retries_a_subscription_plex_was_unavailable_for() {
+    let start = Utc::now();
+    let config = ManagerConfig {
+        retry_backoff: Some(5),
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, state) =
+        start_with(start, "grid.json", StatusCode::SERVICE_UNAVAILABLE, config).await;
+    let clock = Arc::new(ManualClock::new(start));
+    let manager = manager.with_clock(clock.clone());
+
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(state.pending_failures().unwrap().len(), 1);
+
+    *fake.subscribe_status.lock().unwrap() = StatusCode::OK;
+    clock.advance(std::time::Duration::from_secs(10));
+    manager.schedule_next_recordings().await.unwrap();
+
+    assert!(state.pending_failures().unwrap().is_empty());
+    let subscribed = fake.subscriptions();
+    assert_eq!(subscribed.len(), 2);
+    assert_eq!(subscribed[0], subscribed[1]);
+}
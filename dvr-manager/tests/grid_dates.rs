@@ -0,0 +1,195 @@
+//! Guide dates in the server's timezone, over the days daylight saving
+//! starts and ends in New Zealand and the United States
+//!
+//! Zones are given to chrono through `TZ` as POSIX rules, so the tests don't
+//! depend on the zoneinfo installed.
+
+use chrono::{DateTime, Duration, Local, Utc};
+use dvr_manager::manager::guide_dates;
+use dvr_manager::plex;
+use std::sync::Mutex;
+
+const NEW_ZEALAND: &str = "NZST-12NZDT,M9.5.0,M4.1.0/3";
+const NEW_YORK: &str = "EST5EDT,M3.2.0,M11.1.0";
+
+/// `TZ` is process-wide, so tests changing it take turns
+static ZONE: Mutex<()> = Mutex::new(());
+
+fn in_zone(zone: &str, test: impl FnOnce()) {
+    let _turn = ZONE.lock().unwrap_or_else(|e| e.into_inner());
+    std::env::set_var("TZ", zone);
+    test();
+}
+
+fn at(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+}
+
+/// Checks the guide dates at each instant, given as UTC with local time noted
+fn assert_dates(cases: &[(&str, [&str; 3])]) {
+    for (time, dates) in cases {
+        assert_eq!(guide_dates(at(time), &Local), *dates, "at {}", time);
+    }
+}
+
+/// Every airing in the coming hours, checked every quarter hour across a
+/// changeover day, is listed on one of the dates a pass fetches
+fn assert_horizon_covered(from: &str) {
+    let from = at(from);
+    for step in 0..4 * 48 {
+        let now = from + Duration::minutes(15 * step);
+        let dates = guide_dates(now, &Local);
+        for ahead in 0..=12 * 4 {
+            let begins_at = now + Duration::minutes(15 * ahead);
+            let day = begins_at
+                .with_timezone(&Local)
+                .format(plex::GRID_DATE_FORMAT)
+                .to_string();
+            assert!(
+                dates.contains(&day),
+                "{} not in {:?} at {}",
+                day,
+                dates,
+                now
+            );
+        }
+    }
+}
+
+#[test]
+fn new_zealand_daylight_saving_starts() {
+    in_zone(NEW_ZEALAND, || {
+        assert_dates(&[
+            // Just before midnight NZST
+            (
+                "2026-09-26T11:59:59Z",
+                ["2026-09-25", "2026-09-26", "2026-09-27"],
+            ),
+            // 23:30 NZST, 24 hours before 00:30 NZDT on the 28th
+            (
+                "2026-09-26T11:30:00Z",
+                ["2026-09-25", "2026-09-26", "2026-09-27"],
+            ),
+            // Midnight, then either side of 02:00 NZST becoming 03:00 NZDT
+            (
+                "2026-09-26T12:00:00Z",
+                ["2026-09-26", "2026-09-27", "2026-09-28"],
+            ),
+            (
+                "2026-09-26T13:59:59Z",
+                ["2026-09-26", "2026-09-27", "2026-09-28"],
+            ),
+            (
+                "2026-09-26T14:00:00Z",
+                ["2026-09-26", "2026-09-27", "2026-09-28"],
+            ),
+            // 23:59:59 then midnight NZDT, an hour earlier in UTC than the day before
+            (
+                "2026-09-27T10:59:59Z",
+                ["2026-09-26", "2026-09-27", "2026-09-28"],
+            ),
+            (
+                "2026-09-27T11:00:00Z",
+                ["2026-09-27", "2026-09-28", "2026-09-29"],
+            ),
+        ]);
+        assert_horizon_covered("2026-09-25T12:00:00Z");
+    });
+}
+
+#[test]
+fn new_zealand_daylight_saving_ends() {
+    in_zone(NEW_ZEALAND, || {
+        assert_dates(&[
+            // Midnight NZDT
+            (
+                "2026-04-04T10:59:59Z",
+                ["2026-04-03", "2026-04-04", "2026-04-05"],
+            ),
+            (
+                "2026-04-04T11:00:00Z",
+                ["2026-04-04", "2026-04-05", "2026-04-06"],
+            ),
+            // Either side of 03:00 NZDT becoming 02:00 NZST
+            (
+                "2026-04-04T13:59:59Z",
+                ["2026-04-04", "2026-04-05", "2026-04-06"],
+            ),
+            (
+                "2026-04-04T14:00:00Z",
+                ["2026-04-04", "2026-04-05", "2026-04-06"],
+            ),
+            // 23:30 NZST, 24 hours after 00:30 NZDT the same day
+            (
+                "2026-04-05T11:30:00Z",
+                ["2026-04-04", "2026-04-05", "2026-04-06"],
+            ),
+            // Midnight NZST
+            (
+                "2026-04-05T12:00:00Z",
+                ["2026-04-05", "2026-04-06", "2026-04-07"],
+            ),
+        ]);
+        assert_horizon_covered("2026-04-03T11:00:00Z");
+    });
+}
+
+#[test]
+fn united_states_daylight_saving_starts() {
+    in_zone(NEW_YORK, || {
+        assert_dates(&[
+            // 20:00 EST on the 7th, already the 8th in UTC
+            (
+                "2026-03-08T01:00:00Z",
+                ["2026-03-06", "2026-03-07", "2026-03-08"],
+            ),
+            // Either side of 02:00 EST becoming 03:00 EDT
+            (
+                "2026-03-08T06:59:59Z",
+                ["2026-03-07", "2026-03-08", "2026-03-09"],
+            ),
+            (
+                "2026-03-08T07:00:00Z",
+                ["2026-03-07", "2026-03-08", "2026-03-09"],
+            ),
+            // 23:59:59 then midnight EDT
+            (
+                "2026-03-09T03:59:59Z",
+                ["2026-03-07", "2026-03-08", "2026-03-09"],
+            ),
+            (
+                "2026-03-09T04:00:00Z",
+                ["2026-03-08", "2026-03-09", "2026-03-10"],
+            ),
+        ]);
+        assert_horizon_covered("2026-03-07T05:00:00Z");
+    });
+}
+
+#[test]
+fn united_states_daylight_saving_ends() {
+    in_zone(NEW_YORK, || {
+        assert_dates(&[
+            // Either side of 02:00 EDT becoming 01:00 EST
+            (
+                "2026-11-01T05:59:59Z",
+                ["2026-10-31", "2026-11-01", "2026-11-02"],
+            ),
+            (
+                "2026-11-01T06:00:00Z",
+                ["2026-10-31", "2026-11-01", "2026-11-02"],
+            ),
+            // 23:30 EST, the 2nd in UTC
+            (
+                "2026-11-02T04:30:00Z",
+                ["2026-10-31", "2026-11-01", "2026-11-02"],
+            ),
+            // Midnight EST
+            (
+                "2026-11-02T05:00:00Z",
+                ["2026-11-01", "2026-11-02", "2026-11-03"],
+            ),
+        ]);
+        assert_horizon_covered("2026-10-31T04:00:00Z");
+    });
+}
@@ -0,0 +1,127 @@
+//! Daily time windows parsed from config, and Plex's nightly maintenance
+//! window waited out by the manager without alerting about Plex being down
+
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, FixedOffset, Local, Utc};
+use common::{day_config, day_start, day_subscriptions, start_with, Fault};
+use dvr_manager::clock::{Clock, ManualClock};
+use dvr_manager::daily_window::DailyWindow;
+use dvr_manager::manager::ManagerConfig;
+use std::sync::Arc;
+
+fn at(time: &str) -> DateTime<Utc> {
+    time.parse().unwrap()
+}
+
+fn window(s: &str) -> DailyWindow {
+    s.parse().unwrap()
+}
+
+#[test]
+fn parses_and_prints() {
+    assert_eq!(window("03:00-03:30").to_string(), "03:00-03:30");
+    assert_eq!(window(" 23:45 - 00:15 ").to_string(), "23:45-00:15");
+    assert_eq!(
+        "3am".parse::<DailyWindow>().unwrap_err(),
+        "Time window must be like 03:00-03:30, not 3am"
+    );
+    assert_eq!(
+        "03:00-25:00".parse::<DailyWindow>().unwrap_err(),
+        "Time window must be like 03:00-03:30, not 03:00-25:00"
+    );
+    assert_eq!(
+        "03:00-03:00".parse::<DailyWindow>().unwrap_err(),
+        "Time window 03:00-03:00 is empty"
+    );
+}
+
+#[test]
+fn ends_the_window_its_in() {
+    let nightly = window("03:00-03:30");
+    assert_eq!(nightly.end_of(at("2031-06-01T02:59:59Z"), &Utc), None);
+    assert_eq!(
+        nightly.end_of(at("2031-06-01T03:00:00Z"), &Utc),
+        Some(at("2031-06-01T03:30:00Z"))
+    );
+    assert_eq!(
+        nightly.end_of(at("2031-06-01T03:29:59Z"), &Utc),
+        Some(at("2031-06-01T03:30:00Z"))
+    );
+    assert_eq!(nightly.end_of(at("2031-06-01T03:30:00Z"), &Utc), None);
+}
+
+#[test]
+fn spans_midnight() {
+    let midnight = window("23:45-00:15");
+    assert_eq!(
+        midnight.end_of(at("2031-06-01T23:50:00Z"), &Utc),
+        Some(at("2031-06-02T00:15:00Z"))
+    );
+    assert_eq!(
+        midnight.end_of(at("2031-06-02T00:05:00Z"), &Utc),
+        Some(at("2031-06-02T00:15:00Z"))
+    );
+    assert_eq!(midnight.end_of(at("2031-06-02T12:00:00Z"), &Utc), None);
+}
+
+#[test]
+fn overlaps_spans_starting_before_it() {
+    let evening = window("19:00-22:00");
+    let overlaps = |from: &str, to: &str| evening.overlaps(at(from), at(to), &Utc);
+    assert!(overlaps("2031-06-01T18:30:00Z", "2031-06-01T19:30:00Z"));
+    assert!(overlaps("2031-06-01T21:30:00Z", "2031-06-01T23:00:00Z"));
+    assert!(!overlaps("2031-06-01T18:00:00Z", "2031-06-01T19:00:00Z"));
+    assert!(!overlaps("2031-06-01T22:00:00Z", "2031-06-02T18:00:00Z"));
+    assert!(overlaps("2031-06-01T23:00:00Z", "2031-06-02T19:01:00Z"));
+}
+
+#[test]
+fn goes_by_local_time() {
+    let nzst = FixedOffset::east_opt(12 * 60 * 60).unwrap();
+    let nightly = window("03:00-03:30");
+    // 03:10 in New Zealand
+    assert_eq!(
+        nightly.end_of(at("2031-05-31T15:10:00Z"), &nzst),
+        Some(at("2031-05-31T15:30:00Z"))
+    );
+    assert_eq!(nightly.end_of(at("2031-06-01T03:10:00Z"), &nzst), None);
+}
+
+#[tokio::test]
+async fn waits_out_maintenance_quietly() {
+    let start = day_start();
+    let local = |t: DateTime<Utc>| t.with_timezone(&Local).format("%H:%M").to_string();
+    let config = ManagerConfig {
+        maintenance_window: Some(window(&format!(
+            "{}-{}",
+            local(start),
+            local(start + Duration::minutes(30))
+        ))),
+        ..day_config()
+    };
+    let (fake, manager, state) = start_with(start, "day.json", StatusCode::OK, config).await;
+    let clock = Arc::new(ManualClock::new(start));
+    let manager = manager.with_clock(clock.clone());
+
+    // Plex restarting throughout the window
+    fake.inject(100, &[Fault::Unavailable]);
+    manager
+        .auto_record_until(Some(start + Duration::minutes(20)))
+        .await
+        .expect("manager keeps running");
+    assert_eq!(
+        clock.now(),
+        start + Duration::minutes(30),
+        "paused for the window"
+    );
+    assert!(state.status().unwrap().errors.is_empty());
+
+    fake.inject(0, &[]);
+    manager
+        .auto_record_until(Some(start + Duration::days(1)))
+        .await
+        .unwrap();
+    assert_eq!(fake.subscribed(), day_subscriptions());
+}
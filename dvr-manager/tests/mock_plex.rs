@@ -0,0 +1,611 @@
+//! Scheduling passes against a fake Plex server serving recorded responses,
+//! covering everything from fetching the guide to creating subscriptions
+
+mod common;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Local, Utc};
+use common::{start, start_at, start_routing, start_with};
+use dvr_manager::backend::UnknownTypePolicy;
+use dvr_manager::clock::{Clock, ManualClock};
+use dvr_manager::manager::{self, ManagerConfig};
+use dvr_manager::notify::Event;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn subscribes_to_the_airing_about_to_start() {
+    let (fake, manager, state) = start(StatusCode::OK).await;
+
+    let next = manager.schedule_next_recordings().await.unwrap();
+
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1, "only Fair Go is due");
+    let subscription = &subscriptions[0];
+    assert_eq!(subscription["prefs[lineupChannel]"], "001");
+    assert_eq!(
+        subscription["prefs[startTimeslot]"],
+        (fake.now + 10).to_string()
+    );
+    assert_eq!(subscription["prefs[oneShot]"], "true");
+    assert_eq!(subscription["targetLibrarySectionID"], "2");
+
+    // Wakes for the film, the next airing on either channel
+    assert_eq!(next.timestamp(), fake.now + 3600);
+
+    let calendar = state.calendar().unwrap();
+    let scheduled: Vec<_> = calendar.iter().filter(|e| e.scheduled).collect();
+    assert_eq!(scheduled.len(), 1);
+    assert_eq!(scheduled[0].title, "Fair Go");
+    assert!(
+        calendar.iter().all(|e| e.title != "Breakfast"),
+        "airings that have started aren't planned"
+    );
+}
+
+#[tokio::test]
+async fn ignores_the_order_of_the_grid() {
+    let now = Utc::now();
+    let (fake, manager, state) = start_with(
+        now,
+        "grid-shuffled.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+
+    let next = manager.schedule_next_recordings().await.unwrap();
+
+    // Breakfast, listed after Fair Go, has still started
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(
+        subscriptions[0]["prefs[startTimeslot]"],
+        (fake.now + 10).to_string()
+    );
+    // Whale Rider, listed after Shortland Street, is still the next airing
+    assert_eq!(next.timestamp(), fake.now + 3600);
+    assert!(state
+        .calendar()
+        .unwrap()
+        .iter()
+        .all(|e| e.title != "Breakfast"));
+}
+
+#[tokio::test]
+async fn subscribes_once_to_an_airing_listed_twice() {
+    let now = Utc::now();
+    let (fake, manager, state) = start_with(
+        now,
+        "grid-repeated.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+
+    // Listed twice, as an airing crossing midnight is in both days' guides
+    manager.schedule_next_recordings().await.unwrap();
+
+    assert_eq!(fake.subscriptions().len(), 1);
+    assert!(state.pending_failures().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn goes_by_plexs_clock() {
+    let start = Utc::now();
+    let (fake, manager, _state) = start_at(start, StatusCode::OK).await;
+    // This machine's a minute fast, so Fair Go looks to have started
+    *fake.clock_ahead.lock().unwrap() = -60;
+    let clock = Arc::new(ManualClock::new(start + Duration::seconds(60)));
+    let manager = manager.with_clock(clock.clone());
+
+    let next = manager.schedule_next_recordings().await.unwrap();
+
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["prefs[lineupChannel]"], "001");
+    // Wakes for the film by this machine's clock
+    assert_eq!(next.timestamp(), fake.now + 3600 + 60);
+}
+
+#[tokio::test]
+async fn allows_for_unmeasured_skew() {
+    let start = Utc::now();
+    let config = ManagerConfig {
+        clock_skew_allowance: Some(90),
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, _state) = start_with(start, "grid.json", StatusCode::OK, config).await;
+    let clock = Arc::new(ManualClock::new(start + Duration::seconds(60)));
+    let manager = manager.with_clock(clock.clone());
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    assert_eq!(fake.subscriptions().len(), 1);
+}
+
+#[tokio::test]
+async fn journals_subscriptions_plex_refuses() {
+    let (fake, manager, state) = start(StatusCode::BAD_REQUEST).await;
+
+    manager
+        .schedule_next_recordings()
+        .await
+        .expect("a refused subscription doesn't fail the pass");
+
+    assert_eq!(fake.subscriptions().len(), 1);
+    let failures = state.pending_failures().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].title, "Fair Go");
+    assert_eq!(failures[0].attempts, 1);
+    assert!(failures[0].retry_at.is_some());
+}
+
+#[tokio::test]
+async fn fills_in_settings_the_template_leaves_out() {
+    let (fake, manager, state) = start(StatusCode::OK).await;
+    *fake.template.lock().unwrap() = "template-sparse.json";
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["prefs[comskipEnabled]"], "-1");
+    assert_eq!(subscriptions[0]["prefs[comskipMethod]"], "2");
+    assert_eq!(subscriptions[0]["prefs[remoteMedia]"], "false");
+    assert!(state.pending_failures().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn rejects_templates_for_other_types() {
+    let (fake, manager, state) = start(StatusCode::OK).await;
+    *fake.template.lock().unwrap() = "template-episode.json";
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    assert!(fake.subscriptions().is_empty());
+    // Given up on straight away, it won't be any different next time
+    assert!(state.pending_failures().unwrap().is_empty());
+    let failures = state.failures(0).unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].title, "Fair Go");
+}
+
+#[tokio::test]
+async fn routes_other_types_by_policy() {
+    for (policy, library) in [(UnknownTypePolicy::Film, "1"), (UnknownTypePolicy::Tv, "2")] {
+        let (fake, manager, _state) = start_routing(policy).await;
+        *fake.template.lock().unwrap() = "template-episode.json";
+
+        manager.schedule_next_recordings().await.unwrap();
+
+        let subscriptions = fake.subscriptions();
+        assert_eq!(subscriptions.len(), 1, "{:?}", policy);
+        assert_eq!(subscriptions[0]["targetLibrarySectionID"], library);
+        assert_eq!(subscriptions[0]["type"], "4");
+    }
+}
+
+#[tokio::test]
+async fn follows_a_recreated_library() {
+    let config = ManagerConfig {
+        retry_backoff: Some(0),
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, state) = start_with(Utc::now(), "grid.json", StatusCode::OK, config).await;
+    *fake.providers.lock().unwrap() = "providers-recreated.json";
+
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(state.pending_failures().unwrap().len(), 1);
+
+    // Refused, so the retry looks the library up again
+    manager.schedule_next_recordings().await.unwrap();
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 2);
+    assert_eq!(subscriptions[0]["targetLibrarySectionID"], "2");
+    assert_eq!(subscriptions[1]["targetLibrarySectionID"], "3");
+    assert!(state.pending_failures().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn runs_passes_at_a_virtual_time() {
+    let start = DateTime::from_timestamp(1_932_346_800, 0).unwrap();
+    let (fake, manager, _state) = start_at(start, StatusCode::OK).await;
+    let clock = Arc::new(ManualClock::new(start));
+    let manager = manager.with_clock(clock.clone());
+
+    let next = manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.subscriptions().len(), 1);
+    assert_eq!(next.timestamp(), fake.now + 3600);
+
+    // Fast-forward to the film
+    clock
+        .sleep(manager::sleep_duration(next, clock.now()))
+        .await;
+    manager.schedule_next_recordings().await.unwrap();
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 2);
+    assert_eq!(subscriptions[1]["prefs[lineupChannel]"], "002");
+    assert_eq!(
+        subscriptions[1]["prefs[startTimeslot]"],
+        (fake.now + 3600).to_string()
+    );
+}
+
+#[tokio::test]
+async fn skips_airings_with_garbled_times() {
+    let now = Utc::now();
+    let (fake, manager, state) = start_with(
+        now,
+        "garbage.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+
+    let next = manager
+        .schedule_next_recordings()
+        .await
+        .expect("garbled airings don't fail the pass");
+
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["prefs[lineupChannel]"], "001");
+    // Nothing else can be waited for, so the next pass is an idle one
+    assert!(next >= now + Duration::hours(1));
+    assert!(state
+        .calendar()
+        .unwrap()
+        .iter()
+        .all(|e| !e.title.starts_with("Garbled")));
+}
+
+#[tokio::test]
+async fn schedules_around_sparse_airings() {
+    let now = Utc::now();
+    let (fake, manager, _state) =
+        start_with(now, "sparse.json", StatusCode::OK, ManagerConfig::default()).await;
+
+    let next = manager
+        .schedule_next_recordings()
+        .await
+        .expect("missing fields don't fail the pass");
+
+    // Fair Go has no duration, and the news no guid to subscribe by
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["prefs[lineupChannel]"], "001");
+    assert_eq!(next.timestamp(), fake.now + 3600);
+}
+
+#[tokio::test]
+async fn schedules_around_a_broken_guide() {
+    let now = Utc::now();
+    let (fake, manager, _state) =
+        start_with(now, "broken.json", StatusCode::OK, ManagerConfig::default()).await;
+
+    // TVNZ 2's guide has an airing without a title, so can't be read
+    let next = manager
+        .schedule_next_recordings()
+        .await
+        .expect("one channel's guide doesn't fail the pass");
+
+    let subscriptions = fake.subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0]["prefs[lineupChannel]"], "001");
+    // Tries TVNZ 2 again soon rather than waiting for the next airing
+    assert!(next < now + Duration::minutes(1));
+}
+
+#[tokio::test]
+async fn stops_when_no_channel_is_in_the_lineup() {
+    let now = Utc::now();
+    let config = ManagerConfig {
+        channels: vec!["5".into(), "TVNZ 1".into()],
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, state) = start_with(now, "grid.json", StatusCode::OK, config).await;
+
+    let error = manager
+        .auto_record_until(Some(now + Duration::hours(1)))
+        .await
+        .expect_err("nothing could ever be recorded");
+
+    assert_eq!(
+        error.to_string(),
+        "None of the channels configured (5, TVNZ 1) are in the DVR's lineup: \
+         001 (TVNZ 1), 002 (TVNZ 2), 003 (DUKE)"
+    );
+    assert!(fake.subscriptions().is_empty());
+    let errors = state.status().unwrap().errors;
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].error, error.to_string());
+}
+
+#[tokio::test]
+async fn samples_series_premieres() {
+    let config = ManagerConfig {
+        titles: vec!["Fair Go".into()],
+        premieres_only: true,
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, _state) =
+        start_with(Utc::now(), "grid-premieres.json", StatusCode::OK, config).await;
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    // Kōtuku's S01E01 and the Fair Go asked for, not Under the Vines' S02E01
+    let channels: Vec<_> = fake
+        .subscribed()
+        .into_iter()
+        .map(|(channel, _)| channel)
+        .collect();
+    assert_eq!(channels, ["001", "002"]);
+}
+
+#[tokio::test]
+async fn grants_wishes_on_any_channel() {
+    let config = ManagerConfig {
+        channels: vec!["001".into()],
+        wishlist: vec!["Rebecca Gibney".into()],
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, _state) =
+        start_with(Utc::now(), "grid-premieres.json", StatusCode::OK, config).await;
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    // Under the Vines is on DUKE, which isn't configured, but stars her
+    let channels: Vec<_> = fake
+        .subscribed()
+        .into_iter()
+        .map(|(channel, _)| channel)
+        .collect();
+    assert_eq!(channels, ["001", "003"]);
+}
+
+#[tokio::test]
+async fn batches_grid_requests_again_after_one_fails() {
+    let (fake, manager, _state) = start_with(
+        Utc::now(),
+        "grid-premieres.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+
+    // The channels are fetched one at a time instead, so nothing's missed
+    fake.fail_batches.store(true, Ordering::Relaxed);
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.subscriptions().len(), 3);
+    assert_eq!(fake.batched_grids(), 0);
+
+    fake.fail_batches.store(false, Ordering::Relaxed);
+    manager.schedule_next_recordings().await.unwrap();
+    assert!(fake.batched_grids() > 0);
+}
+
+#[tokio::test]
+async fn keeps_batching_grid_requests_with_one_channel_on_air() {
+    let (fake, manager, _state) = start_with(
+        Utc::now(),
+        "grid-midnight.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+
+    // The other channels have nothing, which isn't a provider ignoring keys
+    manager.schedule_next_recordings().await.unwrap();
+    let batched = fake.batched_grids();
+    assert!(batched > 0);
+
+    manager.schedule_next_recordings().await.unwrap();
+    assert!(fake.batched_grids() > batched);
+}
+
+#[tokio::test]
+async fn stops_batching_grid_requests_providers_cannot_attribute() {
+    let (fake, manager, _state) = start_with(
+        Utc::now(),
+        "grid-premieres.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+
+    fake.single_key_grids.store(true, Ordering::Relaxed);
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.subscriptions().len(), 3);
+    let batched = fake.batched_grids();
+    assert!(batched > 0);
+
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.batched_grids(), batched);
+}
+
+#[tokio::test]
+async fn keeps_to_the_recording_limit() {
+    let config = ManagerConfig {
+        max_concurrent_recordings: Some(2),
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, state) =
+        start_with(Utc::now(), "grid-premieres.json", StatusCode::OK, config).await;
+
+    // Three airings start together, one on each channel
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.subscriptions().len(), 2);
+
+    // The recordings already going count on the next pass too
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.subscriptions().len(), 2);
+
+    // The airing left out is reported once, however many passes it's due in
+    let dropped: Vec<_> = state
+        .history_since(0)
+        .unwrap()
+        .into_iter()
+        .filter(|h| h.kind == "dropped")
+        .collect();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(
+        dropped[0].error.as_deref(),
+        Some("too many recordings at once")
+    );
+}
+
+#[tokio::test]
+async fn reports_a_dropped_airing_once_across_restarts() {
+    let now = Utc::now();
+    let config = ManagerConfig {
+        max_concurrent_recordings: Some(2),
+        ..ManagerConfig::default()
+    };
+    let (_fake, manager, state) =
+        start_with(now, "grid-premieres.json", StatusCode::OK, config).await;
+    // An earlier run reported whichever airing is left out
+    let airings = [
+        ("Kōtuku", "TVNZ 1"),
+        ("Fair Go", "TVNZ 2"),
+        ("Under the Vines", "DUKE"),
+    ];
+    for (title, channel) in airings {
+        state
+            .record_event(&Event::Dropped {
+                title: title.into(),
+                channel: channel.into(),
+                begins_at: now.timestamp() + 10,
+                reason: "too many recordings at once".into(),
+                alongside: Vec::new(),
+            })
+            .unwrap();
+    }
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    let dropped = state
+        .history_since(0)
+        .unwrap()
+        .into_iter()
+        .filter(|h| h.kind == "dropped")
+        .count();
+    assert_eq!(dropped, airings.len());
+}
+
+#[tokio::test]
+async fn keeps_a_log_of_decisions() {
+    let config = ManagerConfig {
+        max_concurrent_recordings: Some(2),
+        ..ManagerConfig::default()
+    };
+    let (_fake, manager, state) =
+        start_with(Utc::now(), "grid-premieres.json", StatusCode::OK, config).await;
+
+    manager.schedule_next_recordings().await.unwrap();
+    manager.schedule_next_recordings().await.unwrap();
+
+    // Each airing's decision is logged once, though both passes made it
+    let decisions = state.decisions_since(0).unwrap();
+    let mut outcomes: Vec<_> = decisions
+        .iter()
+        .map(|d| (d.outcome.as_str(), d.reason.as_deref()))
+        .collect();
+    outcomes.sort();
+    assert_eq!(
+        outcomes,
+        [
+            ("scheduled", None),
+            ("scheduled", None),
+            ("skipped", Some("too many recordings at once")),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn keeps_a_tuner_for_live_tv() {
+    let now = Utc::now();
+    let local = |t: DateTime<Utc>| t.with_timezone(&Local).format("%H:%M").to_string();
+    let evening = format!(
+        "{}-{}",
+        local(now - Duration::hours(1)),
+        local(now + Duration::hours(1))
+    );
+    let config = ManagerConfig {
+        tuners: Some(3),
+        live_tv_windows: vec![evening.parse().unwrap()],
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, _state) =
+        start_with(now, "grid-premieres.json", StatusCode::OK, config).await;
+
+    // Three airings start together, but one tuner's left for watching
+    manager.schedule_next_recordings().await.unwrap();
+    assert_eq!(fake.subscriptions().len(), 2);
+}
+
+#[tokio::test]
+async fn enables_channels_the_dvr_disabled() {
+    let start = Utc::now();
+    let config = ManagerConfig {
+        channels: vec!["001".into(), "002".into(), "009".into()],
+        enable_channels: true,
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, state) = start_with(start, "grid.json", StatusCode::OK, config).await;
+    let clock = Arc::new(ManualClock::new(start));
+    let manager = manager.with_clock(clock.clone());
+
+    manager
+        .auto_record_until(Some(start + Duration::days(2)))
+        .await
+        .unwrap();
+
+    // A rescan turned TVNZ 2 off, and DUKE's left alone as it isn't wanted
+    assert!(fake.channel_enabled("001"));
+    assert!(fake.channel_enabled("002"));
+    assert!(!fake.channel_enabled("003"));
+    // There's no mapping for 009 to enable, which is alerted about just once
+    // however often the mapping's checked
+    let failed: Vec<_> = state
+        .history_since(0)
+        .unwrap()
+        .into_iter()
+        .filter(|h| h.kind == "failed")
+        .map(|h| h.channel)
+        .collect();
+    assert_eq!(failed, [Some("009".to_string())]);
+}
+
+#[tokio::test]
+async fn counts_an_airing_over_midnight_once() {
+    // Half an hour before midnight, so the first airing is in both days' grids
+    let midnight = (Local::now() + Duration::days(1))
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap()
+        .to_utc();
+    let start = midnight - Duration::minutes(30);
+    let (fake, manager, state) = start_with(
+        start,
+        "grid-midnight.json",
+        StatusCode::OK,
+        ManagerConfig::default(),
+    )
+    .await;
+    let manager = manager.with_clock(Arc::new(ManualClock::new(start)));
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    assert_eq!(fake.subscriptions().len(), 1);
+    let channels = state.status().unwrap().channels;
+    let tvnz1 = channels
+        .iter()
+        .find(|c| c.channel_title == "TVNZ 1")
+        .unwrap();
+    assert_eq!(tvnz1.seen, 2);
+    assert_eq!(tvnz1.scheduled, 1);
+}
@@ -0,0 +1,161 @@
+//! Snapshots of every message notifiers send, so a change to how one looks
+//! is reviewed rather than discovered in a chat channel
+
+#![cfg(feature = "notifications")]
+
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::Router;
+use common::events::events;
+use dvr_manager::notify::{Notifiers, NotifyConfig, SmtpSecurity};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// Headers that carry part of a message rather than transport details
+const MESSAGE_HEADERS: &[&str] = &["content-type", "title", "priority", "tags"];
+
+/// Messages received, keyed by where they were sent
+type Inbox = Arc<Mutex<BTreeMap<String, Vec<String>>>>;
+
+async fn receive(State(inbox): State<Inbox>, request: Request<Body>) -> &'static str {
+    let (parts, body) = request.into_parts();
+    let mut message = String::new();
+    for name in MESSAGE_HEADERS {
+        if let Some(value) = parts.headers.get(*name) {
+            message += &format!("{}: {}\n", name, value.to_str().unwrap());
+        }
+    }
+    let body = to_bytes(body, usize::MAX).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    message += "\n";
+    message += &match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(json) => serde_json::to_string_pretty(&json).unwrap(),
+        Err(_) => body,
+    };
+    let path = parts.uri.path().trim_start_matches('/').to_string();
+    inbox.lock().unwrap().entry(path).or_default().push(message);
+    "ok"
+}
+
+/// Accepts mail as an SMTP server would, keeping each message's data
+async fn smtp(listener: TcpListener, inbox: Inbox) {
+    loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        let inbox = inbox.clone();
+        tokio::spawn(async move {
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write.write_all(b"220 localhost\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = match line.get(..4).unwrap_or("").to_uppercase().as_str() {
+                    "DATA" => {
+                        write.write_all(b"354 go ahead\r\n").await.unwrap();
+                        let mut data = Vec::new();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            data.push(line);
+                        }
+                        let mut inbox = inbox.lock().unwrap();
+                        inbox
+                            .entry("email".into())
+                            .or_default()
+                            .push(data.join("\n"));
+                        b"250 queued\r\n"
+                    }
+                    "QUIT" => {
+                        write.write_all(b"221 bye\r\n").await.unwrap();
+                        return;
+                    }
+                    _ => b"250 ok\r\n",
+                };
+                write.write_all(reply).await.unwrap();
+            }
+        });
+    }
+}
+
+/// Sends every event through every notifier that can be pointed at a local server
+async fn send_all(config: impl FnOnce(String, u16) -> NotifyConfig) -> Inbox {
+    let inbox = Inbox::default();
+    let app = Router::new().fallback(receive).with_state(inbox.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let smtp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let smtp_port = smtp_listener.local_addr().unwrap().port();
+    tokio::spawn(smtp(smtp_listener, inbox.clone()));
+
+    let notifiers = Notifiers::new(&config(base, smtp_port));
+    for (_, event) in events() {
+        notifiers.send(event).await;
+    }
+    inbox
+}
+
+/// Snapshots what was sent to `path`
+fn assert_messages(inbox: &Inbox, path: &str, name: &str) {
+    let inbox = inbox.lock().unwrap();
+    let messages = inbox
+        .get(path)
+        .unwrap_or_else(|| panic!("nothing sent to {}", path));
+    let mut settings = insta::Settings::clone_current();
+    // Local times depend on where the tests run
+    settings.add_filter(r"\b(Mon|Tue|Wed|Thu|Fri|Sat|Sun) \d\d:\d\d\b", "[time]");
+    settings.add_filter(r"(?m)^(Date|Message-ID): .*$", "$1: [redacted]");
+    settings.bind(|| insta::assert_snapshot!(name, messages.join("\n\n---\n\n")));
+}
+
+#[tokio::test]
+async fn every_notifier() {
+    let inbox = send_all(|base, smtp_port| NotifyConfig {
+        webhook_url: Some(format!("{}/webhook", base)),
+        discord_webhook_url: Some(format!("{}/discord", base)),
+        slack_webhook_url: Some(format!("{}/slack", base)),
+        ntfy_url: Some(format!("{}/ntfy", base)),
+        ntfy_token: Some("ntfy-token".into()),
+        gotify_url: Some(format!("{}/gotify", base)),
+        gotify_token: Some("gotify-token".into()),
+        apprise_url: Some(format!("{}/apprise", base)),
+        apprise_urls: Some("mailto://dvr@example.com".into()),
+        smtp_host: Some("127.0.0.1".into()),
+        smtp_port: Some(smtp_port),
+        smtp_security: Some(SmtpSecurity::None),
+        email_from: Some("DVR <dvr@example.com>".into()),
+        email_to: Some("someone@example.com".into()),
+        ..NotifyConfig::default()
+    })
+    .await;
+
+    for (path, name) in [
+        ("webhook", "webhook"),
+        ("discord", "discord"),
+        ("slack", "slack"),
+        ("ntfy", "ntfy"),
+        ("gotify/message", "gotify"),
+        ("apprise", "apprise"),
+        ("email", "email"),
+    ] {
+        assert_messages(&inbox, path, name);
+    }
+}
+
+#[tokio::test]
+async fn webhook_template() {
+    let inbox = send_all(|base, _| NotifyConfig {
+        webhook_url: Some(format!("{}/webhook", base)),
+        webhook_template: Some(
+            r#"{"text": "{{message}}", "kind": "{{event}}", "show": "{{title}}"}"#.into(),
+        ),
+        ..NotifyConfig::default()
+    })
+    .await;
+
+    assert_messages(&inbox, "webhook", "webhook_template");
+}
@@ -0,0 +1,56 @@
+//! The line logged after each pass, which people and scripts read from the
+//! container's logs to see the manager's working
+
+use chrono::DateTime;
+use dvr_manager::manager::PassSummary;
+use dvr_manager::state::ChannelStats;
+
+fn stats(channel: &str, seen: i64, scheduled: i64, skipped: i64, failed: i64) -> ChannelStats {
+    ChannelStats {
+        channel: channel.into(),
+        channel_title: channel.into(),
+        seen,
+        scheduled,
+        skipped,
+        failed,
+    }
+}
+
+#[test]
+fn totals_every_channel() {
+    let next_wake = DateTime::from_timestamp(1_938_000_000, 0).unwrap();
+    let summary = PassSummary::new(
+        &[stats("001", 12, 1, 4, 0), stats("002", 9, 0, 2, 1)],
+        1,
+        next_wake,
+    );
+
+    assert_eq!(
+        summary.to_string(),
+        "channels=3 airings=21 scheduled=1 skipped=6 failed=1 next_wake=2031-05-31T13:20:00Z"
+    );
+}
+
+#[test]
+fn reads_back_as_pairs() {
+    let next_wake = DateTime::from_timestamp(1_938_000_000, 0).unwrap();
+    let line = PassSummary::new(&[], 0, next_wake).to_string();
+
+    let pairs: Vec<(&str, &str)> = line
+        .split(' ')
+        .map(|pair| pair.split_once('=').expect("key=value"))
+        .collect();
+    let keys: Vec<_> = pairs.iter().map(|(k, _)| *k).collect();
+    assert_eq!(
+        keys,
+        [
+            "channels",
+            "airings",
+            "scheduled",
+            "skipped",
+            "failed",
+            "next_wake"
+        ]
+    );
+    assert_eq!(DateTime::parse_from_rfc3339(pairs[5].1).unwrap(), next_wake);
+}
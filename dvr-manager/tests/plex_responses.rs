@@ -0,0 +1,160 @@
+//! Every saved Plex response, from every server version in the corpus, must
+//! parse, so a renamed field shows up here rather than as missed recordings
+
+use dvr_manager::plex::{self, ProviderDirectoryType, ProvidersMediaProviders};
+use std::path::{Path, PathBuf};
+
+const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/responses");
+
+type Check = fn(&str) -> Result<(), String>;
+
+fn preferences(xml: &str) -> Result<(), String> {
+    let token = plex::parse_preferences(xml).map_err(|e| e.to_string())?;
+    if token != "REDACTED" {
+        return Err("token isn't redacted".into());
+    }
+    Ok(())
+}
+
+fn providers(json: &str) -> Result<(), String> {
+    let providers = plex::parse_providers(json).map_err(|e| e.to_string())?;
+    for library in [ProviderDirectoryType::Show, ProviderDirectoryType::Movie] {
+        let dirs = providers
+            .get_dirs_of_type(library.clone())
+            .map_err(|e| e.to_string())?;
+        if dirs.iter().all(|d| d.id.is_none()) {
+            return Err(format!("no {:?} library", library));
+        }
+    }
+    Ok(())
+}
+
+fn channels(json: &str) -> Result<(), String> {
+    let channels = plex::parse_channels(json).map_err(|e| e.to_string())?;
+    if channels.iter().all(|c| c.identifier.is_none()) {
+        return Err("no channel has an identifier".into());
+    }
+    Ok(())
+}
+
+fn grid(json: &str) -> Result<(), String> {
+    let airings = plex::parse_grid(json).map_err(|e| e.to_string())?;
+    if airings.iter().any(|a| a.begins_at_ts() <= 0) {
+        return Err("airing without a start time".into());
+    }
+    Ok(())
+}
+
+fn template(json: &str) -> Result<(), String> {
+    let templates = plex::parse_template(json).map_err(|e| e.to_string())?;
+    let template = templates.first().ok_or("no template")?;
+    if !template.parameters.hints.guid.starts_with("plex://") {
+        return Err(format!(
+            "guid not decoded: {}",
+            template.parameters.hints.guid
+        ));
+    }
+    for setting in [
+        "minVideoQuality",
+        "replaceLowerQuality",
+        "recordPartials",
+        "comskipEnabled",
+        "comskipMethod",
+        "remoteMedia",
+    ] {
+        template
+            .setting_default(setting)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn subscriptions(json: &str) -> Result<(), String> {
+    plex::parse_subscriptions(json)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn metadata(json: &str) -> Result<(), String> {
+    let items = plex::parse_metadata(json).map_err(|e| e.to_string())?;
+    let item = items.first().ok_or("no item")?;
+    match (
+        item.size() > 0,
+        item.file(),
+        item.library_section_id.is_some(),
+    ) {
+        (true, Some(_), true) => Ok(()),
+        _ => Err("recording is missing its size, file or library".into()),
+    }
+}
+
+/// Files every version has, and what they must contain
+const FILES: &[(&str, Check)] = &[
+    ("Preferences.xml", preferences),
+    ("providers.json", providers),
+    ("channels.json", channels),
+    ("template.json", template),
+    ("subscriptions.json", subscriptions),
+    ("metadata.json", metadata),
+];
+
+fn versions() -> Vec<PathBuf> {
+    let mut versions: Vec<_> = std::fs::read_dir(CORPUS)
+        .expect("corpus exists")
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.is_dir())
+        .collect();
+    versions.sort();
+    versions
+}
+
+fn check(path: &Path, check: Check, failures: &mut Vec<String>) {
+    let result = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| check(&text));
+    if let Err(e) = result {
+        failures.push(format!("{}: {}", path.display(), e));
+    }
+}
+
+#[test]
+fn corpus_covers_several_versions() {
+    assert!(versions().len() >= 2);
+}
+
+#[test]
+fn every_response_parses() {
+    let mut failures = Vec::new();
+    for version in versions() {
+        for (name, parse) in FILES {
+            check(&version.join(name), *parse, &mut failures);
+        }
+
+        let grids: Vec<_> = std::fs::read_dir(version.join("grid"))
+            .map(|entries| entries.map(|e| e.unwrap().path()).collect())
+            .unwrap_or_default();
+        if grids.is_empty() {
+            failures.push(format!("{}: no grids", version.display()));
+        }
+        for path in grids {
+            check(&path, grid, &mut failures);
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn refuses_preferences_without_a_token() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<Preferences FriendlyName="nas" PlexOnlineToken=""/>"#;
+    assert!(matches!(
+        plex::parse_preferences(xml),
+        Err(plex::PlexError::EmptyToken)
+    ));
+}
+
+#[test]
+fn leaves_text_alone_without_a_token() {
+    let plex = plex::Plex::with_token(String::new(), plex::PlexHost::Localhost);
+    assert_eq!(plex.redact("GET /library"), "GET /library");
+}
@@ -0,0 +1,58 @@
+//! Configured Plex URLs are normalised, so requests go to the resource asked
+//! for, and malformed ones are refused with a reason
+
+use dvr_manager::plex::{PlexError, PlexHost};
+
+fn custom(url: &str) -> PlexHost {
+    PlexHost::Custom(url.into())
+}
+
+#[test]
+fn normalises_urls() {
+    for (configured, host) in [
+        ("http://plex:32400", "http://plex:32400"),
+        ("http://plex:32400/", "http://plex:32400"),
+        ("http://plex:32400//", "http://plex:32400"),
+        ("plex:32400", "http://plex:32400"),
+        ("192.168.1.10:32400/", "http://192.168.1.10:32400"),
+        (" https://plex.example.com ", "https://plex.example.com"),
+        ("HTTPS://Plex.Example.com/", "https://plex.example.com"),
+        ("https://example.com/plex/", "https://example.com/plex"),
+    ] {
+        assert_eq!(
+            PlexHost::parse(configured).unwrap(),
+            custom(host),
+            "{}",
+            configured
+        );
+    }
+}
+
+#[test]
+fn refuses_malformed_urls() {
+    for (configured, reason) in [
+        ("", "it's empty"),
+        (
+            "ftp://plex:32400",
+            "the scheme must be http or https, not ftp",
+        ),
+        ("http://plex:99999", "invalid port number"),
+        ("http://", "empty host"),
+        (
+            "http://plex:32400/?X-Plex-Token=abc",
+            "it can't have a query or fragment",
+        ),
+    ] {
+        match PlexHost::parse(configured) {
+            Err(PlexError::InvalidUrl { url, reason: r }) => {
+                assert_eq!(url, configured);
+                assert_eq!(r, reason, "{}", configured);
+            }
+            other => panic!(
+                "{} parsed as {:?}",
+                configured,
+                other.map_err(|e| e.to_string())
+            ),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! The time arithmetic deciding when passes run and which airings they
+//! subscribe to, checked over a wide range of clocks
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, Utc};
+use dvr_manager::manager::{guide_dates, is_due, sleep_duration, PRE_SCHEDULE_TIME};
+use dvr_manager::plex;
+use proptest::prelude::*;
+use serde_json::json;
+
+/// Any moment from 2000 to 2100, to the millisecond
+fn instant() -> impl Strategy<Value = DateTime<Utc>> {
+    (946_684_800_000i64..4_102_444_800_000)
+        .prop_map(|ms| DateTime::from_timestamp_millis(ms).unwrap())
+}
+
+fn airing_at(begins_at: i64) -> plex::GridMetadata {
+    let grid = json!({ "MediaContainer": { "Metadata": [{
+        "ratingKey": "1",
+        "guid": "plex://episode/1",
+        "title": "Episode 1",
+        "type": "episode",
+        "duration": 0,
+        "Media": [{
+            "id": 1,
+            "beginsAt": begins_at,
+            "endsAt": begins_at,
+            "channelIdentifier": "001",
+            "channelTitle": "TVNZ 1",
+        }],
+    }]}});
+    plex::parse_grid(&grid.to_string()).unwrap().remove(0)
+}
+
+fn date(formatted: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(formatted, plex::GRID_DATE_FORMAT).unwrap()
+}
+
+proptest! {
+    #[test]
+    fn airing_is_due_on_waking(now in instant(), ahead in 0i64..7 * 24 * 60 * 60) {
+        let begins_at = now.timestamp() + PRE_SCHEDULE_TIME + 1 + ahead;
+        let next_time = DateTime::from_timestamp(begins_at, 0).unwrap();
+        prop_assume!(!is_due(begins_at, now.timestamp()));
+
+        let woken = now + Duration::from_std(sleep_duration(next_time, now)).unwrap();
+        prop_assert!(is_due(begins_at, woken.timestamp()));
+        prop_assert!(woken.timestamp() < begins_at, "woke after the airing started");
+    }
+
+    #[test]
+    fn never_sleeps_for_nothing(now in instant(), offset in -1_000_000i64..1_000_000) {
+        let next_time = now + Duration::seconds(offset);
+        prop_assert!(!sleep_duration(next_time, now).is_zero());
+    }
+
+    #[test]
+    fn wake_times_in_the_past_sleep_briefly(now in instant(), behind in 0i64..1_000_000_000) {
+        let next_time = now - Duration::seconds(behind);
+        prop_assert!(sleep_duration(next_time, now) <= std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn any_epoch_is_a_time_or_none(begins_at in any::<i64>()) {
+        let in_range = (DateTime::<Utc>::MIN_UTC.timestamp()..=DateTime::<Utc>::MAX_UTC.timestamp())
+            .contains(&begins_at);
+        match airing_at(begins_at).begins_at() {
+            Some(time) => prop_assert_eq!(time.timestamp(), begins_at),
+            None => prop_assert!(!in_range, "{} is a valid time", begins_at),
+        }
+    }
+
+    #[test]
+    fn guide_dates_are_consecutive_days(now in instant()) {
+        let [yesterday, today, tomorrow] = guide_dates(now, &Utc).map(|d| date(&d));
+        prop_assert_eq!(today, now.date_naive());
+        prop_assert_eq!(today - yesterday, Duration::days(1));
+        prop_assert_eq!(tomorrow - today, Duration::days(1));
+    }
+
+    #[test]
+    fn guide_dates_are_the_zones_days(now in instant(), east in -14 * 60 * 60..=14 * 60 * 60) {
+        let zone = FixedOffset::east_opt(east).unwrap();
+        let [_, today, _] = guide_dates(now, &zone).map(|d| date(&d));
+        prop_assert_eq!(today, now.with_timezone(&zone).date_naive());
+    }
+
+    #[test]
+    fn airings_within_a_day_fall_in_the_guide_dates(
+        now in instant(),
+        offset in -24 * 60 * 60i64..=24 * 60 * 60,
+    ) {
+        let begins_at = now + Duration::seconds(offset);
+        let dates = guide_dates(now, &Utc);
+        let day = begins_at.format(plex::GRID_DATE_FORMAT).to_string();
+        prop_assert!(dates.contains(&day), "{} not in {:?}", day, dates);
+    }
+}
+
+#[test]
+fn due_at_the_threshold() {
+    assert!(is_due(1_000 + PRE_SCHEDULE_TIME, 1_000));
+    assert!(!is_due(1_001 + PRE_SCHEDULE_TIME, 1_000));
+}
+
+#[test]
+fn guide_dates_across_midnight() {
+    let before = DateTime::parse_from_rfc3339("2026-12-31T23:59:59Z").unwrap();
+    let after = DateTime::parse_from_rfc3339("2027-01-01T00:00:00Z").unwrap();
+    assert_eq!(
+        guide_dates(before.to_utc(), &Utc),
+        ["2026-12-30", "2026-12-31", "2027-01-01"]
+    );
+    assert_eq!(
+        guide_dates(after.to_utc(), &Utc),
+        ["2026-12-31", "2027-01-01", "2027-01-02"]
+    );
+}
+
+#[test]
+fn garbled_epochs_have_no_time() {
+    for begins_at in [
+        i64::MIN,
+        -9_000_000_000_000_000_000,
+        9_000_000_000_000_000_000,
+        i64::MAX,
+    ] {
+        assert_eq!(airing_at(begins_at).begins_at(), None, "{}", begins_at);
+    }
+    assert!(airing_at(0).begins_at().is_some());
+}
@@ -0,0 +1,130 @@
+//! Databases from before the schema was versioned are brought up to date on
+//! open, keeping what's in them
+
+use dvr_manager::state::{State, SCHEMA_VERSION};
+use rusqlite::Connection;
+use std::path::Path;
+
+/// The tables as they were created on demand, before versioning
+const UNVERSIONED: &str = "
+    CREATE TABLE passes (
+        id INTEGER PRIMARY KEY,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE TABLE daemon (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE upcoming (
+        channel TEXT PRIMARY KEY,
+        channel_title TEXT NOT NULL,
+        title TEXT NOT NULL,
+        begins_at INTEGER NOT NULL
+    );
+    CREATE TABLE calendar (
+        channel TEXT NOT NULL,
+        channel_title TEXT NOT NULL,
+        title TEXT NOT NULL,
+        begins_at INTEGER NOT NULL,
+        ends_at INTEGER NOT NULL,
+        scheduled INTEGER NOT NULL,
+        PRIMARY KEY (channel, begins_at)
+    );
+    CREATE TABLE channel_stats (
+        channel TEXT PRIMARY KEY,
+        channel_title TEXT NOT NULL,
+        seen INTEGER NOT NULL DEFAULT 0,
+        scheduled INTEGER NOT NULL DEFAULT 0,
+        skipped INTEGER NOT NULL DEFAULT 0,
+        failed INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE history (
+        id INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        title TEXT,
+        channel TEXT,
+        begins_at INTEGER,
+        error TEXT
+    );
+    CREATE TABLE recordings (
+        id INTEGER PRIMARY KEY,
+        title TEXT NOT NULL,
+        show_title TEXT,
+        grabbed_at INTEGER NOT NULL,
+        rating_key TEXT,
+        added_at INTEGER
+    );
+    CREATE TABLE tokens (
+        service TEXT PRIMARY KEY,
+        access_token TEXT NOT NULL,
+        refresh_token TEXT NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+    CREATE TABLE ratings (
+        key TEXT PRIMARY KEY,
+        rating REAL,
+        fetched_at INTEGER NOT NULL
+    );
+    CREATE TABLE cleanups (
+        id INTEGER PRIMARY KEY,
+        ran_at INTEGER NOT NULL,
+        deleted INTEGER NOT NULL,
+        bytes_freed INTEGER NOT NULL
+    );
+    INSERT INTO daemon (key, value) VALUES ('last_digest', 1000);
+    INSERT INTO history (at, kind, title, channel, begins_at)
+        VALUES (2000, 'scheduled', 'Fair Go', 'TVNZ 1', 3000);
+    INSERT INTO cleanups (ran_at, deleted, bytes_freed) VALUES (4000, 2, 5000000);
+";
+
+fn user_version(path: &Path) -> i64 {
+    Connection::open(path)
+        .unwrap()
+        .query_row("PRAGMA user_version", [], |r| r.get(0))
+        .unwrap()
+}
+
+/// What's kept in the database, as read through the state
+fn contents(state: &State) -> (Option<i64>, Vec<String>, i64) {
+    let history = state
+        .history_since(0)
+        .unwrap()
+        .into_iter()
+        .map(|h| format!("{} {}", h.kind, h.title.unwrap_or_default()))
+        .collect();
+    let cleanup = state.cleanup_since(0).unwrap();
+    (state.last_digest().unwrap(), history, cleanup.deleted)
+}
+
+#[test]
+fn migrates_an_unversioned_database() {
+    let path = std::env::temp_dir().join(format!("state-migrations-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    Connection::open(&path)
+        .unwrap()
+        .execute_batch(UNVERSIONED)
+        .unwrap();
+    assert_eq!(user_version(&path), 0);
+
+    let state = State::open(&path).unwrap();
+    assert_eq!(user_version(&path), SCHEMA_VERSION);
+    let migrated = contents(&state);
+    assert_eq!(
+        migrated,
+        (Some(1000), vec!["scheduled Fair Go".to_string()], 2)
+    );
+    // Tables added since are usable
+    assert!(state.pending_failures().unwrap().is_empty());
+    drop(state);
+
+    // Opening it again changes nothing
+    let state = State::open(&path).unwrap();
+    assert_eq!(user_version(&path), SCHEMA_VERSION);
+    assert_eq!(contents(&state), migrated);
+    drop(state);
+
+    std::fs::remove_file(&path).unwrap();
+}
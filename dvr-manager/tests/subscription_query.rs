@@ -0,0 +1,125 @@
+//! The query strings subscriptions are created with, compared byte for byte,
+//! since Plex silently ignores subscriptions it can't make sense of
+
+use dvr_manager::plex::{self, AiringChannels, AiringTimes, Subscription};
+use std::path::{Path, PathBuf};
+
+const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/responses");
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// The query subscribing to the airing a version's template was fetched for
+fn query(version: &Path) -> String {
+    let templates = plex::parse_template(&read(&version.join("template.json"))).unwrap();
+    let template = &templates[0];
+    let grid = version.join("grid/5fc705b2ba4d3c002d06a5d5-1_2026-10-12.json");
+    let airing = plex::parse_grid(&read(&grid))
+        .unwrap()
+        .into_iter()
+        .find(|a| {
+            template
+                .parameters
+                .params
+                .airing_times
+                .contains(a.begins_at_ts())
+        })
+        .expect("template's airing is in the grid");
+    let subscription = Subscription::one_shot(template, &airing.media[0], "2").unwrap();
+    plex::subscription_query(&subscription)
+}
+
+fn versions() -> Vec<PathBuf> {
+    let mut versions: Vec<_> = std::fs::read_dir(CORPUS)
+        .expect("corpus exists")
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.is_dir())
+        .collect();
+    versions.sort();
+    versions
+}
+
+#[test]
+fn queries_are_unchanged() {
+    for version in versions() {
+        let expected = read(&version.join("subscription.query"));
+        let actual = query(&version);
+        // Compared whole, but reported a pair at a time to show where they differ
+        for (expected, actual) in expected.trim_end().split('&').zip(actual.split('&')) {
+            assert_eq!(actual, expected, "in {}", version.display());
+        }
+        assert_eq!(actual, expected.trim_end(), "in {}", version.display());
+    }
+}
+
+/// Plex hands out the hints and params as a query string in the template, so
+/// they're sent back just as it encoded them
+#[test]
+fn hints_and_params_are_sent_as_plex_gave_them() {
+    for version in versions() {
+        let templates: serde_json::Value =
+            serde_json::from_str(&read(&version.join("template.json"))).unwrap();
+        let parameters = templates["MediaContainer"]["SubscriptionTemplate"][0]
+            ["MediaSubscription"][0]["parameters"]
+            .as_str()
+            .unwrap();
+        let parameters = urlencoding::decode(parameters).unwrap();
+
+        let actual = query(&version);
+        let sent: Vec<_> = actual
+            .split('&')
+            .filter(|pair| pair.starts_with("hints[") || pair.starts_with("params["))
+            .collect();
+        assert_eq!(sent.join("&"), parameters, "in {}", version.display());
+    }
+}
+
+#[test]
+fn airings_are_aimed_at_what_is_subscribed() {
+    for version in versions() {
+        let templates = plex::parse_template(&read(&version.join("template.json"))).unwrap();
+        let grid = version.join("grid/5fc705b2ba4d3c002d06a5d5-1_2026-10-12.json");
+        let airing = plex::parse_grid(&read(&grid)).unwrap().pop().unwrap();
+        let media = &airing.media[0];
+
+        let subscription = Subscription::one_shot(&templates[0], media, "2").unwrap();
+        assert_eq!(
+            subscription.params.airing_channels.identifiers(),
+            std::slice::from_ref(&media.channel_identifier)
+        );
+        assert_eq!(subscription.params.airing_times.slots(), [media.begins_at]);
+    }
+}
+
+#[test]
+fn airing_params_round_trip() {
+    let channels = AiringChannels::new(vec!["001".into(), "003".into()]);
+    assert_eq!(channels.encode(), "001,003");
+    assert_eq!(AiringChannels::decode(&channels.encode()), channels);
+
+    let times = AiringTimes::new(vec![1791795600, 1791799200]);
+    assert_eq!(times.encode(), "1791795600,1791799200");
+    assert_eq!(AiringTimes::decode(&times.encode()).unwrap(), times);
+
+    assert_eq!(AiringChannels::decode(""), AiringChannels::default());
+    assert_eq!(AiringTimes::decode("").unwrap(), AiringTimes::default());
+    assert!(AiringTimes::decode("1791795600,soon").is_err());
+}
+
+#[test]
+fn channel_lists_are_encoded_once() {
+    let version = &versions()[0];
+    let templates = plex::parse_template(&read(&version.join("template.json"))).unwrap();
+    let grid = version.join("grid/5fc705b2ba4d3c002d06a5d5-1_2026-10-12.json");
+    let airing = plex::parse_grid(&read(&grid)).unwrap().pop().unwrap();
+    let mut subscription = Subscription::one_shot(&templates[0], &airing.media[0], "2").unwrap();
+    subscription.params.airing_channels = AiringChannels::new(vec!["001".into(), "003".into()]);
+
+    let query = plex::subscription_query(&subscription);
+    assert!(
+        query.contains("&params[airingChannels]=001%2C003&"),
+        "{}",
+        query
+    );
+}
@@ -0,0 +1,46 @@
+//! Shows recognised under the other names guides and services give them
+
+mod common;
+
+use axum::http::StatusCode;
+use chrono::Utc;
+use common::start_with;
+use dvr_manager::manager::ManagerConfig;
+use dvr_manager::title::TitleAliases;
+use std::collections::HashMap;
+
+fn aliases(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(alias, title)| (alias.to_string(), title.to_string()))
+        .collect()
+}
+
+#[test]
+fn maps_aliases_to_their_title() {
+    let aliases = TitleAliases::new(&aliases(&[("NCIS: LA", "NCIS: Los Angeles")]));
+    assert_eq!(aliases.canonical("ncis la"), "NCIS: Los Angeles");
+    assert_eq!(aliases.canonical("NCIS"), "NCIS");
+    assert!(aliases.same("NCIS: LA", "NCIS Los Angeles"));
+    assert!(aliases.same("NCIS - LA", "ncis: los angeles"));
+    assert!(!aliases.same("NCIS", "NCIS: Los Angeles"));
+    assert_eq!(aliases.key("NCIS: LA"), "ncislosangeles");
+}
+
+#[tokio::test]
+async fn allows_titles_by_their_aliases() {
+    let config = ManagerConfig {
+        titles: vec!["Fair Go: Consumer Affairs".into()],
+        title_aliases: aliases(&[("Fair Go", "Fair Go: Consumer Affairs")]),
+        ..ManagerConfig::default()
+    };
+    let (fake, manager, _state) = start_with(Utc::now(), "grid.json", StatusCode::OK, config).await;
+
+    manager.schedule_next_recordings().await.unwrap();
+
+    assert_eq!(
+        fake.subscriptions().len(),
+        1,
+        "the guide's Fair Go is recorded"
+    );
+}
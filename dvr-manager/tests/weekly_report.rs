@@ -0,0 +1,81 @@
+//! Snapshots of the weekly report, so a change to how it reads is reviewed
+
+mod common;
+
+use chrono::Utc;
+use common::events::events;
+use dvr_manager::state::{CalendarEntry, State};
+use dvr_manager::weekly_report::WeeklyReport;
+
+const HOUR: i64 = 60 * 60;
+
+fn entry(channel: &str, title: &str, begins_at: i64, hours: f64, scheduled: bool) -> CalendarEntry {
+    CalendarEntry {
+        channel: channel.into(),
+        channel_title: channel.into(),
+        title: title.into(),
+        begins_at,
+        ends_at: begins_at + (hours * HOUR as f64) as i64,
+        scheduled,
+    }
+}
+
+fn report() -> WeeklyReport {
+    let now = Utc::now();
+    let at = |hours: i64| now.timestamp() + hours * HOUR;
+    let state = State::open(":memory:").unwrap();
+    state
+        .set_calendar(&[
+            entry("TVNZ 1", "Fair Go", at(-50), 0.5, true),
+            entry("TVNZ 1", "Country Calendar", at(-26), 1.0, true),
+            entry("TVNZ 2", "Whale Rider", at(-100), 2.0, true),
+            // Starting now, so not counted yet
+            entry("TVNZ 2", "Shortland Street", at(0), 0.5, true),
+            entry("DUKE", "The Chase", at(3), 1.0, false),
+            entry("TVNZ 1", "Law & Order: <SVU>", at(5), 1.0, false),
+        ])
+        .unwrap();
+    for (_, event) in events() {
+        state.record_event(&event).unwrap();
+    }
+    state.record_cleanup(3, 4_500_000_000).unwrap();
+    WeeklyReport::build(&state, now).unwrap()
+}
+
+fn assert_report(name: &str, report: String) {
+    let mut settings = insta::Settings::clone_current();
+    // Local times depend on where, and when, the tests run
+    settings.add_filter(r"\b(Mon|Tue|Wed|Thu|Fri|Sat|Sun) \d\d:\d\d\b", "[time]");
+    settings.bind(|| insta::assert_snapshot!(name, report));
+}
+
+#[test]
+fn markdown() {
+    assert_report("markdown", report().markdown());
+}
+
+#[test]
+fn html() {
+    assert_report("html", report().html());
+}
+
+#[test]
+fn written_by_extension() {
+    let dir = std::env::temp_dir().join(format!("weekly-report-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let report = report();
+
+    let html = dir.join("weekly-report.html");
+    report.write(&html).unwrap();
+    assert!(std::fs::read_to_string(&html)
+        .unwrap()
+        .starts_with("<!DOCTYPE html>"));
+
+    let markdown = dir.join("weekly-report.md");
+    report.write(&markdown).unwrap();
+    assert!(std::fs::read_to_string(&markdown)
+        .unwrap()
+        .starts_with("# Weekly DVR report"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
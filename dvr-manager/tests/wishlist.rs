@@ -0,0 +1,58 @@
+//! Wishlist phrases matched against what the guide says about an airing
+
+use dvr_manager::plex::{self, GridMetadata};
+use dvr_manager::wishlist::Wishlist;
+use serde_json::json;
+
+fn airing(show: &str, title: &str, summary: Option<&str>) -> GridMetadata {
+    let grid = json!({ "MediaContainer": { "Metadata": [{
+        "ratingKey": "1",
+        "guid": "plex://episode/1",
+        "title": title,
+        "grandparentTitle": show,
+        "summary": summary,
+        "type": "episode",
+        "Media": [{
+            "id": 1,
+            "beginsAt": 0,
+            "endsAt": 1800,
+            "channelIdentifier": "001",
+            "channelTitle": "TVNZ 1",
+        }],
+    }]}});
+    plex::parse_grid(&grid.to_string()).unwrap().remove(0)
+}
+
+fn wishlist(phrases: &[&str]) -> Wishlist {
+    Wishlist::new(&phrases.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+fn matches_titles_and_summaries() {
+    let wishes = wishlist(&["Kiri Te Kanawa", "  haka "]);
+    let concert = airing(
+        "Sunday Night Concert",
+        "Live from Auckland",
+        Some("Dame KIRI te Kanawa's farewell performance."),
+    );
+    assert_eq!(wishes.find(&concert), Some("Kiri Te Kanawa"));
+    let final_ = airing("Rugby World Cup", "Haka and Kick-off", None);
+    assert_eq!(wishes.find(&final_), Some("haka"));
+}
+
+#[test]
+fn matches_whole_words() {
+    let wishes = wishlist(&["art", "te kanawa"]);
+    assert_eq!(wishes.find(&airing("Smart Homes", "Kitchens", None)), None);
+    assert_eq!(
+        wishes.find(&airing("Profiles", "Kanawa, te reo and more", None)),
+        None
+    );
+}
+
+#[test]
+fn ignores_blank_phrases() {
+    let wishes = wishlist(&["", " - "]);
+    assert!(wishes.is_empty());
+    assert_eq!(wishes.find(&airing("Fair Go", "Episode 1", None)), None);
+}